@@ -0,0 +1,327 @@
+use crate::discovery::{definition_key, relative_file_ref};
+use crate::lint::escape_pointer;
+use crate::sources::{parse_source, SourceFormat, SourceMap};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// `$id` URIs of schemas kept outside the schemas directory mapped to local files,
+/// so `$ref`s by URI are resolved without network access
+#[derive(Clone, Debug, Default)]
+pub struct Catalog {
+    entries: BTreeMap<String, PathBuf>,
+}
+
+impl Catalog {
+    /// Catalog of [entries] from URI to file path, relative paths are relative to the current directory,
+    /// [GeneratorConfig::load](crate::config::GeneratorConfig::load) resolves the ones of the config file.
+    /// A URI ending with `/` maps every URI under it to the same relative path under the directory.
+    pub fn new(entries: &BTreeMap<String, String>) -> Result<Catalog, Box<dyn Error>> {
+        let mut catalog = Catalog::default();
+        for (uri, path) in entries {
+            if !is_absolute_uri(uri) || uri.contains('#') {
+                return Err(format!(
+                    "catalog URI `{uri}` must be an absolute URI without a fragment, e.g. `https://schemas.example.com/common/v1.json`"
+                ).into());
+            }
+            catalog.entries.insert(uri.clone(), PathBuf::from(path));
+        }
+        Ok(catalog)
+    }
+
+    /// Local file of [uri], None for URIs the catalog does not map
+    pub fn path(&self, uri: &str) -> Option<PathBuf> {
+        if let Some(path) = self.entries.get(uri) {
+            return Some(path.clone());
+        }
+        self.entries.iter()
+            .filter(|(prefix, _)| prefix.ends_with('/') && uri.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, dir)| dir.join(&uri[prefix.len()..]))
+    }
+}
+
+/// Resolves `$ref`s of [schemas] by file key that go through URIs: absolute URIs, and relative refs of
+/// documents with an absolute `$id`, which is their base URI. A URI is the `$id` of one of the schemas or
+/// is mapped to a file by [catalog], catalog files are loaded into [schemas] and [source_map] once referenced.
+/// Resolved refs are rewritten to relative file refs, so bundling and linting treat them as any other.
+/// Relative refs resolving to unknown URIs stay relative to the file, except in catalog files.
+pub fn resolve_ids(
+    schemas: &mut BTreeMap<String, Value>,
+    source_map: &mut SourceMap,
+    catalog: &Catalog,
+    schemas_path: &Path
+) -> Result<(), Box<dyn Error>> {
+    let mut resolver = Resolver { schemas, source_map, catalog, ids: BTreeMap::new(), bases: BTreeMap::new(), loaded: BTreeMap::new() };
+    let file_keys = resolver.schemas.keys().cloned().collect::<Vec<_>>();
+    for file_key in &file_keys {
+        resolver.register_id(file_key)?;
+        // the same file referenced through the catalog is not loaded twice
+        if let Ok(path) = std::fs::canonicalize(schemas_path.join(resolver.source_map.path(file_key))) {
+            resolver.loaded.insert(path, file_key.clone());
+        }
+    }
+
+    let mut pending = file_keys;
+    while let Some(file_key) = pending.pop() {
+        let mut refs = Vec::new();
+        collect_refs(&resolver.schemas[&file_key], "", &mut refs);
+        for (pointer, reference) in refs {
+            let Some(rewritten) = resolver.resolve(&file_key, &pointer, &reference, &mut pending)? else {
+                continue;
+            };
+            if let Some(value) = resolver.schemas.get_mut(&file_key).and_then(|schema| schema.pointer_mut(&pointer)) {
+                *value = Value::String(rewritten);
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Resolver<'a> {
+    schemas: &'a mut BTreeMap<String, Value>,
+    source_map: &'a mut SourceMap,
+    catalog: &'a Catalog,
+    /// File key by `$id` and catalog URI
+    ids: BTreeMap<String, String>,
+    /// Base URI by file key, for documents that have one
+    bases: BTreeMap<String, String>,
+    /// File key by canonical path of the loaded files
+    loaded: BTreeMap<PathBuf, String>,
+}
+
+impl Resolver<'_> {
+    /// Makes the absolute `$id` of the root of [file_key] its base URI and a target of refs
+    fn register_id(&mut self, file_key: &str) -> Result<(), Box<dyn Error>> {
+        let Some(id) = self.schemas[file_key].get("$id").and_then(Value::as_str) else {
+            return Ok(());
+        };
+        let id = id.split('#').next().unwrap_or_default();
+        if !is_absolute_uri(id) {
+            return Ok(());
+        }
+        if let Some(other) = self.ids.get(id) && other != file_key {
+            return Err(format!(
+                "{}: `$id` `{id}` is also the `$id` of {}",
+                self.source_map.locate(file_key, "/$id"),
+                self.source_map.path(other)
+            ).into());
+        }
+        self.ids.insert(id.to_string(), file_key.to_string());
+        self.bases.insert(file_key.to_string(), id.to_string());
+        Ok(())
+    }
+
+    /// Relative file ref replacing [reference] at [pointer] of [file_key], None if it stays as is
+    fn resolve(
+        &mut self,
+        file_key: &str,
+        pointer: &str,
+        reference: &str,
+        pending: &mut Vec<String>
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let (path, fragment) = match reference.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (reference, None),
+        };
+        if path.is_empty() {
+            return Ok(None);
+        }
+        let uri = if is_absolute_uri(path) {
+            remove_dot_segments_of(path)
+        } else if let Some(base) = self.bases.get(file_key) {
+            resolve_uri(base, path)
+        } else {
+            return Ok(None);
+        };
+
+        let target = match (self.ids.get(&uri), self.catalog.path(&uri)) {
+            (Some(target), _) => target.clone(),
+            (None, Some(catalog_path)) => self.load(&uri, &catalog_path, pending)?,
+            // catalog files have no directory of their own, their relative refs are URIs too
+            (None, None) if is_absolute_uri(path) || self.source_map.catalog_uri(file_key).is_some() => {
+                return Err(format!(
+                    "{}: `$ref` `{reference}` resolves to `{uri}`, which is neither the `$id` of a schema nor in the catalog, \
+                     map it to a local file with `--catalog {uri}=PATH` or in `schemas.catalog`",
+                    self.source_map.locate(file_key, pointer)
+                ).into());
+            }
+            (None, None) => return Ok(None),
+        };
+
+        let mut rewritten = if target == file_key {
+            String::new()
+        } else {
+            relative_file_ref(file_key, &target, self.source_map.suffix())
+        };
+        if let Some(fragment) = fragment {
+            rewritten.push('#');
+            rewritten.push_str(fragment);
+        }
+        if rewritten.is_empty() {
+            rewritten.push('#');
+        }
+        Ok(Some(rewritten))
+    }
+
+    /// Loads catalog file [path] of [uri] as a schema, returns its file key
+    fn load(&mut self, uri: &str, path: &Path, pending: &mut Vec<String>) -> Result<String, Box<dyn Error>> {
+        let canonical_path = std::fs::canonicalize(path)
+            .map_err(|err| format!("catalog file {} of `{uri}` can not be read: {err}", path.display()))?;
+        if let Some(file_key) = self.loaded.get(&canonical_path) {
+            let file_key = file_key.clone();
+            self.ids.insert(uri.to_string(), file_key.clone());
+            return Ok(file_key);
+        }
+
+        let file_key = uri_file_key(uri, self.source_map.suffix())
+            .ok_or_else(|| format!("catalog URI `{uri}` has no path to name its definitions after"))?;
+        if let Some(other) = self.schemas.keys().find(|other| definition_key(other) == definition_key(&file_key)) {
+            return Err(format!(
+                "catalog file {} of `{uri}` and {} are both bundled as definition `{}`",
+                path.display(),
+                self.source_map.path(other),
+                definition_key(&file_key)
+            ).into());
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("catalog file {} of `{uri}` can not be read: {err}", path.display()))?;
+        let display_path = path.display().to_string();
+        let (schema, positions) = parse_source(&content, SourceFormat::of(&display_path))
+            .map_err(|err| format!("{display_path}:{err}"))?;
+
+        self.schemas.insert(file_key.clone(), schema);
+        self.source_map.insert_catalog_file(&file_key, &display_path, uri, positions);
+        self.register_id(&file_key)?;
+        self.bases.entry(file_key.clone()).or_insert_with(|| uri.to_string());
+        self.ids.insert(uri.to_string(), file_key.clone());
+        self.loaded.insert(canonical_path, file_key.clone());
+        pending.push(file_key.clone());
+        Ok(file_key)
+    }
+}
+
+/// Appends the pointer and the value of every `$ref` of [value] at [pointer] to [refs]
+fn collect_refs(value: &Value, pointer: &str, refs: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let pointer = format!("{pointer}/{}", escape_pointer(key));
+                match value {
+                    Value::String(reference) if key == "$ref" => refs.push((pointer, reference.clone())),
+                    _ => collect_refs(value, &pointer, refs),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                collect_refs(value, &format!("{pointer}/{index}"), refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// File key of a catalog file named after the path of its [uri], e.g. `common/v1` for
+/// `https://schemas.example.com/common/v1.json`. Characters not allowed in Rust identifiers become `_`.
+fn uri_file_key(uri: &str, suffix: &str) -> Option<String> {
+    let (_, rest) = uri.split_once(':')?;
+    let path = match rest.strip_prefix("//") {
+        Some(authority_and_path) => authority_and_path.split_once('/').map_or("", |(_, path)| path),
+        None => rest,
+    };
+    let path = path.split('?').next().unwrap_or_default();
+    let segments = SourceFormat::strip_suffix(path, suffix).split(['/', ':'])
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect::<String>())
+        .collect::<Vec<_>>();
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+/// Whether [uri] starts with a scheme, e.g. `https:` or `urn:`
+fn is_absolute_uri(uri: &str) -> bool {
+    let Some((scheme, _)) = uri.split_once(':') else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// [reference] resolved against absolute [base] as RFC 3986 does, both without fragments
+fn resolve_uri(base: &str, reference: &str) -> String {
+    if is_absolute_uri(reference) {
+        return remove_dot_segments_of(reference);
+    }
+    let (scheme, rest) = base.split_once(':').unwrap_or(("", base));
+    let (authority, base_path) = match rest.strip_prefix("//") {
+        Some(authority_and_path) => match authority_and_path.find('/') {
+            Some(index) => (format!("//{}", &authority_and_path[..index]), &authority_and_path[index..]),
+            None => (format!("//{authority_and_path}"), ""),
+        },
+        None => (String::new(), rest),
+    };
+    let base_path = base_path.split('?').next().unwrap_or_default();
+
+    if let Some(network_path) = reference.strip_prefix("//") {
+        return remove_dot_segments_of(&format!("{scheme}://{network_path}"));
+    }
+    let path = if reference.starts_with('/') {
+        reference.to_string()
+    } else if reference.is_empty() {
+        base_path.to_string()
+    } else {
+        // merge with the directory of the base path
+        match base_path.rfind('/') {
+            Some(index) => format!("{}{reference}", &base_path[..=index]),
+            None if !authority.is_empty() => format!("/{reference}"),
+            None => reference.to_string(),
+        }
+    };
+    format!("{scheme}:{authority}{}", remove_dot_segments(&path))
+}
+
+/// Absolute [uri] with `.` and `..` segments of its path removed
+fn remove_dot_segments_of(uri: &str) -> String {
+    let (scheme, rest) = uri.split_once(':').unwrap_or(("", uri));
+    match rest.strip_prefix("//") {
+        Some(authority_and_path) => match authority_and_path.find('/') {
+            Some(index) => format!(
+                "{scheme}://{}{}",
+                &authority_and_path[..index],
+                remove_dot_segments(&authority_and_path[index..])
+            ),
+            None => uri.to_string(),
+        },
+        None => format!("{scheme}:{}", remove_dot_segments(rest)),
+    }
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    if !path.split('/').any(|segment| segment == "." || segment == "..") {
+        return path.to_string();
+    }
+    let mut output: Vec<&str> = Vec::new();
+    let segments = path.split('/').collect::<Vec<_>>();
+    for (index, segment) in segments.iter().enumerate() {
+        let last = index == segments.len() - 1;
+        match *segment {
+            "." => {
+                if last {
+                    output.push("");
+                }
+            }
+            ".." => {
+                if output.len() > 1 || output.first().is_some_and(|first| !first.is_empty()) {
+                    output.pop();
+                }
+                if last {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+    output.join("/")
+}
@@ -0,0 +1,326 @@
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Single difference between two bundled schemas
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaChange {
+    pub definition: String,
+    pub pointer: String,
+    pub description: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompatReport {
+    /// Changes that reject pipelines the old schemas accept
+    pub breaking: Vec<SchemaChange>,
+    /// Changes that only extend the schemas, pipelines valid for the old schemas stay valid
+    pub additive: Vec<SchemaChange>,
+    /// Changes of the generated Rust API that breaking or additive changes imply. The generated types
+    /// are exhaustive, so e.g. a new optional field breaks struct literals and a new variant matches.
+    pub rust_api: Vec<SchemaChange>,
+    /// Whether definitions differ in anything not classified above, e.g. descriptions
+    pub other_changes: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum SemverBump {
+    None,
+    Patch,
+    Minor,
+    Major,
+}
+
+/// Compares two bundled schemas (see [crate::get_bundled_schema]) definition by definition
+pub fn compare_bundled_schemas(old: &Value, new: &Value) -> CompatReport {
+    let empty = serde_json::Map::new();
+    let old_defs = old.get("$defs").and_then(Value::as_object).unwrap_or(&empty);
+    let new_defs = new.get("$defs").and_then(Value::as_object).unwrap_or(&empty);
+    let mut comparison = Comparison { report: CompatReport::default(), definition: String::new() };
+
+    for (name, old_def) in old_defs {
+        comparison.definition = name.clone();
+        match new_defs.get(name) {
+            Some(new_def) => {
+                comparison.compare(old_def, new_def, "");
+                if old_def != new_def {
+                    comparison.report.other_changes = true;
+                }
+            }
+            None => comparison.breaking("", "definition removed".to_string()),
+        }
+    }
+    for name in new_defs.keys().filter(|name| !old_defs.contains_key(*name)) {
+        comparison.definition = name.clone();
+        comparison.additive("", "definition added".to_string());
+    }
+
+    comparison.report
+}
+
+impl CompatReport {
+    /// Semver bump of the schemas implied by the changes, Rust callers may need more, see [CompatReport::rust_api]
+    pub fn bump(&self) -> SemverBump {
+        if !self.breaking.is_empty() {
+            SemverBump::Major
+        } else if !self.additive.is_empty() {
+            SemverBump::Minor
+        } else if self.other_changes {
+            SemverBump::Patch
+        } else {
+            SemverBump::None
+        }
+    }
+}
+
+struct Comparison {
+    report: CompatReport,
+    definition: String,
+}
+
+impl Comparison {
+    fn breaking(&mut self, pointer: &str, description: String) {
+        let change = SchemaChange { definition: self.definition.clone(), pointer: pointer.to_string(), description };
+        self.report.breaking.push(change);
+    }
+
+    fn additive(&mut self, pointer: &str, description: String) {
+        let change = SchemaChange { definition: self.definition.clone(), pointer: pointer.to_string(), description };
+        self.report.additive.push(change);
+    }
+
+    fn rust_api(&mut self, pointer: &str, description: String) {
+        let change = SchemaChange { definition: self.definition.clone(), pointer: pointer.to_string(), description };
+        self.report.rust_api.push(change);
+    }
+
+    fn compare(&mut self, old: &Value, new: &Value, pointer: &str) {
+        let (Value::Object(old_map), Value::Object(new_map)) = (old, new) else {
+            if old != new {
+                self.breaking(pointer, format!("schema changed from {old} to {new}"));
+            }
+            return;
+        };
+
+        if old_map.get("$ref") != new_map.get("$ref") {
+            let display = |value: Option<&Value>| value.map(Value::to_string).unwrap_or("none".to_string());
+            self.breaking(pointer, format!("$ref changed from {} to {}", display(old_map.get("$ref")), display(new_map.get("$ref"))));
+        }
+        if old_map.get("const") != new_map.get("const") {
+            self.breaking(pointer, "const value changed".to_string());
+        }
+
+        self.compare_types(old_map.get("type"), new_map.get("type"), pointer);
+        self.compare_required(old_map.get("required"), new_map.get("required"), pointer);
+        self.compare_enum(old_map.get("enum"), new_map.get("enum"), pointer);
+        self.compare_additional_properties(old_map.get("additionalProperties"), new_map.get("additionalProperties"), pointer);
+        self.compare_bounds(old_map, new_map, pointer);
+
+        let empty = serde_json::Map::new();
+        let old_properties = old_map.get("properties").and_then(Value::as_object).unwrap_or(&empty);
+        let new_properties = new_map.get("properties").and_then(Value::as_object).unwrap_or(&empty);
+        for (name, old_property) in old_properties {
+            let property_pointer = format!("{pointer}/properties/{name}");
+            match new_properties.get(name) {
+                Some(new_property) => self.compare(old_property, new_property, &property_pointer),
+                None => self.breaking(&property_pointer, format!("property `{name}` removed")),
+            }
+        }
+        // a required property added is reported by `compare_required`
+        let required = string_set(new_map.get("required"));
+        for name in new_properties.keys().filter(|name| !old_properties.contains_key(*name)) {
+            let property_pointer = format!("{pointer}/properties/{name}");
+            if !required.contains(name) {
+                self.additive(&property_pointer, format!("optional property `{name}` added"));
+            }
+            self.rust_api(&property_pointer, format!("generated struct gets field `{name}`, struct literals need it"));
+        }
+
+        for keyword in ["items", "additionalProperties"] {
+            if let (Some(old_schema @ Value::Object(_)), Some(new_schema @ Value::Object(_))) = (old_map.get(keyword), new_map.get(keyword)) {
+                self.compare(old_schema, new_schema, &format!("{pointer}/{keyword}"));
+            }
+        }
+
+        for keyword in ["oneOf", "anyOf", "allOf"] {
+            let branches = |map: &serde_json::Map<String, Value>| -> Vec<Value> {
+                map.get(keyword).and_then(Value::as_array).cloned().unwrap_or_default()
+            };
+            let old_branches = branches(old_map);
+            let new_branches = branches(new_map);
+            for (index, old_branch) in old_branches.iter().enumerate() {
+                if !new_branches.contains(old_branch) {
+                    // a branch changed in place is compared, a branch that disappeared is removed
+                    match new_branches.get(index) {
+                        Some(new_branch) if !old_branches.contains(new_branch) =>
+                            self.compare(old_branch, new_branch, &format!("{pointer}/{keyword}/{index}")),
+                        _ => self.breaking(&format!("{pointer}/{keyword}/{index}"), format!("{keyword} branch {old_branch} removed")),
+                    }
+                }
+            }
+            for (index, new_branch) in new_branches.iter().enumerate() {
+                let replaced_in_place = old_branches.get(index).is_some_and(|old_branch| !new_branches.contains(old_branch));
+                if !old_branches.contains(new_branch) && !replaced_in_place {
+                    let branch_pointer = format!("{pointer}/{keyword}/{index}");
+                    if keyword == "allOf" {
+                        // every branch of `allOf` applies, another one narrows the schema
+                        self.breaking(&branch_pointer, format!("allOf branch {new_branch} added"));
+                    } else {
+                        self.additive(&branch_pointer, format!("{keyword} branch {new_branch} added"));
+                        self.rust_api(&branch_pointer, "generated enum gets a variant, exhaustive matches need it".to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    fn compare_types(&mut self, old: Option<&Value>, new: Option<&Value>, pointer: &str) {
+        let old_types = schema_types(old);
+        let new_types = schema_types(new);
+        if old_types == new_types {
+            return;
+        }
+        let display = |types: &Option<BTreeSet<String>>| match types {
+            Some(types) => types.iter().cloned().collect::<Vec<_>>().join(" | "),
+            None => "any".to_string(),
+        };
+        let narrowed = match (&old_types, &new_types) {
+            (None, Some(_)) => true,
+            (Some(old_types), Some(new_types)) => old_types.iter().any(|old_type| {
+                let still_accepted = new_types.contains(old_type) || (old_type == "integer" && new_types.contains("number"));
+                !still_accepted
+            }),
+            _ => false,
+        };
+        let description = format!("type changed from {} to {}", display(&old_types), display(&new_types));
+        if narrowed {
+            self.breaking(pointer, format!("{description}, narrowed"));
+        } else {
+            // widened types accept existing pipelines, but change generated Rust types
+            self.additive(pointer, format!("{description}, widened"));
+            self.rust_api(pointer, "generated Rust type changes".to_string());
+        }
+    }
+
+    fn compare_required(&mut self, old: Option<&Value>, new: Option<&Value>, pointer: &str) {
+        let old_required = string_set(old);
+        let new_required = string_set(new);
+        for name in new_required.difference(&old_required) {
+            self.breaking(&format!("{pointer}/required"), format!("property `{name}` became required"));
+        }
+        for name in old_required.difference(&new_required) {
+            self.additive(&format!("{pointer}/required"), format!("property `{name}` became optional"));
+            self.rust_api(&format!("{pointer}/required"), format!("field `{name}` becomes an `Option`"));
+        }
+    }
+
+    fn compare_enum(&mut self, old: Option<&Value>, new: Option<&Value>, pointer: &str) {
+        let (Some(Value::Array(old_values)), Some(Value::Array(new_values))) = (old, new) else {
+            if old.is_some() != new.is_some() {
+                self.breaking(&format!("{pointer}/enum"), "enum added or removed".to_string());
+            }
+            return;
+        };
+        for value in old_values.iter().filter(|value| !new_values.contains(value)) {
+            self.breaking(&format!("{pointer}/enum"), format!("enum value {value} removed"));
+        }
+        for value in new_values.iter().filter(|value| !old_values.contains(value)) {
+            self.additive(&format!("{pointer}/enum"), format!("enum value {value} added"));
+            self.rust_api(&format!("{pointer}/enum"), format!("generated enum gets a variant for {value}, exhaustive matches need it"));
+        }
+    }
+
+    fn compare_additional_properties(&mut self, old: Option<&Value>, new: Option<&Value>, pointer: &str) {
+        let allows = |value: Option<&Value>| !matches!(value, Some(Value::Bool(false)));
+        let pointer = format!("{pointer}/additionalProperties");
+        match (allows(old), allows(new)) {
+            (true, false) => self.breaking(&pointer, "additionalProperties changed from true to false".to_string()),
+            (false, true) => self.additive(&pointer, "additionalProperties changed from false to true".to_string()),
+            _ => {}
+        }
+    }
+
+    fn compare_bounds(
+        &mut self,
+        old: &serde_json::Map<String, Value>,
+        new: &serde_json::Map<String, Value>,
+        pointer: &str
+    ) {
+        let lower_bounds = ["minimum", "exclusiveMinimum", "minLength", "minItems", "minProperties"];
+        let upper_bounds = ["maximum", "exclusiveMaximum", "maxLength", "maxItems", "maxProperties"];
+        for keyword in lower_bounds.into_iter().chain(upper_bounds) {
+            let old_bound = old.get(keyword).and_then(Value::as_f64);
+            let new_bound = new.get(keyword).and_then(Value::as_f64);
+            if old_bound == new_bound {
+                continue;
+            }
+            let is_lower = lower_bounds.contains(&keyword);
+            let narrowed = match (old_bound, new_bound) {
+                (None, Some(_)) => true,
+                (Some(_), None) => false,
+                (Some(old_bound), Some(new_bound)) => if is_lower { new_bound > old_bound } else { new_bound < old_bound },
+                (None, None) => false,
+            };
+            let description = format!("{keyword} changed from {old_bound:?} to {new_bound:?}");
+            if narrowed {
+                self.breaking(&format!("{pointer}/{keyword}"), description);
+            } else {
+                self.additive(&format!("{pointer}/{keyword}"), description);
+            }
+        }
+    }
+}
+
+fn schema_types(value: Option<&Value>) -> Option<BTreeSet<String>> {
+    match value {
+        Some(Value::String(schema_type)) => Some(BTreeSet::from([schema_type.clone()])),
+        Some(Value::Array(_)) => Some(string_set(value)),
+        _ => None,
+    }
+}
+
+fn string_set(value: Option<&Value>) -> BTreeSet<String> {
+    value.and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect()
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}: {}", self.definition, self.pointer, self.description)
+    }
+}
+
+impl fmt::Display for SemverBump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SemverBump::None => "none",
+            SemverBump::Patch => "patch",
+            SemverBump::Minor => "minor",
+            SemverBump::Major => "major",
+        })
+    }
+}
+
+impl fmt::Display for CompatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Breaking changes: {}", self.breaking.len())?;
+        for change in &self.breaking {
+            writeln!(f, "  {change}")?;
+        }
+        writeln!(f, "Additive changes: {}", self.additive.len())?;
+        for change in &self.additive {
+            writeln!(f, "  {change}")?;
+        }
+        if !self.rust_api.is_empty() {
+            writeln!(f, "Rust API changes: {}", self.rust_api.len())?;
+            for change in &self.rust_api {
+                writeln!(f, "  {change}")?;
+            }
+        }
+        writeln!(f, "Implied semver bump: {}", self.bump())
+    }
+}
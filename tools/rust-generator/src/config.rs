@@ -0,0 +1,335 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+/// Default name of the config file, looked up in the current directory
+pub const CONFIG_FILE_NAME: &str = "rust-generator.toml";
+
+/// Generator settings read from `rust-generator.toml`, every section and key is optional.
+/// Relative paths in the file are relative to the directory of the file:
+///
+/// ```toml
+/// [package]
+/// name = "agentic-pipeline-schemas"
+/// version = "0.0.1"
+/// schema_version = "1"
+/// license = "MIT"
+///
+/// [package.dependencies]
+/// schemars = "0.8.22"
+///
+/// [schemas]
+/// suffix = ".schema.json"
+/// include = ["**"]
+/// exclude = ["drafts/**"]
+/// vendor_keywords = ["x-rust-type", "x-ui-*"]
+///
+/// [schemas.catalog]
+/// "https://schemas.example.com/common/v1.json" = "vendor/common-v1.schema.json"
+/// "https://schemas.example.com/shared/" = "vendor/shared/"
+///
+/// [types]
+/// derives = ["PartialEq", "Eq"]
+/// attributes = ['#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]']
+/// struct_builder = false
+/// unknown_crates = "allow"
+/// roots = ["pipeline"]
+///
+/// [types.renames]
+/// StartConfig = "StartNodeConfig"
+///
+/// [types.patches.PortDirection]
+/// derives = ["Default"]
+///
+/// [types.definitions.agentConfig]
+/// rename = "AgentNodeConfig"
+/// fields = { agent_id = "agent" }
+///
+/// [types.definitions."common.expressionSpec"]
+/// replace = "crate::expression::Expression"
+///
+/// [lints]
+/// unreferenced_definition = "allow"
+/// missing_additional_properties = "deny"
+/// missing_description = "allow"
+///
+/// [output]
+/// crate_dir = "rust"
+/// types_layout = "modules"
+/// modules = ["src/hand_written/expression.rs"]
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneratorConfig {
+    pub package: PackageConfig,
+    pub schemas: SchemasConfig,
+    pub types: TypesConfig,
+    pub lints: LintsConfig,
+    pub output: OutputConfig,
+}
+
+/// Fields of the generated Cargo.toml
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageConfig {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    /// Pipeline schema version of the generated crate, bumped with a migration of stored pipelines,
+    /// so it is set explicitly instead of following [version]
+    pub schema_version: Option<String>,
+    pub edition: String,
+    pub description: String,
+    pub homepage: String,
+    pub repository: String,
+    pub license: String,
+    pub readme: String,
+    /// Dependencies added to the generated Cargo.toml, e.g. for derives, name to version requirement
+    pub dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchemasConfig {
+    /// Suffix of schema file names, the rest of the path relative to the schemas directory is the file key,
+    /// e.g. `nodes/agent`, its root is bundled as definition `nodes_agent`. For a `.json` suffix the JSON5
+    /// and YAML variants are read too, e.g. `nodes/agent.schema.yaml`, a file key can have only one of them.
+    pub suffix: String,
+    /// Globs of schema file paths relative to the schemas directory that are read, e.g. `nodes/*.schema.json`.
+    /// `*` matches within a path segment, `?` one character, `**` any number of segments, see [crate::discovery::Glob].
+    pub include: Vec<String>,
+    /// Globs of schema file paths that are left out even if an `include` glob matches them
+    pub exclude: Vec<String>,
+    /// Keywords the meta-schema does not define that schemas may use, e.g. `x-rust-type`.
+    /// A trailing `*` matches any keyword with the prefix, e.g. `x-*`.
+    pub vendor_keywords: Vec<String>,
+    /// `$id` URI of a schema outside the schemas directory to its file, relative to the config file,
+    /// so `$ref`s by URI resolve offline. A URI ending with `/` maps the URIs under it into a directory.
+    pub catalog: BTreeMap<String, String>,
+}
+
+/// Settings of the typify type space
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TypesConfig {
+    /// Derives added to every generated type where its fields allow them
+    pub derives: Vec<String>,
+    /// Attributes added to every generated type, e.g. `#[serde(...)]` or `#[cfg_attr(...)]`
+    pub attributes: Vec<String>,
+    pub struct_builder: bool,
+    pub unknown_crates: UnknownCratePolicy,
+    /// Definitions to generate types for, together with the definitions they reference.
+    /// Empty means every definition.
+    pub roots: Vec<String>,
+    /// Generated type name to the name it is renamed to. The generated name stays an alias of the type,
+    /// e.g. `pub type StartConfig = StartNodeConfig;`, so the runtime modules keep compiling.
+    pub renames: BTreeMap<String, String>,
+    /// Derives and attributes of single types, by the generated type name after renames
+    pub patches: BTreeMap<String, TypePatch>,
+    /// Rules for schema definitions, by the bundled definition key, e.g. `agentConfig`,
+    /// or by the schema file and definition, e.g. `common.expressionSpec`
+    pub definitions: BTreeMap<String, DefinitionConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TypePatch {
+    pub derives: Vec<String>,
+    pub attributes: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefinitionConfig {
+    /// Rust type name of the definition, the generated name stays an alias of it as for `types.renames`
+    pub rename: Option<String>,
+    /// JSON property name to Rust field name. Fields the runtime modules or the crate tests read on the type,
+    /// e.g. `id` of nodes, can not be renamed while the crate is generated with them.
+    pub fields: BTreeMap<String, String>,
+    /// Path of a hand-written type used instead of the generated one, e.g. `crate::expression::Expression`.
+    /// The generator does not compile the type, instead the generated crate gets a test that deserializes
+    /// sample instances of the definition, one per `oneOf` branch and `enum` value, into it and serializes
+    /// them back unchanged, run by `cargo test`.
+    pub replace: Option<String>,
+}
+
+/// Policy for types from crates named by `x-rust-type` schema extensions
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownCratePolicy {
+    Generate,
+    Allow,
+    Deny,
+}
+
+/// Levels of the schema lints run before generation
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintsConfig {
+    /// Keyword value that does not match the meta-schema declared by `$schema`
+    pub invalid_schema: LintLevel,
+    /// Keyword the meta-schema does not define and that is not in `schemas.vendor_keywords`
+    pub unknown_keyword: LintLevel,
+    /// `required` entry that is not in `properties`
+    pub required_without_property: LintLevel,
+    /// Node schema or node config without `additionalProperties: false` while its siblings have it
+    pub missing_additional_properties: LintLevel,
+    /// Definition that no `$ref` points to
+    pub unreferenced_definition: LintLevel,
+    /// Node schema without the required `id`, `type`, `ports` and `config` properties
+    pub node_shape: LintLevel,
+    /// Node `type` const that is missing, used by another node or differs from `config.kind`
+    pub node_discriminator: LintLevel,
+    /// Definition of the bundle without `description`, its generated type has no docs
+    pub missing_description: LintLevel,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Generation fails
+    Deny,
+    /// Reported as a warning
+    Warn,
+    /// Not reported
+    Allow,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Directory of the generated crate, relative to the output directory
+    pub crate_dir: String,
+    pub types_layout: TypesLayout,
+    /// Hand-written source files copied into the generated crate as modules, e.g. for replaced types,
+    /// relative to the config file
+    pub modules: Vec<String>,
+}
+
+/// Layout of the generated `types` module
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TypesLayout {
+    /// src/types/mod.rs re-exporting a module per schema file, e.g. src/types/agent.rs
+    Modules,
+    /// All types in src/types.rs
+    Single,
+}
+
+impl GeneratorConfig {
+    /// Reads config from [path], unknown keys are errors. Relative paths of `schemas.catalog` and
+    /// `output.modules` are resolved against the directory of [path].
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let mut config = Self::from_toml(&content).map_err(|err| format!("{}: {err}", path.display()))?;
+        let config_dir = std::path::absolute(path)?.parent().map(Path::to_path_buf).unwrap_or_default();
+        let resolve = |relative_path: &mut String| {
+            *relative_path = config_dir.join(&*relative_path).to_string_lossy().into_owned();
+        };
+        config.schemas.catalog.values_mut().for_each(resolve);
+        config.output.modules.iter_mut().for_each(resolve);
+        Ok(config)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Config with [package_name] and [package_version], everything else is default
+    pub fn for_package(package_name: &str, package_version: &str, schema_version: &str) -> Self {
+        let mut config = GeneratorConfig::default();
+        config.package.name = Some(package_name.to_string());
+        config.package.version = Some(package_version.to_string());
+        config.package.schema_version = Some(schema_version.to_string());
+        config
+    }
+}
+
+impl PackageConfig {
+    pub fn name(&self) -> Result<&str, Box<dyn Error>> {
+        self.name.as_deref().ok_or("package name is set neither in config nor by --package-name".into())
+    }
+
+    pub fn version(&self) -> Result<&str, Box<dyn Error>> {
+        self.version.as_deref().ok_or("package version is set neither in config nor by --package-version".into())
+    }
+
+    pub fn schema_version(&self) -> Result<&str, Box<dyn Error>> {
+        self.schema_version.as_deref().ok_or("schema version is set neither in config nor by --schema-version".into())
+    }
+}
+
+impl Default for PackageConfig {
+    fn default() -> Self {
+        PackageConfig {
+            name: None,
+            version: None,
+            schema_version: None,
+            edition: "2024".to_string(),
+            description: "Rust types and parser generated from JSON schemas".to_string(),
+            homepage: "https://github.com/agolokoz/agentic-pipilenes-schemas".to_string(),
+            repository: "https://github.com/agolokoz/agentic-pipilenes-schemas".to_string(),
+            license: "MIT".to_string(),
+            readme: "README.md".to_string(),
+            dependencies: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for SchemasConfig {
+    fn default() -> Self {
+        SchemasConfig {
+            suffix: ".schema.json".to_string(),
+            include: vec!["**".to_string()],
+            exclude: Vec::new(),
+            vendor_keywords: vec!["x-rust-type".to_string()],
+            catalog: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for TypesConfig {
+    fn default() -> Self {
+        TypesConfig {
+            derives: vec!["PartialEq".to_string()],
+            attributes: Vec::new(),
+            struct_builder: false,
+            unknown_crates: UnknownCratePolicy::Allow,
+            roots: Vec::new(),
+            renames: BTreeMap::new(),
+            patches: BTreeMap::new(),
+            definitions: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for LintsConfig {
+    fn default() -> Self {
+        LintsConfig {
+            invalid_schema: LintLevel::Deny,
+            unknown_keyword: LintLevel::Deny,
+            required_without_property: LintLevel::Deny,
+            missing_additional_properties: LintLevel::Warn,
+            unreferenced_definition: LintLevel::Warn,
+            node_shape: LintLevel::Deny,
+            node_discriminator: LintLevel::Deny,
+            missing_description: LintLevel::Warn,
+        }
+    }
+}
+
+impl From<UnknownCratePolicy> for typify::UnknownPolicy {
+    fn from(policy: UnknownCratePolicy) -> Self {
+        match policy {
+            UnknownCratePolicy::Generate => typify::UnknownPolicy::Generate,
+            UnknownCratePolicy::Allow => typify::UnknownPolicy::Allow,
+            UnknownCratePolicy::Deny => typify::UnknownPolicy::Deny,
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig { crate_dir: "rust".to_string(), types_layout: TypesLayout::Modules, modules: Vec::new() }
+    }
+}
@@ -0,0 +1,367 @@
+use crate::config::{DefinitionConfig, TypesConfig};
+use crate::field_uses::FieldReads;
+use crate::passes::type_name;
+use crate::Bundle;
+use heck::ToPascalCase;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use syn::visit_mut::VisitMut;
+use syn::{Expr, ExprStruct, Fields, Item, Member};
+
+/// Definition rules of the config resolved against the bundled schema
+pub struct DefinitionRules<'a> {
+    /// Bundled definition key to its rules
+    rules: BTreeMap<String, &'a DefinitionConfig>,
+    /// Generated type name to the name it is renamed to by `types.renames` or a definition rename
+    renames: BTreeMap<String, String>,
+    /// Sources of the runtime modules and the crate tests, fields they read can not be renamed
+    runtime_sources: &'a [&'a str],
+}
+
+impl<'a> DefinitionRules<'a> {
+    /// Resolves keys of `types.definitions` against the definitions of [bundle],
+    /// [runtime_sources] are the hand-written sources shipped with the crate
+    pub fn resolve(
+        types_config: &'a TypesConfig,
+        bundle: &Bundle,
+        runtime_sources: &'a [&'a str]
+    ) -> Result<Self, Box<dyn Error>> {
+        let empty = Map::new();
+        let defs = bundle.schema.get("$defs").and_then(Value::as_object).unwrap_or(&empty);
+
+        let mut rules = BTreeMap::new();
+        let mut renames = types_config.renames.clone();
+        for (key, definition_config) in &types_config.definitions {
+            let def_key = resolve_definition_key(key, defs, &bundle.sources)?;
+            if definition_config.replace.is_some() && !definition_config.fields.is_empty() {
+                return Err(format!("types.definitions.{key}: fields of a replaced definition can not be renamed").into());
+            }
+            if !definition_config.fields.is_empty() && types_config.struct_builder {
+                return Err(format!("types.definitions.{key}: fields can not be renamed together with struct builders").into());
+            }
+            if let Some(rename) = &definition_config.rename {
+                let renamed = types_config.renames.get(rename).unwrap_or(rename);
+                renames.insert(generated_type_name(&def_key), renamed.clone());
+            }
+            rules.insert(def_key, definition_config);
+        }
+        Ok(DefinitionRules { rules, renames, runtime_sources })
+    }
+
+    /// Adds renames and replacements to typify [settings]
+    pub fn configure(&self, settings: &mut typify::TypeSpaceSettings) {
+        for (def_key, definition_config) in &self.rules {
+            let type_name = generated_type_name(def_key);
+            if let Some(replace) = &definition_config.replace {
+                settings.with_replacement(&type_name, replace, std::iter::empty());
+            } else if let Some(rename) = &definition_config.rename {
+                settings.with_patch(&type_name, typify::TypeSpacePatch::default().with_rename(rename));
+            }
+        }
+    }
+
+    /// Renames struct fields in [file] and adds aliases named after replaced and renamed types,
+    /// so hand-written code using the generated names keeps compiling. Returns the renamed fields,
+    /// by the names of their type and its aliases, for [crate::field_uses::rename_struct_fields].
+    pub fn apply(&self, file: &mut syn::File) -> Result<BTreeMap<String, BTreeMap<String, String>>, Box<dyn Error>> {
+        let mut field_renames = BTreeMap::new();
+        let mut reads = None;
+        for (def_key, definition_config) in &self.rules {
+            let type_name = definition_config.rename.clone().unwrap_or_else(|| generated_type_name(def_key));
+            if let Some(replace) = &definition_config.replace {
+                let replace_path = syn::parse_str::<syn::Path>(replace)
+                    .map_err(|err| format!("types.definitions.{def_key}: invalid replace path `{replace}`: {err}"))?;
+                let type_ident = syn::parse_str::<syn::Ident>(&type_name)?;
+                if replace_path.segments.last().is_none_or(|segment| segment.ident != type_ident) {
+                    file.items.push(syn::parse_quote!(pub type #type_ident = #replace_path;));
+                }
+                continue;
+            }
+            if !definition_config.fields.is_empty() {
+                // reads are collected before any field is renamed, the sources use the generated names
+                let reads = match &mut reads {
+                    Some(reads) => reads,
+                    None => reads.insert(FieldReads::collect(self.runtime_sources, file, &self.aliases())?),
+                };
+                let renamed = rename_fields(file, &type_name, &definition_config.fields, reads)
+                    .map_err(|err| format!("types.definitions.{def_key}: {err}"))?;
+                field_renames.insert(type_name, renamed);
+            }
+        }
+
+        // e.g. `pub type OutputSelector = OutputSelection;` for the runtime modules
+        let declared = file.items.iter().filter_map(type_name).collect::<BTreeSet<_>>();
+        for (generated, renamed) in &self.renames {
+            if generated == renamed || declared.contains(generated) || !declared.contains(renamed) {
+                continue;
+            }
+            let generated_ident = syn::parse_str::<syn::Ident>(generated)?;
+            let renamed_ident = syn::parse_str::<syn::Ident>(renamed)
+                .map_err(|err| format!("invalid type name `{renamed}`: {err}"))?;
+            file.items.push(syn::parse_quote!(pub type #generated_ident = #renamed_ident;));
+        }
+        for (alias, type_name) in self.aliases() {
+            if let Some(renamed) = field_renames.get(&type_name).cloned() {
+                field_renames.insert(alias, renamed);
+            }
+        }
+        Ok(field_renames)
+    }
+
+    /// Generated type names to the names the types are renamed to
+    fn aliases(&self) -> BTreeMap<String, String> {
+        self.renames.iter()
+            .filter(|(generated, renamed)| generated != renamed)
+            .map(|(generated, renamed)| (generated.clone(), renamed.clone()))
+            .collect()
+    }
+
+    /// Tests for the generated crate that deserialize sample instances of every replaced
+    /// definition into the hand-written type and serialize them back unchanged
+    pub fn replacement_tests(&self, schema: &Value) -> String {
+        let empty = Map::new();
+        let defs = schema.get("$defs").and_then(Value::as_object).unwrap_or(&empty);
+
+        let mut tests = String::new();
+        for (def_key, definition_config) in &self.rules {
+            let Some(replace) = &definition_config.replace else {
+                continue;
+            };
+            let samples = Value::Array(sample_instances(&defs[def_key], defs, 0));
+            let test_name = format!("{}_replacement_round_trips_schema_json", heck::ToSnakeCase::to_snake_case(def_key.as_str()));
+            tests.push_str(&format!(r##"
+    #[test]
+    fn {test_name}() {{
+        let samples: Vec<serde_json::Value> = serde_json::from_str(r#"{samples}"#).unwrap();
+        for sample in samples {{
+            let value: {replace} = serde_json::from_value(sample.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&value).unwrap(), sample);
+        }}
+    }}
+"##));
+        }
+        if tests.is_empty() {
+            return tests;
+        }
+        format!("\n#[cfg(test)]\nmod replacement_tests {{{tests}}}\n")
+    }
+}
+
+/// Name typify gives to the type of bundled definition [def_key]
+pub fn generated_type_name(def_key: &str) -> String {
+    def_key.to_pascal_case()
+}
+
+/// Name of the generated type of definition [def_key] after the renames of [types_config]
+pub fn definition_type_name(def_key: &str, types_config: &TypesConfig) -> String {
+    let renamed = types_config.definitions.iter()
+        .find(|(key, _)| *key == def_key || key.split_once('.').is_some_and(|(_, key)| key == def_key))
+        .and_then(|(_, definition_config)| definition_config.rename.clone());
+    let type_name = renamed.unwrap_or_else(|| generated_type_name(def_key));
+    types_config.renames.get(&type_name).cloned().unwrap_or(type_name)
+}
+
+/// Bundled definition key of config [key]: the key itself or `<file>.<definition>`, e.g. `nodes/agent.agentConfig`,
+/// where the definition has to come from the file by [sources]
+fn resolve_definition_key(
+    key: &str,
+    defs: &Map<String, Value>,
+    sources: &BTreeMap<String, String>
+) -> Result<String, Box<dyn Error>> {
+    if defs.contains_key(key) {
+        return Ok(key.to_string());
+    }
+    if let Some((file_key, def_key)) = key.split_once('.')
+        && defs.contains_key(def_key)
+    {
+        return match sources.get(def_key) {
+            Some(source) if source == file_key => Ok(def_key.to_string()),
+            Some(source) => Err(format!("types.definitions.{key}: definition `{def_key}` comes from `{source}`, not `{file_key}`").into()),
+            None => Err(format!("types.definitions.{key}: the schema file of definition `{def_key}` is unknown").into()),
+        };
+    }
+    Err(format!("types.definitions: there is no definition `{key}` in the bundled schema").into())
+}
+
+/// Renames fields of struct [type_name] by their JSON names and keeps the JSON names with `#[serde(rename)]`.
+/// Fields hand-written code [reads] are kept. Returns the old field names to the new ones.
+fn rename_fields(
+    file: &mut syn::File,
+    type_name: &str,
+    fields: &BTreeMap<String, String>,
+    reads: &FieldReads
+) -> Result<BTreeMap<String, String>, String> {
+    let item = file.items.iter_mut()
+        .find_map(|item| match item {
+            Item::Struct(item) if item.ident == type_name => Some(item),
+            _ => None,
+        })
+        .ok_or_else(|| format!("there is no generated struct `{type_name}`"))?;
+    let Fields::Named(named_fields) = &mut item.fields else {
+        return Err(format!("`{type_name}` has no named fields"));
+    };
+
+    let mut renamed_idents = BTreeMap::new();
+    for (json_name, rust_name) in fields {
+        let field = named_fields.named.iter_mut()
+            .find(|field| json_field_name(field) == *json_name)
+            .ok_or_else(|| format!("`{type_name}` has no field for property `{json_name}`"))?;
+        let new_ident = syn::parse_str::<syn::Ident>(rust_name).map_err(|err| format!("invalid field name `{rust_name}`: {err}"))?;
+        if let Some(ident) = &field.ident
+            && reads.reads(type_name, &ident.to_string())
+        {
+            return Err(format!("field `{ident}` of `{type_name}` can not be renamed, the runtime modules or the crate tests read it"));
+        }
+        let old_ident = field.ident.replace(new_ident.clone()).expect("named field");
+        if serde_rename(&field.attrs).is_none() {
+            field.attrs.push(syn::parse_quote!(#[serde(rename = #json_name)]));
+        }
+        renamed_idents.insert(old_ident.to_string(), new_ident);
+    }
+
+    let mut renamer = FieldRenamer { type_name, renamed_idents: renamed_idents.clone(), in_type_impl: false };
+    renamer.visit_file_mut(file);
+    Ok(renamed_idents.into_iter().map(|(old, new)| (old, new.to_string())).collect())
+}
+
+/// Name of [field] in JSON
+fn json_field_name(field: &syn::Field) -> String {
+    serde_rename(&field.attrs)
+        .or_else(|| field.ident.as_ref().map(ToString::to_string))
+        .unwrap_or_default()
+}
+
+/// JSON name set by `#[serde(rename = "...")]`
+pub fn serde_rename(attrs: &[syn::Attribute]) -> Option<String> {
+    let mut rename = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                rename = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+            } else if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<Expr>()?;
+            }
+            Ok(())
+        });
+    }
+    rename
+}
+
+/// Renames fields in struct expressions of the renamed type, e.g. in typify `Default` impls
+struct FieldRenamer<'a> {
+    type_name: &'a str,
+    renamed_idents: BTreeMap<String, syn::Ident>,
+    in_type_impl: bool,
+}
+
+impl VisitMut for FieldRenamer<'_> {
+    fn visit_item_impl_mut(&mut self, item: &mut syn::ItemImpl) {
+        let in_type_impl = self.in_type_impl;
+        self.in_type_impl = matches!(&*item.self_ty, syn::Type::Path(path) if path.path.is_ident(self.type_name));
+        syn::visit_mut::visit_item_impl_mut(self, item);
+        self.in_type_impl = in_type_impl;
+    }
+
+    fn visit_expr_struct_mut(&mut self, expr: &mut ExprStruct) {
+        let is_type = expr.path.is_ident(self.type_name) || (self.in_type_impl && expr.path.is_ident("Self"));
+        if is_type {
+            for field in &mut expr.fields {
+                if let Member::Named(ident) = &mut field.member
+                    && let Some(new_ident) = self.renamed_idents.get(&ident.to_string())
+                {
+                    *ident = new_ident.clone();
+                }
+            }
+        }
+        syn::visit_mut::visit_expr_struct_mut(self, expr);
+    }
+}
+
+/// Depth of nested `$ref`s, items and properties [sample_instances] follows
+const SAMPLE_DEPTH: usize = 8;
+/// Number of instances [sample_instances] returns at most for a schema
+const MAX_SAMPLES: usize = 16;
+
+/// Instances of [schema] that together use every `oneOf`/`anyOf` branch and every `enum` value,
+/// used to check replacements. Optional properties are present until [SAMPLE_DEPTH], past it
+/// a `$ref` has no instance, so a schema that requires a recursive `$ref` has none.
+fn sample_instances(schema: &Value, defs: &Map<String, Value>, depth: usize) -> Vec<Value> {
+    let Value::Object(schema) = schema else {
+        return vec![json!("sample")];
+    };
+    if let Some(key) = schema.get("$ref").and_then(Value::as_str).and_then(|s| s.strip_prefix("#/$defs/"))
+        && let Some(def) = defs.get(key)
+    {
+        if depth >= SAMPLE_DEPTH {
+            return Vec::new();
+        }
+        return sample_instances(def, defs, depth + 1);
+    }
+    if let Some(value) = schema.get("const") {
+        return vec![value.clone()];
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return values.iter().take(MAX_SAMPLES).cloned().collect();
+    }
+    for keyword in ["oneOf", "anyOf"] {
+        if let Some(branches) = schema.get(keyword).and_then(Value::as_array) {
+            return branches.iter()
+                .flat_map(|branch| sample_instances(branch, defs, depth))
+                .take(MAX_SAMPLES)
+                .collect();
+        }
+    }
+
+    let schema_type = match schema.get("type") {
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).find(|t| *t != "null"),
+        Some(value) => value.as_str(),
+        None if schema.contains_key("properties") => Some("object"),
+        None => None,
+    };
+    match schema_type {
+        Some("string") => vec![json!("sample")],
+        Some("integer") => vec![json!(1)],
+        Some("number") => vec![json!(1.5)],
+        Some("boolean") => vec![json!(true)],
+        // one array holds every instance of the items, recursive schemas stop at empty arrays
+        Some("array") => match schema.get("items") {
+            Some(items) if depth < SAMPLE_DEPTH => vec![Value::Array(sample_instances(items, defs, depth + 1))],
+            _ => vec![json!([])],
+        },
+        Some("object") => {
+            let required = schema.get("required").and_then(Value::as_array).cloned().unwrap_or_default();
+            let mut properties = Vec::new();
+            for (name, property) in schema.get("properties").and_then(Value::as_object).into_iter().flatten() {
+                let samples = if depth < SAMPLE_DEPTH { sample_instances(property, defs, depth + 1) } else { Vec::new() };
+                match samples.is_empty() {
+                    true if required.contains(&json!(name)) => return Vec::new(),
+                    true => {}
+                    false => properties.push((name.clone(), samples)),
+                }
+            }
+            if let Some(additional @ Value::Object(_)) = schema.get("additionalProperties") && depth < SAMPLE_DEPTH {
+                let samples = sample_instances(additional, defs, depth + 1);
+                if !samples.is_empty() {
+                    properties.push(("key".to_string(), samples));
+                }
+            }
+
+            // the first instance of every property, then one object per further instance of a property
+            let first = properties.iter()
+                .map(|(name, samples)| (name.clone(), samples[0].clone()))
+                .collect::<Map<_, _>>();
+            let mut objects = vec![Value::Object(first.clone())];
+            for (name, samples) in &properties {
+                for sample in &samples[1..] {
+                    let mut object = first.clone();
+                    object.insert(name.clone(), sample.clone());
+                    objects.push(Value::Object(object));
+                }
+            }
+            objects.truncate(MAX_SAMPLES);
+            objects
+        }
+        _ => vec![json!("sample")],
+    }
+}
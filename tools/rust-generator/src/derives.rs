@@ -0,0 +1,270 @@
+use crate::config::TypesConfig;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use syn::punctuated::Punctuated;
+use syn::{Fields, GenericArgument, Item, Path, PathArguments, Token, Type};
+
+/// Requested derive that was not applied to a generated type
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedDerive {
+    pub type_name: String,
+    pub derive: String,
+    pub reason: String,
+}
+
+/// Generated struct or enum with the types of its fields
+struct GeneratedItem {
+    is_enum: bool,
+    derived: BTreeSet<String>,
+    field_types: Vec<(String, Type)>,
+}
+
+/// Adds derives from [types_config] to the generated types of [file].
+/// A derive is only added where it compiles: fields of the type have to implement the
+/// derived trait, e.g. `Eq` is skipped on types with `f64` or `serde_json::Value` fields
+/// and `Hash` on types with `HashMap` fields. Skipped derives are returned with the reason.
+pub fn apply_derives(file: &mut syn::File, types_config: &TypesConfig) -> Result<Vec<SkippedDerive>, Box<dyn Error>> {
+    let items = generated_items(file);
+    let aliases = type_aliases(file);
+    let implemented = implemented_traits(file);
+
+    // requested derives by type, already derived ones are left out
+    let mut requested: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    for (type_name, item) in &items {
+        let patch = types_config.patches.get(type_name);
+        let derives = types_config.derives.iter().chain(patch.into_iter().flat_map(|patch| &patch.derives));
+        for derive in derives {
+            let trait_name = trait_name(derive);
+            if !item.derived.contains(&trait_name) {
+                requested.entry(type_name.clone()).or_default().insert(trait_name, derive.clone());
+            }
+        }
+    }
+    if let Some(type_name) = types_config.patches.keys().find(|type_name| !items.contains_key(*type_name)) {
+        return Err(format!("types.patches: there is no generated type `{type_name}`").into());
+    }
+
+    // drops requested derives until every remaining one is supported by all fields
+    let mut skipped = Vec::new();
+    let checker = DeriveChecker { items: &items, aliases: &aliases, implemented: &implemented };
+    loop {
+        let mut unsupported = Vec::new();
+        for (type_name, derives) in &requested {
+            for (trait_name, derive) in derives {
+                if let Err(reason) = checker.check(type_name, trait_name, &requested) {
+                    unsupported.push(SkippedDerive { type_name: type_name.clone(), derive: derive.clone(), reason });
+                }
+            }
+        }
+        if unsupported.is_empty() {
+            break;
+        }
+        for skipped_derive in &unsupported {
+            if let Some(derives) = requested.get_mut(&skipped_derive.type_name) {
+                derives.remove(&trait_name(&skipped_derive.derive));
+            }
+        }
+        skipped.extend(unsupported);
+    }
+
+    for item in &mut file.items {
+        let (ident, attrs) = match item {
+            Item::Struct(item) => (&item.ident, &mut item.attrs),
+            Item::Enum(item) => (&item.ident, &mut item.attrs),
+            _ => continue,
+        };
+        let Some(derives) = requested.get(&ident.to_string()) else {
+            continue;
+        };
+        let Some(derive_attr) = attrs.iter_mut().find(|attr| attr.path().is_ident("derive")) else {
+            continue;
+        };
+        if !derives.is_empty() {
+            let mut paths = derive_attr.parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated)?;
+            for derive in derives.values() {
+                paths.push(syn::parse_str::<Path>(derive)?);
+            }
+            *derive_attr = syn::parse_quote!(#[derive(#paths)]);
+        }
+    }
+
+    skipped.sort_by(|a, b| (&a.type_name, &a.derive).cmp(&(&b.type_name, &b.derive)));
+    Ok(skipped)
+}
+
+struct DeriveChecker<'a> {
+    items: &'a BTreeMap<String, GeneratedItem>,
+    aliases: &'a BTreeMap<String, Type>,
+    implemented: &'a BTreeSet<(String, String)>,
+}
+
+impl DeriveChecker<'_> {
+    /// Whether [trait_name] can be derived for [type_name] given the derives still [requested]
+    fn check(
+        &self,
+        type_name: &str,
+        trait_name: &str,
+        requested: &BTreeMap<String, BTreeMap<String, String>>
+    ) -> Result<(), String> {
+        let item = &self.items[type_name];
+        let has_derive = |trait_name: &str| {
+            item.derived.contains(trait_name) || requested.get(type_name).is_some_and(|derives| derives.contains_key(trait_name))
+        };
+
+        if self.implemented.contains(&(trait_name.to_string(), type_name.to_string())) {
+            return Err(format!("`{type_name}` already implements `{trait_name}`"));
+        }
+        for required in required_traits(trait_name) {
+            if !has_derive(required) {
+                return Err(format!("`{trait_name}` requires `{required}`, which `{type_name}` does not derive"));
+            }
+        }
+        if trait_name == "Default" && item.is_enum {
+            return Err("enums have no default variant to derive `Default` from".to_string());
+        }
+        for (field_name, field_type) in &item.field_types {
+            self.check_type(field_type, trait_name, requested)
+                .map_err(|type_name| format!("{field_name} has type `{type_name}`, which does not implement `{trait_name}`"))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the type inside [ty] that does not implement [trait_name]
+    fn check_type(
+        &self,
+        ty: &Type,
+        trait_name: &str,
+        requested: &BTreeMap<String, BTreeMap<String, String>>
+    ) -> Result<(), String> {
+        let Type::Path(type_path) = ty else {
+            return Ok(());
+        };
+        let Some(segment) = type_path.path.segments.last() else {
+            return Ok(());
+        };
+        let name = segment.ident.to_string();
+
+        if let Some(item) = self.items.get(&name) {
+            let derived = item.derived.contains(trait_name)
+                || self.implemented.contains(&(trait_name.to_string(), name.clone()))
+                || requested.get(&name).is_some_and(|derives| derives.contains_key(trait_name));
+            return if derived { Ok(()) } else { Err(name) };
+        }
+        if let Some(aliased) = self.aliases.get(&name) {
+            return self.check_type(aliased, trait_name, requested);
+        }
+        if !leaf_implements(&name, trait_name) {
+            return Err(name);
+        }
+        if trait_name == "Default" && matches!(name.as_str(), "Option" | "Vec" | "HashMap" | "BTreeMap" | "HashSet" | "BTreeSet" | "Map") {
+            // empty containers do not need default values of their elements
+            return Ok(());
+        }
+        if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+            for argument in &arguments.args {
+                if let GenericArgument::Type(argument) = argument {
+                    self.check_type(argument, trait_name, requested)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a type that is not generated implements [trait_name], assuming its type arguments do
+fn leaf_implements(type_name: &str, trait_name: &str) -> bool {
+    match type_name {
+        "f32" | "f64" => !matches!(trait_name, "Eq" | "Hash" | "Ord"),
+        // `serde_json::Value` has `Eq` and `Hash` only in recent versions, its numbers may be floats
+        "Value" | "Map" => !matches!(trait_name, "Eq" | "Hash" | "PartialOrd" | "Ord" | "Copy"),
+        "HashMap" | "HashSet" => !matches!(trait_name, "Hash" | "PartialOrd" | "Ord" | "Copy"),
+        "String" | "Vec" | "Box" | "BTreeMap" | "BTreeSet" => trait_name != "Copy",
+        _ => true,
+    }
+}
+
+/// Traits [trait_name] can only be derived together with
+fn required_traits(trait_name: &str) -> &'static [&'static str] {
+    match trait_name {
+        "Eq" | "PartialOrd" => &["PartialEq"],
+        "Ord" => &["PartialOrd", "Eq"],
+        "Copy" => &["Clone"],
+        _ => &[],
+    }
+}
+
+/// Last segment of a derive path, e.g. "JsonSchema" for "schemars::JsonSchema"
+fn trait_name(derive: &str) -> String {
+    derive.rsplit("::").next().unwrap_or(derive).trim().to_string()
+}
+
+fn generated_items(file: &syn::File) -> BTreeMap<String, GeneratedItem> {
+    let mut items = BTreeMap::new();
+    for item in &file.items {
+        let (ident, attrs, is_enum, field_types) = match item {
+            Item::Struct(item) => (&item.ident, &item.attrs, false, fields_types(&item.fields, "")),
+            Item::Enum(item) => {
+                let field_types = item.variants.iter()
+                    .flat_map(|variant| fields_types(&variant.fields, &format!("variant `{}` ", variant.ident)))
+                    .collect();
+                (&item.ident, &item.attrs, true, field_types)
+            }
+            _ => continue,
+        };
+        let derived = attrs.iter()
+            .filter(|attr| attr.path().is_ident("derive"))
+            .filter_map(|attr| attr.parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated).ok())
+            .flatten()
+            .filter_map(|path| path.segments.last().map(|segment| segment.ident.to_string()))
+            .collect();
+        items.insert(ident.to_string(), GeneratedItem { is_enum, derived, field_types });
+    }
+    items
+}
+
+fn fields_types(fields: &Fields, prefix: &str) -> Vec<(String, Type)> {
+    fields.iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let field_name = match &field.ident {
+                Some(ident) => format!("{prefix}field `{ident}`"),
+                None => format!("{prefix}field {index}"),
+            };
+            (field_name, field.ty.clone())
+        })
+        .collect()
+}
+
+fn type_aliases(file: &syn::File) -> BTreeMap<String, Type> {
+    file.items.iter()
+        .filter_map(|item| match item {
+            Item::Type(item) => Some((item.ident.to_string(), (*item.ty).clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// (trait, type) pairs of hand-written `impl Trait for Type` blocks
+fn implemented_traits(file: &syn::File) -> BTreeSet<(String, String)> {
+    file.items.iter()
+        .filter_map(|item| {
+            let Item::Impl(item) = item else {
+                return None;
+            };
+            let (_, trait_path, _) = item.trait_.as_ref()?;
+            let Type::Path(self_type) = &*item.self_ty else {
+                return None;
+            };
+            let trait_name = trait_path.segments.last()?.ident.to_string();
+            let type_name = self_type.path.segments.last()?.ident.to_string();
+            Some((trait_name, type_name))
+        })
+        .collect()
+}
+
+impl fmt::Display for SkippedDerive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is not derived for `{}`: {}", self.derive, self.type_name, self.reason)
+    }
+}
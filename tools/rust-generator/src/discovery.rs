@@ -0,0 +1,149 @@
+use crate::config::SchemasConfig;
+use crate::sources::SourceFormat;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+/// Glob over `/` separated relative paths, e.g. `nodes/**/*.schema.json`:
+/// - `*` matches any characters within a path segment, also none
+/// - `?` matches one character, not a byte, within a path segment
+/// - `**` as a whole path segment matches any number of segments, also none
+///
+/// Other characters, including `[`, `]`, `{` and `}`, match themselves and there is no escaping.
+/// Patterns are relative: they neither start with `/` nor contain empty segments.
+#[derive(Clone, Debug)]
+pub struct Glob {
+    segments: Vec<String>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, Box<dyn Error>> {
+        let segments = pattern.split('/').map(str::to_string).collect::<Vec<_>>();
+        if pattern.is_empty() || pattern.starts_with('/') || segments.iter().any(String::is_empty) {
+            return Err(format!("invalid glob `{pattern}`, expected a relative path such as `nodes/*.schema.json`").into());
+        }
+        if let Some(segment) = segments.iter().find(|segment| segment.contains("**") && *segment != "**") {
+            return Err(format!("invalid glob `{pattern}`, `**` must be a whole path segment, found `{segment}`").into());
+        }
+        Ok(Glob { segments })
+    }
+
+    /// Whether `/` separated relative [path] matches the glob
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.split('/').collect::<Vec<_>>();
+        matches_segments(&self.segments, &path)
+    }
+}
+
+fn matches_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => (0..=path.len()).any(|skipped| matches_segments(rest, &path[skipped..])),
+        Some((first, rest)) => path.split_first().is_some_and(|(segment, path)| {
+            matches_segment(&first.chars().collect::<Vec<_>>(), &segment.chars().collect::<Vec<_>>()) && matches_segments(rest, path)
+        }),
+    }
+}
+
+fn matches_segment(pattern: &[char], segment: &[char]) -> bool {
+    match pattern.split_first() {
+        None => segment.is_empty(),
+        Some(('*', rest)) => (0..=segment.len()).any(|skipped| matches_segment(rest, &segment[skipped..])),
+        Some(('?', rest)) => !segment.is_empty() && matches_segment(rest, &segment[1..]),
+        Some((c, rest)) => segment.first() == Some(c) && matches_segment(rest, &segment[1..]),
+    }
+}
+
+/// Finds schema files under [schemas_path] recursively: files with names ending with the suffix
+/// of [schemas_config] or its JSON5 and YAML variants, see [SourceFormat::suffixes], whose relative
+/// paths match an `include` glob and no `exclude` glob. Returns the relative paths by file key,
+/// the `/` separated relative path without the suffix, e.g. `nodes/agent`.
+pub fn discover_schema_files(
+    schemas_path: &Path,
+    schemas_config: &SchemasConfig
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let include = schemas_config.include.iter().map(|pattern| Glob::new(pattern)).collect::<Result<Vec<_>, _>>()?;
+    let exclude = schemas_config.exclude.iter().map(|pattern| Glob::new(pattern)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut relative_paths = Vec::new();
+    collect_files(schemas_path, "", &mut relative_paths)?;
+
+    let suffixes = SourceFormat::suffixes(&schemas_config.suffix);
+    let mut files = BTreeMap::new();
+    let mut definition_keys: BTreeMap<String, String> = BTreeMap::new();
+    for relative_path in relative_paths {
+        let Some(file_key) = suffixes.iter().find_map(|(suffix, _)| relative_path.strip_suffix(suffix.as_str())) else {
+            continue;
+        };
+        if !include.iter().any(|glob| glob.matches(&relative_path)) || exclude.iter().any(|glob| glob.matches(&relative_path)) {
+            continue;
+        }
+        // the bundle has one flat `$defs`, two paths must not end up as the same definition
+        if let Some(other) = definition_keys.insert(definition_key(file_key), relative_path.clone()) {
+            return Err(format!(
+                "{other} and {relative_path} are both bundled as definition `{}`, rename one of them",
+                definition_key(file_key)
+            ).into());
+        }
+        files.insert(file_key.to_string(), relative_path.clone());
+    }
+    Ok(files)
+}
+
+/// Appends `/` separated paths of the files under [dir] relative to the scanned root to [files]
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_str()
+            .ok_or_else(|| format!("schema path {} is not valid UTF-8", entry.path().display()))?;
+        let relative_path = format!("{prefix}{file_name}");
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{relative_path}/"), files)?;
+        } else {
+            files.push(relative_path);
+        }
+    }
+    Ok(())
+}
+
+/// Bundled definition key of the root of the schema file [file_key], e.g. `nodes_agent` for `nodes/agent`.
+/// typify reads only the last segment of a `$ref` pointer, so definition keys can not contain `/`.
+pub fn definition_key(file_key: &str) -> String {
+    file_key.replace('/', "_")
+}
+
+/// File key of the schema file a `$ref` [path] of the file [file_key] points to, relative to the
+/// directory of the file, e.g. `common` for `../common.schema.json` in `nodes/agent`. Any format
+/// suffix of [suffix] resolves to the same file key, whatever the format of the file is.
+/// None for paths without such a suffix and paths leaving the schemas directory.
+pub fn resolve_file_ref(file_key: &str, path: &str, suffix: &str) -> Option<String> {
+    if path.is_empty() {
+        return Some(file_key.to_string());
+    }
+    let mut segments = file_key.split('/').collect::<Vec<_>>();
+    segments.pop();
+    let path = SourceFormat::suffixes(suffix).iter().find_map(|(suffix, _)| path.strip_suffix(suffix.as_str()))?;
+    for segment in path.split('/') {
+        match segment {
+            "." | "" => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+/// `$ref` path from the file [file_key] to the file [target_file_key], e.g. `../common.schema.json`
+pub fn relative_file_ref(file_key: &str, target_file_key: &str, suffix: &str) -> String {
+    let dirs = file_key.split('/').collect::<Vec<_>>();
+    let dirs = &dirs[..dirs.len() - 1];
+    let target = target_file_key.split('/').collect::<Vec<_>>();
+    let common = dirs.iter().zip(&target[..target.len() - 1]).take_while(|(a, b)| a == b).count();
+    let up = "../".repeat(dirs.len() - common);
+    let prefix = if up.is_empty() { "./".to_string() } else { up };
+    format!("{prefix}{}{suffix}", target[common..].join("/"))
+}
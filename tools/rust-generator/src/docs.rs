@@ -0,0 +1,219 @@
+use crate::config::{LintLevel, LintsConfig, TypesConfig};
+use crate::definitions::{definition_type_name, serde_rename};
+use crate::discovery::definition_key;
+use crate::lint::{escape_pointer, LintIssue};
+use crate::passes::AstPass;
+use crate::sources::SourceMap;
+use crate::Bundle;
+use heck::ToPascalCase;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use syn::ext::IdentExt;
+use syn::{Attribute, Fields, Item};
+
+/// Rustdoc of a generated type, its fields and variants
+#[derive(Default)]
+struct TypeDocs {
+    doc: Option<String>,
+    /// JSON property name to the doc of the field
+    fields: BTreeMap<String, String>,
+    /// JSON value or type name of the variant to its doc
+    variants: BTreeMap<String, String>,
+}
+
+/// Writes rustdoc built from the `title`, `description`, `default` and `examples` keywords
+/// of the bundled schema on generated types, fields and enum variants
+pub struct SchemaDocs {
+    types: BTreeMap<String, TypeDocs>,
+}
+
+impl SchemaDocs {
+    pub fn new(schema: &Value, types_config: &TypesConfig) -> Self {
+        let mut docs = SchemaDocs { types: BTreeMap::new() };
+        let empty = Map::new();
+        for (def_key, def) in schema.get("$defs").and_then(Value::as_object).unwrap_or(&empty) {
+            docs.collect(&definition_type_name(def_key, types_config), def, types_config);
+        }
+        docs
+    }
+
+    /// Collects docs of [schema] generated as [type_name] and of the types typify names after
+    /// its properties, e.g. `AgentConfigOutputMapping` for property `output_mapping` of `AgentConfig`
+    fn collect(&mut self, type_name: &str, schema: &Value, types_config: &TypesConfig) {
+        let type_docs = self.types.entry(type_name.to_string()).or_default();
+        if type_docs.doc.is_none() {
+            type_docs.doc = doc_text(schema);
+        }
+
+        for keyword in ["oneOf", "anyOf"] {
+            for branch in schema.get(keyword).and_then(Value::as_array).into_iter().flatten() {
+                let Some(doc) = doc_text(branch) else {
+                    continue;
+                };
+                let variant = match (branch.get("const"), ref_key(branch)) {
+                    (Some(Value::String(value)), _) => value.clone(),
+                    (_, Some(def_key)) => definition_type_name(def_key, types_config),
+                    _ => continue,
+                };
+                type_docs.variants.insert(variant, doc);
+            }
+        }
+
+        let mut nested = Vec::new();
+        for (name, property) in schema.get("properties").and_then(Value::as_object).into_iter().flatten() {
+            if let Some(doc) = doc_text(property) {
+                type_docs.fields.insert(name.clone(), doc);
+            }
+            if property.get("properties").is_some() || property.get("oneOf").is_some() || property.get("enum").is_some() {
+                nested.push((format!("{type_name}{}", name.to_pascal_case()), property));
+            }
+        }
+        for (nested_type_name, property) in nested {
+            self.collect(&nested_type_name, property, types_config);
+        }
+    }
+}
+
+impl AstPass for SchemaDocs {
+    fn apply(&self, file: &mut syn::File) -> Result<(), Box<dyn Error>> {
+        for item in &mut file.items {
+            match item {
+                Item::Struct(item) => {
+                    let Some(type_docs) = self.types.get(&item.ident.to_string()) else {
+                        continue;
+                    };
+                    set_doc(&mut item.attrs, type_docs.doc.as_deref());
+                    set_field_docs(&mut item.fields, type_docs);
+                }
+                Item::Enum(item) => {
+                    let Some(type_docs) = self.types.get(&item.ident.to_string()) else {
+                        continue;
+                    };
+                    set_doc(&mut item.attrs, type_docs.doc.as_deref());
+                    for variant in &mut item.variants {
+                        let doc = type_docs.variants.get(&json_name(&variant.attrs, &variant.ident));
+                        set_doc(&mut variant.attrs, doc.map(String::as_str));
+                    }
+                }
+                Item::Type(item) => {
+                    let doc = self.types.get(&item.ident.to_string()).and_then(|type_docs| type_docs.doc.as_deref());
+                    set_doc(&mut item.attrs, doc);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keys of the bundled definitions of [bundle] that have no `description`. Definitions that only
+/// hold `$defs`, e.g. the root of `common`, generate no type of their own and are left out.
+pub fn undocumented_definitions(bundle: &Bundle) -> Vec<String> {
+    let empty = Map::new();
+    bundle.schema.get("$defs").and_then(Value::as_object).unwrap_or(&empty)
+        .iter()
+        .filter(|(_, def)| def.get("description").and_then(Value::as_str).is_none_or(|description| description.trim().is_empty()))
+        .filter(|(def_key, def)| !is_defs_container(bundle, def_key, def))
+        .map(|(def_key, _)| def_key.clone())
+        .collect()
+}
+
+/// Whether [def] only held `$defs`, they are hoisted out of it. Definitions of a pre-bundled schema
+/// come from the root before them, the others are located inside the definition.
+fn is_defs_container(bundle: &Bundle, def_key: &str, def: &Value) -> bool {
+    let only_meta = def.as_object().is_some_and(|def| def.keys().all(|keyword| ["$schema", "$id", "$comment"].contains(&keyword.as_str())));
+    let holds = |other_key: &String| match (bundle.locations.get(def_key), bundle.locations.get(other_key)) {
+        (Some((file_key, pointer)), Some((other_file_key, other_pointer))) =>
+            file_key == other_file_key && other_pointer.starts_with(&format!("{pointer}/")),
+        _ => false,
+    };
+    only_meta && bundle.sources.iter().any(|(other_key, source)| {
+        other_key != def_key && (definition_key(source) == def_key || holds(other_key))
+    })
+}
+
+/// Issues of the `missing_description` lint for [undocumented_definitions] of [bundle], located
+/// in the loaded files of [source_map]. None if the lint is allowed in [lints_config].
+pub fn lint_missing_descriptions(bundle: &Bundle, source_map: &SourceMap, lints_config: &LintsConfig) -> Vec<LintIssue> {
+    if lints_config.missing_description == LintLevel::Allow {
+        return Vec::new();
+    }
+    undocumented_definitions(bundle).into_iter()
+        .map(|def_key| {
+            let (file, pointer, position) = match bundle.locations.get(&def_key) {
+                Some((file_key, pointer)) => (source_map.path(file_key), pointer.clone(), source_map.position(file_key, pointer)),
+                None => ("bundled.schema.json".to_string(), format!("/$defs/{}", escape_pointer(&def_key)), None),
+            };
+            LintIssue {
+                lint: "missing_description",
+                level: lints_config.missing_description,
+                file,
+                pointer,
+                position,
+                message: format!("definition `{def_key}` has no description, its type is generated without docs"),
+            }
+        })
+        .collect()
+}
+
+/// Rustdoc of [schema]: the title, the description, the default value
+/// and the examples as JSON code blocks
+fn doc_text(schema: &Value) -> Option<String> {
+    let mut paragraphs = Vec::new();
+    for keyword in ["title", "description"] {
+        if let Some(text) = schema.get(keyword).and_then(Value::as_str).map(str::trim) && !text.is_empty() {
+            paragraphs.push(text.to_string());
+        }
+    }
+    if let Some(default) = schema.get("default") {
+        paragraphs.push(format!("Default: `{default}`"));
+    }
+    if let Some(examples) = schema.get("examples").and_then(Value::as_array) && !examples.is_empty() {
+        paragraphs.push("# Examples".to_string());
+        for example in examples {
+            let json = serde_json::to_string_pretty(example).unwrap_or_default();
+            paragraphs.push(format!("```json\n{json}\n```"));
+        }
+    }
+    if paragraphs.is_empty() {
+        None
+    } else {
+        Some(paragraphs.join("\n\n"))
+    }
+}
+
+fn set_field_docs(fields: &mut Fields, type_docs: &TypeDocs) {
+    for field in fields {
+        let Some(ident) = field.ident.clone() else {
+            set_doc(&mut field.attrs, None);
+            continue;
+        };
+        let doc = type_docs.fields.get(&json_name(&field.attrs, &ident));
+        set_doc(&mut field.attrs, doc.map(String::as_str));
+    }
+}
+
+/// Replaces doc attributes of [attrs] with [doc], one `///` line per line of text
+fn set_doc(attrs: &mut Vec<Attribute>, doc: Option<&str>) {
+    attrs.retain(|attr| !attr.path().is_ident("doc"));
+    let Some(doc) = doc else {
+        return;
+    };
+    let doc_attrs = doc.lines().map(|line| {
+        let line = if line.is_empty() { String::new() } else { format!(" {line}") };
+        let attr: Attribute = syn::parse_quote!(#[doc = #line]);
+        attr
+    });
+    attrs.splice(0..0, doc_attrs);
+}
+
+/// Name of a field or variant in JSON: its `#[serde(rename)]` or its identifier
+fn json_name(attrs: &[Attribute], ident: &syn::Ident) -> String {
+    serde_rename(attrs).unwrap_or_else(|| ident.unraw().to_string())
+}
+
+/// Definition key of a `#/$defs/...` reference
+fn ref_key(schema: &Value) -> Option<&str> {
+    schema.get("$ref").and_then(Value::as_str).and_then(|reference| reference.strip_prefix("#/$defs/"))
+}
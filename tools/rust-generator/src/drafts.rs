@@ -0,0 +1,390 @@
+use crate::discovery::{relative_file_ref, resolve_file_ref};
+use crate::lint::escape_pointer;
+use crate::sources::SourceMap;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// `$schema` of the bundle and of lowered files
+pub const DRAFT_07_SCHEMA: &str = "http://json-schema.org/draft-07/schema";
+
+/// JSON Schema draft of a schema file, selected by its `$schema`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Draft {
+    Draft07,
+    Draft201909,
+    Draft202012,
+}
+
+impl Draft {
+    pub const ALL: [Draft; 3] = [Draft::Draft07, Draft::Draft201909, Draft::Draft202012];
+
+    /// `$schema` URI of the draft without the scheme and the trailing `#`
+    pub fn uri(self) -> &'static str {
+        match self {
+            Draft::Draft07 => "json-schema.org/draft-07/schema",
+            Draft::Draft201909 => "json-schema.org/draft/2019-09/schema",
+            Draft::Draft202012 => "json-schema.org/draft/2020-12/schema",
+        }
+    }
+
+    /// Draft declared by `$schema` of [schema], draft-07 for files without `$schema`
+    pub fn of(schema: &Value) -> Result<Draft, String> {
+        let Some(uri) = schema.get("$schema") else {
+            return Ok(Draft::Draft07);
+        };
+        let uri = uri.as_str().ok_or("`$schema` is not a string")?;
+        let normalized = uri.strip_prefix("https://").or_else(|| uri.strip_prefix("http://")).unwrap_or(uri).trim_end_matches('#');
+        Draft::ALL.into_iter()
+            .find(|draft| draft.uri() == normalized)
+            .ok_or_else(|| {
+                let supported = Draft::ALL.map(Draft::uri).join(", ");
+                format!("meta-schema `{uri}` is not supported, supported are: {supported}")
+            })
+    }
+}
+
+/// Keywords whose values are instances, not schemas
+const INSTANCE_KEYWORDS: [&str; 4] = ["const", "enum", "default", "examples"];
+
+/// Lowers 2019-09 and 2020-12 files of [schemas] by file key to draft-07, the draft the bundler
+/// and typify understand. [source_map] locates errors. Draft-07 files are returned unchanged.
+///
+/// - `prefixItems` and `items` become `items` and `additionalItems`
+/// - `dependentRequired` and `dependentSchemas` become `dependencies`
+/// - `unevaluatedProperties` and `unevaluatedItems` become `additionalProperties` and `additionalItems`
+///   where no other subschema evaluates properties or items
+/// - `$ref`s to `$anchor`s, `$dynamicRef`s and `$recursiveRef`s become JSON pointer `$ref`s
+///
+/// Keywords without a draft-07 equivalent are errors with the file and the pointer of the keyword.
+pub fn lower_schemas(
+    schemas: &BTreeMap<String, Value>,
+    source_map: &SourceMap
+) -> Result<BTreeMap<String, Value>, Box<dyn Error>> {
+    let mut anchors = Anchors::default();
+    let mut drafts = BTreeMap::new();
+    for (file_key, schema) in schemas {
+        let draft = Draft::of(schema).map_err(|err| format!("{}: {err}", source_map.locate(file_key, "/$schema")))?;
+        if draft != Draft::Draft07 {
+            anchors.collect(file_key, schema, "", draft);
+        }
+        drafts.insert(file_key, draft);
+    }
+
+    let mut lowered = BTreeMap::new();
+    for (file_key, schema) in schemas {
+        let draft = drafts[file_key];
+        if draft == Draft::Draft07 {
+            lowered.insert(file_key.clone(), schema.clone());
+            continue;
+        }
+        let lowering = Lowering { file_key, source_map, draft, anchors: &anchors };
+        let mut schema = lowering.lower(schema, "")
+            .map_err(|(pointer, message)| format!("{}: {message}", source_map.locate(file_key, &pointer)))?;
+        if let Value::Object(map) = &mut schema {
+            map.insert("$schema".to_string(), json!(DRAFT_07_SCHEMA));
+        }
+        lowered.insert(file_key.clone(), schema);
+    }
+    Ok(lowered)
+}
+
+/// Locations of anchors in 2019-09 and 2020-12 files, as (file key, pointer)
+#[derive(Default)]
+struct Anchors {
+    /// `$anchor` and `$dynamicAnchor` by file key and name
+    anchors: BTreeMap<(String, String), String>,
+    /// `$dynamicAnchor` by name
+    dynamic: BTreeMap<String, Vec<(String, String)>>,
+    /// Files with `$recursiveAnchor: true` on the root
+    recursive: Vec<String>,
+}
+
+impl Anchors {
+    fn collect(&mut self, file_key: &str, schema: &Value, pointer: &str, draft: Draft) {
+        let Value::Object(map) = schema else {
+            return;
+        };
+        if let Some(Value::String(name)) = map.get("$anchor") {
+            self.anchors.insert((file_key.to_string(), name.clone()), pointer.to_string());
+        }
+        if draft == Draft::Draft202012 && let Some(Value::String(name)) = map.get("$dynamicAnchor") {
+            self.anchors.insert((file_key.to_string(), name.clone()), pointer.to_string());
+            self.dynamic.entry(name.clone()).or_default().push((file_key.to_string(), pointer.to_string()));
+        }
+        if draft == Draft::Draft201909 && pointer.is_empty() && map.get("$recursiveAnchor") == Some(&Value::Bool(true)) {
+            self.recursive.push(file_key.to_string());
+        }
+        for_each_subschema(map, pointer, |subschema, subschema_pointer| {
+            self.collect(file_key, subschema, &subschema_pointer, draft);
+        });
+    }
+}
+
+/// Calls [f] with each subschema of schema object [map] at [pointer] and the pointer of the subschema
+fn for_each_subschema(map: &Map<String, Value>, pointer: &str, mut f: impl FnMut(&Value, String)) {
+    for (keyword, value) in map {
+        if INSTANCE_KEYWORDS.contains(&keyword.as_str()) {
+            continue;
+        }
+        let keyword_pointer = format!("{pointer}/{}", escape_pointer(keyword));
+        match (keyword.as_str(), value) {
+            ("properties" | "patternProperties" | "$defs" | "definitions" | "dependentSchemas" | "dependencies", Value::Object(subschemas)) => {
+                for (name, subschema) in subschemas {
+                    f(subschema, format!("{keyword_pointer}/{}", escape_pointer(name)));
+                }
+            }
+            ("allOf" | "anyOf" | "oneOf" | "prefixItems" | "items", Value::Array(subschemas)) => {
+                for (index, subschema) in subschemas.iter().enumerate() {
+                    f(subschema, format!("{keyword_pointer}/{index}"));
+                }
+            }
+            (
+                "items" | "additionalItems" | "contains" | "additionalProperties" | "propertyNames" | "if" | "then" | "else"
+                | "not" | "unevaluatedItems" | "unevaluatedProperties" | "contentSchema",
+                _
+            ) => f(value, keyword_pointer),
+            _ => {}
+        }
+    }
+}
+
+/// Lowering of one 2019-09 or 2020-12 file, errors are (pointer, message)
+struct Lowering<'a> {
+    file_key: &'a str,
+    source_map: &'a SourceMap,
+    draft: Draft,
+    anchors: &'a Anchors,
+}
+
+impl Lowering<'_> {
+    fn lower(&self, schema: &Value, pointer: &str) -> Result<Value, (String, String)> {
+        let Value::Object(map) = schema else {
+            return Ok(schema.clone());
+        };
+
+        // subschemas first, the keywords of this schema are lowered below
+        let mut lowered = map.clone();
+        let mut result = Ok(());
+        for_each_subschema(map, pointer, |subschema, subschema_pointer| {
+            if result.is_err() {
+                return;
+            }
+            match self.lower(subschema, &subschema_pointer) {
+                Ok(subschema) => {
+                    let relative = subschema_pointer[pointer.len()..].to_string();
+                    result = set_at(&mut lowered, &relative, subschema).map_err(|message| (subschema_pointer, message));
+                }
+                Err(err) => result = Err(err),
+            }
+        });
+        result?;
+        let mut map = lowered;
+
+        let keyword_pointer = |keyword: &str| format!("{pointer}/{}", escape_pointer(keyword));
+        for keyword in ["minContains", "maxContains"] {
+            if map.contains_key(keyword) {
+                return Err((keyword_pointer(keyword), format!("`{keyword}` has no draft-07 equivalent")));
+            }
+        }
+
+        self.lower_refs(&mut map, pointer)?;
+        if self.draft == Draft::Draft202012 {
+            lower_prefix_items(&mut map, &keyword_pointer)?;
+        }
+        lower_dependencies(&mut map);
+        lower_unevaluated(&mut map, "unevaluatedProperties", "additionalProperties", &keyword_pointer)?;
+        lower_unevaluated(&mut map, "unevaluatedItems", "additionalItems", &keyword_pointer)?;
+        Ok(Value::Object(map))
+    }
+
+    /// Replaces anchor `$ref`s, `$dynamicRef`s and `$recursiveRef`s with JSON pointer `$ref`s
+    fn lower_refs(&self, map: &mut Map<String, Value>, pointer: &str) -> Result<(), (String, String)> {
+        let keyword_pointer = |keyword: &str| format!("{pointer}/{}", escape_pointer(keyword));
+        let mut references = Vec::new();
+        if let Some(Value::String(reference)) = map.get("$ref") {
+            let reference = self.resolve_anchor(reference).map_err(|message| (keyword_pointer("$ref"), message))?;
+            references.push(reference);
+        }
+        if let Some(Value::String(reference)) = map.remove("$dynamicRef") {
+            let reference = self.resolve_dynamic(&reference).map_err(|message| (keyword_pointer("$dynamicRef"), message))?;
+            references.push(reference);
+        }
+        if let Some(Value::String(reference)) = map.remove("$recursiveRef") {
+            if self.anchors.recursive.len() > 1 && self.anchors.recursive.iter().any(|file_key| file_key == self.file_key) {
+                let files = self.anchors.recursive.iter().map(|file_key| self.source_map.path(file_key)).collect::<Vec<_>>();
+                let message = format!("`$recursiveRef` can not be lowered, `$recursiveAnchor` is set in several files: {}", files.join(", "));
+                return Err((keyword_pointer("$recursiveRef"), message));
+            }
+            references.push(reference);
+        }
+        match references.len() {
+            0 => {}
+            1 => {
+                map.insert("$ref".to_string(), json!(references.remove(0)));
+            }
+            _ => {
+                // draft-07 ignores keywords next to `$ref`, both references have to apply
+                map.remove("$ref");
+                let all_of = references.into_iter().map(|reference| json!({ "$ref": reference }));
+                let existing = map.remove("allOf").and_then(|all_of| all_of.as_array().cloned()).unwrap_or_default();
+                map.insert("allOf".to_string(), Value::Array(existing.into_iter().chain(all_of).collect()));
+            }
+        }
+        map.remove("$anchor");
+        map.remove("$dynamicAnchor");
+        map.remove("$recursiveAnchor");
+        Ok(())
+    }
+
+    /// JSON pointer form of [reference] if it points to a named anchor, e.g. `#node` or `./graph.schema.json#node`
+    fn resolve_anchor(&self, reference: &str) -> Result<String, String> {
+        let Some((path, name)) = reference.split_once('#') else {
+            return Ok(reference.to_string());
+        };
+        if name.is_empty() || name.starts_with('/') {
+            return Ok(reference.to_string());
+        }
+        let suffix = self.source_map.suffix();
+        let target_file = resolve_file_ref(self.file_key, path, suffix)
+            .ok_or_else(|| format!("`{reference}` does not point to a `{suffix}` file in the schemas directory"))?;
+        let anchor_pointer = self.anchors.anchors.get(&(target_file.clone(), name.to_string()))
+            .ok_or_else(|| format!("there is no anchor `{name}` in {}", self.source_map.path(&target_file)))?;
+        self.pointer_reference(path, &target_file, anchor_pointer, &format!("anchor `{name}`"))
+    }
+
+    /// Static form of a 2020-12 `$dynamicRef`, possible while one `$dynamicAnchor` has its name
+    fn resolve_dynamic(&self, reference: &str) -> Result<String, String> {
+        let name = reference.split_once('#').map(|(_, name)| name).unwrap_or_default();
+        match self.anchors.dynamic.get(name).map(Vec::as_slice) {
+            None | Some([]) => self.resolve_anchor(reference),
+            Some([(file_key, anchor_pointer)]) => {
+                let path = if file_key == self.file_key { String::new() } else { relative_file_ref(self.file_key, file_key, self.source_map.suffix()) };
+                self.pointer_reference(&path, file_key, anchor_pointer, &format!("`$dynamicAnchor` `{name}`"))
+            }
+            Some(locations) => {
+                let locations = locations.iter()
+                    .map(|(file_key, pointer)| format!("{}#{pointer}", self.source_map.path(file_key)))
+                    .collect::<Vec<_>>();
+                Err(format!(
+                    "`$dynamicRef` `{reference}` can not be lowered, `$dynamicAnchor` `{name}` is declared in several places: {}",
+                    locations.join(", ")
+                ))
+            }
+        }
+    }
+
+    /// `$ref` to [anchor_pointer] of [target_file] written with [path], only file roots
+    /// and definitions can be bundled
+    fn pointer_reference(&self, path: &str, target_file: &str, anchor_pointer: &str, anchor: &str) -> Result<String, String> {
+        let is_definition = ["/$defs/", "/definitions/"].iter()
+            .any(|prefix| anchor_pointer.strip_prefix(prefix).is_some_and(|name| !name.contains('/')));
+        if anchor_pointer.is_empty() || is_definition {
+            Ok(format!("{path}#{anchor_pointer}"))
+        } else {
+            Err(format!(
+                "{anchor} is at {}#{anchor_pointer}, only anchors on a file root or on a definition can be bundled",
+                self.source_map.path(target_file)
+            ))
+        }
+    }
+}
+
+/// `prefixItems: [A], items: B` is `items: [A], additionalItems: B` in draft-07. typify generates
+/// tuples only for arrays of a fixed length, other arrays with `prefixItems` are errors.
+fn lower_prefix_items(map: &mut Map<String, Value>, keyword_pointer: &dyn Fn(&str) -> String) -> Result<(), (String, String)> {
+    let Some(prefix_items) = map.remove("prefixItems") else {
+        return Ok(());
+    };
+    let min_items = map.get("minItems").and_then(Value::as_u64);
+    let max_items = map.get("maxItems").and_then(Value::as_u64);
+    if min_items.is_none() || min_items != max_items || min_items == Some(0) {
+        let message = "`prefixItems` is generated as a tuple, it needs equal non-zero `minItems` and `maxItems`".to_string();
+        return Err((keyword_pointer("prefixItems"), message));
+    }
+    if let Some(items) = map.remove("items") {
+        map.insert("additionalItems".to_string(), items);
+    }
+    map.insert("items".to_string(), prefix_items);
+    Ok(())
+}
+
+/// `dependentRequired` and `dependentSchemas` are the two forms of draft-07 `dependencies`
+fn lower_dependencies(map: &mut Map<String, Value>) {
+    let dependent_required = map.remove("dependentRequired");
+    let dependent_schemas = map.remove("dependentSchemas");
+    if dependent_required.is_none() && dependent_schemas.is_none() {
+        return;
+    }
+    let mut dependencies = match map.remove("dependencies") {
+        Some(Value::Object(dependencies)) => dependencies,
+        _ => Map::new(),
+    };
+    for dependent in [dependent_schemas, dependent_required].into_iter().flatten() {
+        let Value::Object(dependent) = dependent else {
+            continue;
+        };
+        for (property, dependency) in dependent {
+            let dependency = match dependencies.remove(&property) {
+                // a property with both forms needs both
+                Some(existing) => {
+                    let as_schema = |dependency: Value| match dependency {
+                        Value::Array(required) => json!({ "required": required }),
+                        schema => schema,
+                    };
+                    json!({ "allOf": [as_schema(existing), as_schema(dependency)] })
+                }
+                None => dependency,
+            };
+            dependencies.insert(property, dependency);
+        }
+    }
+    map.insert("dependencies".to_string(), Value::Object(dependencies));
+}
+
+/// Lowers [unevaluated] to [additional] where the schema has no other subschemas that could
+/// evaluate properties or items, only then the two keywords are the same
+fn lower_unevaluated(
+    map: &mut Map<String, Value>,
+    unevaluated: &str,
+    additional: &str,
+    keyword_pointer: &dyn Fn(&str) -> String
+) -> Result<(), (String, String)> {
+    let Some(value) = map.remove(unevaluated) else {
+        return Ok(());
+    };
+    // everything is evaluated by `additionalProperties`, or by a single `items` schema
+    let all_evaluated = map.contains_key(additional) || (additional == "additionalItems" && map.get("items").is_some_and(Value::is_object));
+    if all_evaluated {
+        return Ok(());
+    }
+    let applicators = ["allOf", "anyOf", "oneOf", "if", "then", "else", "$ref", "dependencies"];
+    if let Some(applicator) = applicators.iter().find(|applicator| map.contains_key(**applicator)) {
+        let message = format!(
+            "`{unevaluated}` can not be lowered to draft-07 `{additional}`, it also sees what `{applicator}` evaluates"
+        );
+        return Err((keyword_pointer(unevaluated), message));
+    }
+    if additional == "additionalItems" && !map.contains_key("items") {
+        map.insert("items".to_string(), value);
+    } else {
+        map.insert(additional.to_string(), value);
+    }
+    Ok(())
+}
+
+/// Replaces the value at relative [pointer] of [map] with [value]
+fn set_at(map: &mut Map<String, Value>, pointer: &str, value: Value) -> Result<(), String> {
+    let mut tokens = pointer.split('/').skip(1).map(|token| token.replace("~1", "/").replace("~0", "~"));
+    let first = tokens.next().ok_or("empty pointer")?;
+    let mut target = map.get_mut(&first).ok_or("missing subschema")?;
+    for token in tokens {
+        target = match target {
+            Value::Object(map) => map.get_mut(&token),
+            Value::Array(values) => token.parse::<usize>().ok().and_then(|index| values.get_mut(index)),
+            _ => None,
+        }.ok_or("missing subschema")?;
+    }
+    *target = value;
+    Ok(())
+}
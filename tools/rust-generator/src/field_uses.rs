@@ -0,0 +1,430 @@
+use std::collections::{BTreeMap, BTreeSet};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::visit_mut::VisitMut;
+use syn::{Expr, Fields, Item, Member, Pat, Token, Type};
+
+/// Fields of generated types that hand-written code, the runtime modules and the crate tests, reads.
+/// Types of values are followed through typed bindings, variant and struct patterns, `self` and
+/// field accesses. A field read on a value of unknown type may be a field of any generated type.
+#[derive(Debug, Default)]
+pub struct FieldReads {
+    /// Type name to the fields read on its values
+    by_type: BTreeMap<String, BTreeSet<String>>,
+    /// Fields read on values of unknown type
+    unknown: BTreeSet<String>,
+}
+
+impl FieldReads {
+    /// Collects field reads of [sources] on the types generated in [file],
+    /// [aliases] maps names the sources may use, e.g. of renamed types, to generated type names
+    pub fn collect(sources: &[&str], file: &syn::File, aliases: &BTreeMap<String, String>) -> Result<Self, syn::Error> {
+        let types = GeneratedTypes::new(file, aliases);
+        let mut collector = ReadCollector { types: &types, scopes: Vec::new(), self_type: ValueType::Unknown, reads: FieldReads::default() };
+        for source in sources {
+            collector.visit_file_mut(&mut syn::parse_file(source)?);
+        }
+        Ok(collector.reads)
+    }
+
+    /// Whether hand-written code may read [field] of [type_name]
+    pub fn reads(&self, type_name: &str, field: &str) -> bool {
+        self.unknown.contains(field) || self.by_type.get(type_name).is_some_and(|fields| fields.contains(field))
+    }
+}
+
+/// Renames fields in struct expressions and struct patterns of Rust [source] that name the type,
+/// e.g. `End { type_: ... }`, or `Self` in its impls. [renames] maps type names to old to new field names.
+/// Field reads are left as they are, fields in [FieldReads] are not renamed.
+pub fn rename_struct_fields(source: &str, renames: &BTreeMap<String, BTreeMap<String, String>>) -> Result<String, syn::Error> {
+    if renames.is_empty() {
+        return Ok(source.to_string());
+    }
+    let mut renamer = StructFieldRenamer { renames, self_type: None, edits: Vec::new() };
+    renamer.visit_file_mut(&mut syn::parse_file(source)?);
+
+    let line_starts = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+        .collect::<Vec<_>>();
+    let mut edits = renamer.edits.into_iter()
+        .map(|(position, len, replacement)| {
+            // columns count chars from the start of the line
+            let line_start = line_starts[position.line - 1];
+            let offset = source[line_start..].char_indices().nth(position.column).map_or(source.len(), |(index, _)| line_start + index);
+            (offset, len, replacement)
+        })
+        .collect::<Vec<_>>();
+    edits.sort_by_key(|(offset, _, _)| *offset);
+
+    let mut renamed = source.to_string();
+    for (offset, len, replacement) in edits.into_iter().rev() {
+        renamed.replace_range(offset..offset + len, &replacement);
+    }
+    Ok(renamed)
+}
+
+/// Type of a value in hand-written code
+#[derive(Clone, Debug, PartialEq)]
+enum ValueType {
+    Generated(String),
+    /// Any other type, e.g. `PipelineDiff` or `Vec<Node>`
+    Other,
+    Unknown,
+}
+
+/// Field types of generated structs and types wrapped by variants of generated enums
+struct GeneratedTypes<'a> {
+    /// Struct name to field name to the field type
+    fields: BTreeMap<String, BTreeMap<String, Type>>,
+    /// Enum name to variant name to the name of the wrapped type
+    variants: BTreeMap<String, BTreeMap<String, String>>,
+    aliases: &'a BTreeMap<String, String>,
+}
+
+impl<'a> GeneratedTypes<'a> {
+    fn new(file: &syn::File, aliases: &'a BTreeMap<String, String>) -> Self {
+        let mut fields = BTreeMap::new();
+        let mut variants = BTreeMap::new();
+        for item in &file.items {
+            match item {
+                Item::Struct(item) => {
+                    let Fields::Named(named) = &item.fields else {
+                        fields.insert(item.ident.to_string(), BTreeMap::new());
+                        continue;
+                    };
+                    let struct_fields = named.named.iter()
+                        .filter_map(|field| Some((field.ident.as_ref()?.to_string(), field.ty.clone())))
+                        .collect();
+                    fields.insert(item.ident.to_string(), struct_fields);
+                }
+                Item::Enum(item) => {
+                    let enum_variants = item.variants.iter()
+                        .filter_map(|variant| match &variant.fields {
+                            Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+                                Some((variant.ident.to_string(), path_type_name(&unnamed.unnamed[0].ty)?))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    variants.insert(item.ident.to_string(), enum_variants);
+                }
+                _ => {}
+            }
+        }
+        GeneratedTypes { fields, variants, aliases }
+    }
+
+    /// Generated type [name] stands for
+    fn resolve(&self, name: &str) -> Option<String> {
+        let name = self.aliases.get(name).map(String::as_str).unwrap_or(name);
+        (self.fields.contains_key(name) || self.variants.contains_key(name)).then(|| name.to_string())
+    }
+
+    fn value_type(&self, ty: &Type, self_type: &ValueType) -> ValueType {
+        match ty {
+            Type::Reference(reference) => self.value_type(&reference.elem, self_type),
+            Type::Paren(paren) => self.value_type(&paren.elem, self_type),
+            Type::Path(path) if path.qself.is_none() && path.path.is_ident("Self") => self_type.clone(),
+            _ => match path_type_name(ty).and_then(|name| self.resolve(&name)) {
+                Some(name) => ValueType::Generated(name),
+                None => ValueType::Other,
+            },
+        }
+    }
+
+    fn field_type(&self, type_name: &str, field: &str) -> ValueType {
+        match self.fields.get(type_name).and_then(|fields| fields.get(field)) {
+            Some(ty) => self.value_type(ty, &ValueType::Other),
+            None => ValueType::Unknown,
+        }
+    }
+
+    /// Type wrapped by the variant [path], e.g. `Node::Agent`
+    fn variant_type(&self, path: &syn::Path, self_type: &ValueType) -> ValueType {
+        let segments = path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>();
+        let [.., enum_name, variant] = segments.as_slice() else {
+            return ValueType::Unknown;
+        };
+        let enum_name = match (enum_name.as_str(), self_type) {
+            ("Self", ValueType::Generated(self_name)) => Some(self_name.clone()),
+            _ => self.resolve(enum_name),
+        };
+        enum_name.and_then(|enum_name| self.variants.get(&enum_name)?.get(variant).cloned())
+            .map_or(ValueType::Unknown, ValueType::Generated)
+    }
+}
+
+/// Name of a type without generic arguments, e.g. `Agent` of `crate::types::Agent`
+fn path_type_name(ty: &Type) -> Option<String> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    segment.arguments.is_none().then(|| segment.ident.to_string())
+}
+
+struct ReadCollector<'a> {
+    types: &'a GeneratedTypes<'a>,
+    /// Types of the bindings of the enclosing blocks, closures and arms
+    scopes: Vec<BTreeMap<String, ValueType>>,
+    self_type: ValueType,
+    reads: FieldReads,
+}
+
+impl ReadCollector<'_> {
+    fn in_scope(&mut self, visit: impl FnOnce(&mut Self)) {
+        self.scopes.push(BTreeMap::new());
+        visit(self);
+        self.scopes.pop();
+    }
+
+    fn bind(&mut self, name: String, value_type: ValueType) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, value_type);
+        }
+    }
+
+    /// Binds the identifiers of [pat] matching a value of [value_type]
+    fn bind_pat(&mut self, pat: &Pat, value_type: ValueType) {
+        match pat {
+            Pat::Ident(pat) => {
+                self.bind(pat.ident.to_string(), value_type.clone());
+                if let Some((_, subpat)) = &pat.subpat {
+                    self.bind_pat(subpat, value_type);
+                }
+            }
+            Pat::Type(pat) => {
+                let value_type = self.types.value_type(&pat.ty, &self.self_type);
+                self.bind_pat(&pat.pat, value_type);
+            }
+            Pat::Reference(pat) => self.bind_pat(&pat.pat, value_type),
+            Pat::Paren(pat) => self.bind_pat(&pat.pat, value_type),
+            Pat::TupleStruct(pat) => {
+                let variant_type = match pat.elems.len() {
+                    1 => self.types.variant_type(&pat.path, &self.self_type),
+                    _ => ValueType::Unknown,
+                };
+                pat.elems.iter().for_each(|elem| self.bind_pat(elem, variant_type.clone()));
+            }
+            Pat::Struct(pat) => {
+                let struct_type = match pat.path.is_ident("Self") {
+                    true => self.self_type.clone(),
+                    false => pat.path.segments.last()
+                        .and_then(|segment| self.types.resolve(&segment.ident.to_string()))
+                        .map_or(ValueType::Unknown, ValueType::Generated),
+                };
+                for field in &pat.fields {
+                    let field_type = match (&struct_type, &field.member) {
+                        (ValueType::Generated(type_name), Member::Named(ident)) => self.types.field_type(type_name, &ident.to_string()),
+                        _ => ValueType::Unknown,
+                    };
+                    self.bind_pat(&field.pat, field_type);
+                }
+            }
+            Pat::Tuple(pat) => pat.elems.iter().for_each(|elem| self.bind_pat(elem, ValueType::Unknown)),
+            Pat::Slice(pat) => pat.elems.iter().for_each(|elem| self.bind_pat(elem, ValueType::Unknown)),
+            Pat::Or(pat) => pat.cases.iter().for_each(|case| self.bind_pat(case, value_type.clone())),
+            _ => {}
+        }
+    }
+
+    fn lookup(&self, name: &str) -> ValueType {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).cloned()).unwrap_or(ValueType::Unknown)
+    }
+
+    fn expr_type(&self, expr: &Expr) -> ValueType {
+        match expr {
+            Expr::Path(path) if path.qself.is_none() => match path.path.get_ident() {
+                Some(ident) => self.lookup(&ident.to_string()),
+                None => ValueType::Unknown,
+            },
+            Expr::Field(field) => match (self.expr_type(&field.base), &field.member) {
+                (ValueType::Generated(type_name), Member::Named(ident)) => self.types.field_type(&type_name, &ident.to_string()),
+                _ => ValueType::Unknown,
+            },
+            Expr::Reference(reference) => self.expr_type(&reference.expr),
+            Expr::Paren(paren) => self.expr_type(&paren.expr),
+            Expr::Unary(unary) if matches!(unary.op, syn::UnOp::Deref(_)) => self.expr_type(&unary.expr),
+            _ => ValueType::Unknown,
+        }
+    }
+}
+
+impl VisitMut for ReadCollector<'_> {
+    fn visit_item_impl_mut(&mut self, item: &mut syn::ItemImpl) {
+        let self_type = std::mem::replace(&mut self.self_type, ValueType::Unknown);
+        self.self_type = self.types.value_type(&item.self_ty, &ValueType::Other);
+        syn::visit_mut::visit_item_impl_mut(self, item);
+        self.self_type = self_type;
+    }
+
+    fn visit_signature_mut(&mut self, signature: &mut syn::Signature) {
+        for input in &signature.inputs {
+            match input {
+                syn::FnArg::Receiver(_) => self.bind("self".to_string(), self.self_type.clone()),
+                syn::FnArg::Typed(pat) => self.bind_pat(&pat.pat, self.types.value_type(&pat.ty, &self.self_type)),
+            }
+        }
+    }
+
+    fn visit_item_fn_mut(&mut self, item: &mut syn::ItemFn) {
+        self.in_scope(|collector| syn::visit_mut::visit_item_fn_mut(collector, item));
+    }
+
+    fn visit_impl_item_fn_mut(&mut self, item: &mut syn::ImplItemFn) {
+        self.in_scope(|collector| syn::visit_mut::visit_impl_item_fn_mut(collector, item));
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        self.in_scope(|collector| syn::visit_mut::visit_block_mut(collector, block));
+    }
+
+    fn visit_local_mut(&mut self, local: &mut syn::Local) {
+        // bindings are visible after the initializer
+        let mut value_type = ValueType::Unknown;
+        if let Some(init) = &mut local.init {
+            self.visit_expr_mut(&mut init.expr);
+            value_type = self.expr_type(&init.expr);
+            if let Some((_, diverge)) = &mut init.diverge {
+                self.visit_expr_mut(diverge);
+            }
+        }
+        self.bind_pat(&local.pat, value_type);
+    }
+
+    fn visit_expr_let_mut(&mut self, expr: &mut syn::ExprLet) {
+        self.visit_expr_mut(&mut expr.expr);
+        let value_type = self.expr_type(&expr.expr);
+        self.bind_pat(&expr.pat, value_type);
+    }
+
+    fn visit_expr_if_mut(&mut self, expr: &mut syn::ExprIf) {
+        // `if let` bindings are visible in the then branch only
+        self.in_scope(|collector| {
+            collector.visit_expr_mut(&mut expr.cond);
+            collector.visit_block_mut(&mut expr.then_branch);
+        });
+        if let Some((_, else_branch)) = &mut expr.else_branch {
+            self.visit_expr_mut(else_branch);
+        }
+    }
+
+    fn visit_expr_while_mut(&mut self, expr: &mut syn::ExprWhile) {
+        self.in_scope(|collector| {
+            collector.visit_expr_mut(&mut expr.cond);
+            collector.visit_block_mut(&mut expr.body);
+        });
+    }
+
+    fn visit_expr_for_loop_mut(&mut self, expr: &mut syn::ExprForLoop) {
+        self.visit_expr_mut(&mut expr.expr);
+        self.in_scope(|collector| {
+            collector.bind_pat(&expr.pat, ValueType::Unknown);
+            collector.visit_block_mut(&mut expr.body);
+        });
+    }
+
+    fn visit_expr_closure_mut(&mut self, expr: &mut syn::ExprClosure) {
+        self.in_scope(|collector| {
+            for input in &expr.inputs {
+                collector.bind_pat(input, ValueType::Unknown);
+            }
+            collector.visit_expr_mut(&mut expr.body);
+        });
+    }
+
+    fn visit_arm_mut(&mut self, arm: &mut syn::Arm) {
+        self.in_scope(|collector| {
+            collector.bind_pat(&arm.pat, ValueType::Unknown);
+            if let Some((_, guard)) = &mut arm.guard {
+                collector.visit_expr_mut(guard);
+            }
+            collector.visit_expr_mut(&mut arm.body);
+        });
+    }
+
+    fn visit_expr_field_mut(&mut self, expr: &mut syn::ExprField) {
+        if let Member::Named(ident) = &expr.member {
+            match self.expr_type(&expr.base) {
+                ValueType::Generated(type_name) => {
+                    self.reads.by_type.entry(type_name).or_default().insert(ident.to_string());
+                }
+                ValueType::Other => {}
+                ValueType::Unknown => {
+                    self.reads.unknown.insert(ident.to_string());
+                }
+            }
+        }
+        syn::visit_mut::visit_expr_field_mut(self, expr);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        // arguments of `assert_eq!`, `format!` and the like are expressions
+        if let Ok(mut args) = Punctuated::<Expr, Token![,]>::parse_terminated.parse2(mac.tokens.clone()) {
+            args.iter_mut().for_each(|arg| self.visit_expr_mut(arg));
+        }
+    }
+}
+
+struct StructFieldRenamer<'a> {
+    renames: &'a BTreeMap<String, BTreeMap<String, String>>,
+    self_type: Option<String>,
+    /// Start of the text to replace, its length in bytes and the replacement
+    edits: Vec<(proc_macro2::LineColumn, usize, String)>,
+}
+
+impl StructFieldRenamer<'_> {
+    fn field_renames(&self, path: &syn::Path) -> Option<&BTreeMap<String, String>> {
+        let type_name = match path.is_ident("Self") {
+            true => self.self_type.clone()?,
+            false => path.segments.last()?.ident.to_string(),
+        };
+        self.renames.get(&type_name)
+    }
+
+    /// Renames [member] of a struct expression or pattern, a shorthand `field` becomes `new_field: field`
+    fn rename(&mut self, field_renames: &BTreeMap<String, String>, member: &Member, shorthand: bool) {
+        let Member::Named(ident) = member else {
+            return;
+        };
+        let old = ident.to_string();
+        let Some(new) = field_renames.get(&old) else {
+            return;
+        };
+        let replacement = if shorthand { format!("{new}: {old}") } else { new.clone() };
+        self.edits.push((ident.span().start(), old.len(), replacement));
+    }
+}
+
+impl VisitMut for StructFieldRenamer<'_> {
+    fn visit_item_impl_mut(&mut self, item: &mut syn::ItemImpl) {
+        let self_type = self.self_type.take();
+        self.self_type = path_type_name(&item.self_ty);
+        syn::visit_mut::visit_item_impl_mut(self, item);
+        self.self_type = self_type;
+    }
+
+    fn visit_expr_struct_mut(&mut self, expr: &mut syn::ExprStruct) {
+        if let Some(field_renames) = self.field_renames(&expr.path).cloned() {
+            for field in &expr.fields {
+                self.rename(&field_renames, &field.member, field.colon_token.is_none());
+            }
+        }
+        syn::visit_mut::visit_expr_struct_mut(self, expr);
+    }
+
+    fn visit_pat_struct_mut(&mut self, pat: &mut syn::PatStruct) {
+        if let Some(field_renames) = self.field_renames(&pat.path).cloned() {
+            for field in &pat.fields {
+                self.rename(&field_renames, &field.member, field.colon_token.is_none());
+            }
+        }
+        syn::visit_mut::visit_pat_struct_mut(self, pat);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        if let Ok(mut args) = Punctuated::<Expr, Token![,]>::parse_terminated.parse2(mac.tokens.clone()) {
+            args.iter_mut().for_each(|arg| self.visit_expr_mut(arg));
+        }
+    }
+}
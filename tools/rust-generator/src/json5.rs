@@ -0,0 +1,291 @@
+use crate::lint::escape_pointer;
+use crate::sources::{json_number, ParseError, Position, Positions};
+use serde_json::{Map, Value};
+
+/// Parses JSON5 [text], JSON with these extensions:
+/// - `//` and `/* */` comments and trailing commas in objects and arrays
+/// - property names without quotes when they are identifiers, e.g. `$ref` or `_key`
+/// - `'..'` strings, the `\v`, `\0` and `\x41` escapes and line breaks escaped with `\`
+/// - `0x` hexadecimal numbers, a leading `+`, and a leading or trailing `.`, e.g. `.5` or `5.`
+///
+/// Duplicate keys keep the last value like JSON. `Infinity` and `NaN` have no JSON equivalent,
+/// `undefined` and other bare words are not values; they are errors with their position.
+pub fn parse(text: &str) -> Result<(Value, Positions), ParseError> {
+    let mut parser = Parser { chars: text.chars().collect(), index: 0, line: 1, column: 1, positions: Positions::new() };
+    parser.skip_whitespace()?;
+    parser.positions.insert(String::new(), parser.position());
+    let value = parser.parse_value("")?;
+    parser.skip_whitespace()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("trailing characters after the value"));
+    }
+    Ok((value, parser.positions))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+    positions: Positions,
+}
+
+impl Parser {
+    fn position(&self) -> Position {
+        Position { line: self.line, column: self.column }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError { position: self.position(), message: message.into() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' || (c == '\r' && self.peek() != Some('\n')) || c == '\u{2028}' || c == '\u{2029}' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected `{expected}`, found `{c}`"))),
+            None => Err(self.error(format!("expected `{expected}`, found the end of the file"))),
+        }
+    }
+
+    /// Skips whitespace and comments
+    fn skip_whitespace(&mut self) -> Result<(), ParseError> {
+        loop {
+            match (self.peek(), self.peek_at(1)) {
+                (Some(c), _) if c.is_whitespace() || c == '\u{feff}' => {
+                    self.next();
+                }
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n' && c != '\r') {
+                        self.next();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.position();
+                    self.next();
+                    self.next();
+                    while !(self.peek() == Some('*') && self.peek_at(1) == Some('/')) {
+                        if self.next().is_none() {
+                            return Err(ParseError { position: start, message: "unterminated block comment".to_string() });
+                        }
+                    }
+                    self.next();
+                    self.next();
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn parse_value(&mut self, pointer: &str) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('{') => self.parse_object(pointer),
+            Some('[') => self.parse_array(pointer),
+            Some('"' | '\'') => self.parse_string().map(Value::String),
+            Some(c) if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') => self.parse_number(),
+            Some(c) if is_identifier_start(c) => {
+                let position = self.position();
+                let identifier = self.parse_identifier()?;
+                match identifier.as_str() {
+                    "null" => Ok(Value::Null),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "Infinity" | "NaN" => Err(ParseError { position, message: format!("`{identifier}` can not be represented in JSON") }),
+                    _ => Err(ParseError { position, message: format!("unexpected `{identifier}`, strings need quotes") }),
+                }
+            }
+            Some(c) => Err(self.error(format!("unexpected `{c}`"))),
+            None => Err(self.error("unexpected end of the file")),
+        }
+    }
+
+    fn parse_object(&mut self, pointer: &str) -> Result<Value, ParseError> {
+        self.expect('{')?;
+        let mut map = Map::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.peek() == Some('}') {
+                self.next();
+                return Ok(Value::Object(map));
+            }
+            let position = self.position();
+            let key = match self.peek() {
+                Some('"' | '\'') => self.parse_string()?,
+                Some(c) if is_identifier_start(c) => self.parse_identifier()?,
+                Some(c) => return Err(self.error(format!("expected a property name, found `{c}`"))),
+                None => return Err(self.error("unterminated object")),
+            };
+            self.skip_whitespace()?;
+            self.expect(':')?;
+            self.skip_whitespace()?;
+            let member_pointer = format!("{pointer}/{}", escape_pointer(&key));
+            self.positions.entry(member_pointer.clone()).or_insert(position);
+            let value = self.parse_value(&member_pointer)?;
+            // the last duplicate wins in its first place, as serde_json does
+            map.insert(key, value);
+            self.skip_whitespace()?;
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some('}') => {}
+                Some(c) => return Err(self.error(format!("expected `,` or `}}`, found `{c}`"))),
+                None => return Err(self.error("unterminated object")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self, pointer: &str) -> Result<Value, ParseError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            let item_pointer = format!("{pointer}/{}", values.len());
+            self.positions.insert(item_pointer.clone(), self.position());
+            values.push(self.parse_value(&item_pointer)?);
+            self.skip_whitespace()?;
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some(']') => {}
+                Some(c) => return Err(self.error(format!("expected `,` or `]`, found `{c}`"))),
+                None => return Err(self.error("unterminated array")),
+            }
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, ParseError> {
+        let mut identifier = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                self.next();
+                if self.next() != Some('u') {
+                    return Err(self.error("expected a `\\u` escape in the property name"));
+                }
+                identifier.push(self.parse_unicode_escape()?);
+            } else if is_identifier_start(c) || c.is_alphanumeric() || matches!(c, '\u{200c}' | '\u{200d}') {
+                identifier.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        Ok(identifier)
+    }
+
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        let start = self.position();
+        let Some(quote) = self.next() else {
+            return Err(self.error("expected a string"));
+        };
+        let mut string = String::new();
+        loop {
+            // reported before it is consumed, so the position stays on the line of the string
+            if matches!(self.peek(), Some('\n' | '\r')) {
+                return Err(self.error("line breaks in strings need a `\\` before them"));
+            }
+            match self.next() {
+                None => return Err(ParseError { position: start, message: "unterminated string".to_string() }),
+                Some(c) if c == quote => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('v') => string.push('\u{b}'),
+                    Some('0') if !self.peek().is_some_and(|c| c.is_ascii_digit()) => string.push('\0'),
+                    Some(c) if c.is_ascii_digit() => return Err(self.error(format!("invalid escape `\\{c}`"))),
+                    Some('x') => {
+                        let hex = self.take_hex(2)?;
+                        string.push(char::from_u32(hex).unwrap_or_default());
+                    }
+                    Some('u') => string.push(self.parse_unicode_escape()?),
+                    // an escaped line break continues the string on the next line
+                    Some('\r') => {
+                        if self.peek() == Some('\n') {
+                            self.next();
+                        }
+                    }
+                    Some('\n' | '\u{2028}' | '\u{2029}') => {}
+                    Some(c) => string.push(c),
+                    None => return Err(ParseError { position: start, message: "unterminated string".to_string() }),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    /// Character of a `\u` escape after the `u`, surrogate pairs are two escapes
+    fn parse_unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.take_hex(4)?;
+        if (0xd800..0xdc00).contains(&high) && self.peek() == Some('\\') && self.peek_at(1) == Some('u') {
+            self.next();
+            self.next();
+            let low = self.take_hex(4)?;
+            let code = 0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+            return char::from_u32(code).ok_or_else(|| self.error("invalid surrogate pair"));
+        }
+        char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn take_hex(&mut self, digits: usize) -> Result<u32, ParseError> {
+        let mut value = 0;
+        for _ in 0..digits {
+            let digit = self.peek().and_then(|c| c.to_digit(16)).ok_or_else(|| self.error("invalid hexadecimal escape"))?;
+            self.next();
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Value, ParseError> {
+        let position = self.position();
+        let mut literal = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-') {
+                literal.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        if literal.trim_start_matches(['+', '-']) == "Infinity" || literal.trim_start_matches(['+', '-']) == "NaN" {
+            return Err(ParseError { position, message: format!("`{literal}` can not be represented in JSON") });
+        }
+        json_number(&literal)
+            .map(Value::Number)
+            .ok_or(ParseError { position, message: format!("invalid number `{literal}`") })
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
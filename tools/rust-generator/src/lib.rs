@@ -1,61 +1,143 @@
+use catalog::{resolve_ids, Catalog};
+use config::{GeneratorConfig, SchemasConfig, TypesConfig};
+use definitions::DefinitionRules;
+use derives::{apply_derives, SkippedDerive};
+use discovery::{definition_key, discover_schema_files, resolve_file_ref};
+use drafts::{lower_schemas, DRAFT_07_SCHEMA};
+use lint::escape_pointer;
+use passes::{apply_passes, default_passes, print_file, AstPass};
+use sources::{parse_source, SourceFormat, SourceMap};
 use schemars::schema::RootSchema;
 use serde_json::{json, Value};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::ffi::{OsStr, OsString};
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+pub mod catalog;
+pub mod compat;
+pub mod config;
+pub mod definitions;
+pub mod derives;
+pub mod discovery;
+pub mod docs;
+pub mod drafts;
+pub mod field_uses;
+pub mod json5;
+pub mod lint;
+pub mod meta_schema;
+pub mod modules;
+pub mod passes;
+pub mod sources;
+pub mod yaml;
+
+/// Definition the runtime modules, the pipeline binary and the crate tests are written against,
+/// they are left out of the crate when roots prune it
+pub const RUNTIME_ROOT: &str = "pipeline";
+
+/// Modules from [generator_src_path]/runtime copied as is into the generated crate
+const RUNTIME_MODULES: [&str; 7] = ["node", "policy", "diff", "merge", "canonical", "normalize", "migrations"];
+
+/// Sources of [RUNTIME_MODULES] and the crate tests, for the fields of generated types they read
+const RUNTIME_SOURCES: [&str; 8] = [
+    include_str!("runtime/node.rs"),
+    include_str!("runtime/policy.rs"),
+    include_str!("runtime/diff.rs"),
+    include_str!("runtime/merge.rs"),
+    include_str!("runtime/canonical.rs"),
+    include_str!("runtime/normalize.rs"),
+    include_str!("runtime/migrations.rs"),
+    include_str!("tests.rs"),
+];
 
 pub struct CrateStructure {
     pub crate_path: OsString,
     pub src_path: OsString,
 }
 
+/// File of the generated crate, [path] is relative to the crate directory
+pub struct GeneratedFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
 /// Generates crate files structure:
 /// ./rust/
 ///     Cargo.toml
 ///     README.md
 ///     src/
+///         bin/
+///             pipeline.rs
 ///         lib.rs
+///         node.rs
+///         policy.rs
+///         diff.rs
+///         merge.rs
+///         canonical.rs
+///         normalize.rs
+///         migrations.rs
 pub fn generate_crate_structure(
     generator_src_path: &OsString,
     path: &OsString,
-    package_name: &String,
-    package_version: &String
+    package_name: &str,
+    package_version: &str,
+    schema_version: &str
 ) -> Result<CrateStructure, Box<dyn Error>> {
-    // ./rust/
-    let mut crate_path_buf = PathBuf::from(path);
-    crate_path_buf.push("rust");
-    let crate_path = crate_path_buf.as_path();
-    std::fs::create_dir_all(crate_path)?;
+    let config = GeneratorConfig::for_package(package_name, package_version, schema_version);
+    let files = generate_crate_files(generator_src_path, &config, true)?;
+    let crate_path = PathBuf::from(path).join(&config.output.crate_dir);
+    write_crate_files(&crate_path, &files)?;
+
+    Ok(CrateStructure {
+        src_path: crate_path.join("src").into_os_string(),
+        crate_path: crate_path.into_os_string(),
+    })
+}
+
+/// Generates in memory the files of [generate_crate_structure], without types.rs
+/// and the bundled schema. Without [with_runtime] the crate has only the types.
+pub fn generate_crate_files(
+    generator_src_path: &OsString,
+    config: &GeneratorConfig,
+    with_runtime: bool
+) -> Result<Vec<GeneratedFile>, Box<dyn Error>> {
+    let package = &config.package;
+    let package_name = package.name()?;
+    let crate_name = package_name.replace('-', "_");
+    let package_version = package.version()?;
+    let schema_version = package.schema_version()?;
+    let mut files = Vec::new();
 
     // ./rust/Cargo.toml
-    let mut cargo_toml_path_buf = crate_path_buf.clone();
-    cargo_toml_path_buf.push("Cargo.toml");
-    let cargo_toml_path = cargo_toml_path_buf.as_path();
-    let mut cargo_toml_file = File::create(cargo_toml_path)?;
     let cargo_toml_content = format!(r#"[package]
 name = "{package_name}"
 version = "{package_version}"
-edition = "2024"
-description = "Rust types and parser generated from JSON schemas"
-homepage = "https://github.com/agolokoz/agentic-pipilenes-schemas"
-repository = "https://github.com/agolokoz/agentic-pipilenes-schemas"
-license = "MIT"
-readme = "README.md"
+edition = "{edition}"
+description = "{description}"
+homepage = "{homepage}"
+repository = "{repository}"
+license = "{license}"
+readme = "{readme}"
 
 [dependencies]
 serde = {{ version = "1.0.228", features = ["derive"] }}
 serde_json = "1.0.145"
-"#);
-    cargo_toml_file.write_all(cargo_toml_content.as_bytes())?;
+{extra_dependencies}"#,
+        edition = package.edition,
+        description = package.description,
+        homepage = package.homepage,
+        repository = package.repository,
+        license = package.license,
+        readme = package.readme,
+        extra_dependencies = package.dependencies.iter()
+            .map(|(name, version)| format!("{name} = \"{version}\"\n"))
+            .collect::<String>(),
+    );
+    files.push(GeneratedFile { path: PathBuf::from("Cargo.toml"), content: cargo_toml_content });
 
     // ./rust/README.md
-    let mut readme_path_buf = crate_path_buf.clone();
-    readme_path_buf.push("README.md");
-    let readme_path = readme_path_buf.as_path();
-    let mut readme_file = File::create(readme_path)?;
     let readme_content = format!(r#"# Rust types and parser generated from JSON schemas.
 
 ## Installation
@@ -66,8 +148,8 @@ cargo add {package_name}
 ## Usage
 
 ```rust
-use {package_name}::parse_json;
-use {package_name}::types::{{Graph}};
+use {crate_name}::parse_json;
+use {crate_name}::types::{{Graph}};
 
 fn main() {{
     let graph_json: &str = "{{ here is your graph json }}"; 
@@ -79,18 +161,183 @@ fn main() {{
         println!("Failed to parse graph");
     }}
 }}
+```
+
+### Building nodes
+
+Node constructors fill in `type`, `config.kind` and the default ports:
+
+```rust
+use {crate_name}::types::Node;
+
+let agent: Node = Node::agent("agent", "demo-agent")
+    .model("gpt-4.1-mini")
+    .input("input", "{{{{workflow.input_as_text}}}}")
+    .build();
+let end: Node = Node::end("end").output_state().into();
+```
+
+Crates generated with `--struct-builder` or `types.struct_builder = true` also provide
+`builder()` for every generated struct, e.g. `AgentConfig::builder()`.
+
+### Model policy
+
+Agent nodes can be checked against a local model catalog with known models,
+allowed params and per-environment allow/deny lists:
+
+```rust,no_run
+use {crate_name}::policy::ModelCatalog;
+use {crate_name}::types::Pipeline;
+
+fn check(pipeline: &Pipeline) -> Result<(), Box<dyn std::error::Error>> {{
+    let catalog = ModelCatalog::load("models.json")?;
+    for violation in catalog.check_pipeline(pipeline, Some("production"))? {{
+        println!("{{violation}}");
+    }}
+    Ok(())
+}}
+```
+
+### Structural diff
+
+Nodes and edges are matched by id, including inside while bodies, so reordering
+`nodes` doesn't show up as a rewrite:
+
+```rust
+use {crate_name}::diff::diff;
+use {crate_name}::types::Pipeline;
+
+fn print_diff(old_pipeline: &Pipeline, new_pipeline: &Pipeline) {{
+    let pipeline_diff = diff(old_pipeline, new_pipeline);
+    print!("{{pipeline_diff}}");
+}}
+```
+
+### Three-way merge
+
+Pipelines edited on different branches are merged by nodes, edges, state variables
+and config fields. Conflicts are reported only for incompatible changes of the same value:
+
+```rust
+use {crate_name}::merge::merge3;
+use {crate_name}::types::Pipeline;
+
+fn merge(base: &Pipeline, ours: &Pipeline, theirs: &Pipeline) {{
+    match merge3(base, ours, theirs) {{
+        Ok(merged) => {{
+            dbg!(merged);
+        }}
+        Err(conflicts) => conflicts.iter().for_each(|conflict| println!("{{conflict}}")),
+    }}
+}}
+```
+
+### Canonical formatting
+
+`canonicalize` prints a pipeline with keys in the schema property order, nodes sorted
+topologically from the start node and edges sorted by source. The `pipeline` binary
+of this crate, `src/bin/pipeline.rs`, formats files in place or checks them:
+
+```bash
+cargo install {package_name}
+pipeline fmt pipelines/*.json
+pipeline fmt --check pipelines/*.json
+```
+
+It needs the generated types, so the schema generator has no `fmt` command of its own. From a
+freshly generated crate it runs without installing:
+
+```bash
+cargo run --manifest-path rust/Cargo.toml --bin pipeline -- fmt pipelines/*.json
+```
+
+### Shorthand expressions
+
+`WhileConfig::condition` and `OutputSelector::expression` accept either a bare string
+or an `ExpressionSpec`. `normalize` rewrites every shorthand into an explicit
+`ExpressionSpec` with the default format, `compact` does the reverse:
+
+```rust
+use {crate_name}::normalize::{{compact, normalize}};
+use {crate_name}::types::Pipeline;
+
+fn round_trip(pipeline: &mut Pipeline) {{
+    normalize(pipeline);
+    compact(pipeline);
+}}
+```
+
+### Schema version migrations
+
+Stored pipelines are upgraded to `SCHEMA_VERSION` by applying registered migration
+steps one version at a time:
+
+```rust
+use {crate_name}::migrations::upgrade_to_latest_with_report;
+use {crate_name}::types::Pipeline;
+
+fn upgrade(stored_json: &str) -> Result<Pipeline, Box<dyn std::error::Error>> {{
+    let value: serde_json::Value = serde_json::from_str(stored_json)?;
+    let (pipeline, report) = upgrade_to_latest_with_report(value)?;
+    print!("{{report}}");
+    Ok(pipeline)
+}}
 ```
     "#);
-    readme_file.write_all(readme_content.as_bytes())?;
+    files.push(GeneratedFile { path: PathBuf::from(&package.readme), content: readme_content });
+
+    // ./rust/src/*.rs: hand-written modules shipped along with generated types
+    // typify clones `Copy` enums in their `From<&T>` impls
+    let mut lib_rs_content = "#[allow(clippy::clone_on_copy)]\npub mod types;\n".to_string();
+    let runtime_modules = if with_runtime { RUNTIME_MODULES.as_slice() } else { &[] };
+    for module_name in runtime_modules {
+        let file_name = format!("{module_name}.rs");
+        let module_content = get_file_content(PathBuf::from(generator_src_path).join("runtime").join(&file_name).as_os_str())?;
+        files.push(GeneratedFile { path: PathBuf::from("src").join(&file_name), content: module_content });
+        lib_rs_content.push_str(&format!("pub mod {module_name};\n"));
+    }
 
-    // ./rust/src
-    let mut src_path_buf = crate_path_buf.clone();
-    src_path_buf.push("src");
-    let src_path = src_path_buf.as_path();
-    let _ = std::fs::create_dir_all(src_path)?;
+    // ./rust/src/*.rs: hand-written modules from the config, e.g. with replaced types
+    for module_path in &config.output.modules {
+        let module_path = Path::new(module_path);
+        let module_name = module_path.file_stem()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| format!("invalid module file name {}", module_path.display()))?;
+        if module_name == "types" || module_name == "lib" || RUNTIME_MODULES.contains(&module_name) {
+            return Err(format!("module {} clashes with a module of the generated crate", module_path.display()).into());
+        }
+        let module_content = get_file_content(module_path.as_os_str())
+            .map_err(|err| format!("failed to read module {}: {err}", module_path.display()))?;
+        files.push(GeneratedFile { path: PathBuf::from("src").join(format!("{module_name}.rs")), content: module_content });
+        lib_rs_content.push_str(&format!("pub mod {module_name};\n"));
+    }
+
+    // ./rust/src/bin/pipeline.rs
+    let bin_content = format!(r#"use std::process::ExitCode;
+
+fn main() -> ExitCode {{
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {{
+        Some("fmt") => {crate_name}::canonical::fmt_main(args),
+        _ => {{
+            eprintln!("Usage: pipeline fmt [--check] <FILE>...");
+            ExitCode::from(2)
+        }}
+    }}
+}}
+"#);
+    if with_runtime {
+        files.push(GeneratedFile { path: PathBuf::from("src").join("bin").join("pipeline.rs"), content: bin_content });
+    }
 
     // ./rust/src/lib.rs
-    let mut lib_rs_content = r#"pub mod types;
+    lib_rs_content.push_str(&format!(r#"
+/// Schema version of pipelines the types were generated for
+pub const SCHEMA_VERSION: &str = {schema_version:?};
+"#));
+    lib_rs_content.push_str(r#"
+/// Bundled JSON schema the types were generated from
+pub const BUNDLED_SCHEMA: &str = include_str!("../bundled.schema.json");
 
 pub fn parse_json<'a, T>(json: &'a str) -> serde_json::Result<T>
 where T: serde::de::Deserialize<'a>
@@ -98,54 +345,200 @@ where T: serde::de::Deserialize<'a>
     serde_json::from_str(json)
 }
 
-"#.to_string();
-    let tests_content = get_file_content(PathBuf::from(generator_src_path).join("tests.rs").as_os_str())?;
-    lib_rs_content.push_str(&tests_content);
-    let lib_rs_path = src_path_buf.clone().join("lib.rs");
-    let mut lib_rs_file = File::create(lib_rs_path)?;
-    lib_rs_file.write_all(lib_rs_content.as_bytes())?;
+"#);
+    if with_runtime {
+        // README examples use the runtime modules, `cargo test` compiles them as doctests
+        lib_rs_content.push_str(&format!("#[cfg(doctest)]\n#[doc = include_str!(\"../{}\")]\npub struct ReadmeDoctests;\n\n", package.readme));
+        let tests_content = get_file_content(PathBuf::from(generator_src_path).join("tests.rs").as_os_str())?;
+        lib_rs_content.push_str(&tests_content);
+    }
+    files.push(GeneratedFile { path: PathBuf::from("src").join("lib.rs"), content: lib_rs_content });
 
-    Ok(CrateStructure {
-        crate_path: crate_path.as_os_str().to_os_string(),
-        src_path: src_path.as_os_str().to_os_string(),
-    })
+    Ok(files)
+}
+
+/// Writes [files] into [crate_path], creating missing directories
+pub fn write_crate_files(crate_path: &Path, files: &[GeneratedFile]) -> Result<(), Box<dyn Error>> {
+    for file in files {
+        let file_path = crate_path.join(&file.path);
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut output_file = File::create(file_path)?;
+        output_file.write_all(file.content.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Compares [files] with their copies in [crate_path] and returns unified diffs
+/// of the files that differ or are missing
+pub fn diff_crate_files(crate_path: &Path, files: &[GeneratedFile]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut diffs = Vec::new();
+    for file in files {
+        let file_path = crate_path.join(&file.path);
+        let current_content = match std::fs::read_to_string(&file_path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        if current_content == file.content {
+            continue;
+        }
+
+        let display_path = file_path.display().to_string();
+        let diff = similar::TextDiff::from_lines(&current_content, &file.content)
+            .unified_diff()
+            .header(&display_path, &display_path)
+            .to_string();
+        diffs.push(diff);
+    }
+    Ok(diffs)
+}
+
+/// Generated files in [crate_path] that are not among [files], e.g. src/types.rs after switching
+/// to a module per schema file, the module of a deleted schema file or runtime modules left out by roots
+pub fn stale_crate_files(crate_path: &Path, files: &[GeneratedFile]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut candidates = vec![PathBuf::from("src").join("types.rs"), PathBuf::from("src").join("bin").join("pipeline.rs")];
+    candidates.extend(RUNTIME_MODULES.iter().map(|module_name| PathBuf::from("src").join(format!("{module_name}.rs"))));
+    let types_path = crate_path.join("src").join("types");
+    if types_path.is_dir() {
+        for entry in std::fs::read_dir(&types_path)? {
+            let file_name = entry?.file_name();
+            if Path::new(&file_name).extension() == Some(OsStr::new("rs")) {
+                candidates.push(PathBuf::from("src").join("types").join(file_name));
+            }
+        }
+    }
+
+    let mut stale_files = candidates.into_iter()
+        .filter(|path| !files.iter().any(|file| file.path == *path))
+        .map(|path| crate_path.join(path))
+        .filter(|path| path.exists())
+        .collect::<Vec<_>>();
+    stale_files.sort();
+    Ok(stale_files)
+}
+
+/// Saves bundled [schema] to [crate_path]/bundled.schema.json, it is embedded into the crate
+pub fn save_bundled_schema(schema: &Value, crate_path: &OsString) -> Result<(), Box<dyn Error>> {
+    let bundled_schema_path = PathBuf::from(crate_path).join("bundled.schema.json");
+    let mut bundled_schema_file = File::create(bundled_schema_path)?;
+    bundled_schema_file.write_all(serialize_bundled_schema(schema)?.as_bytes())?;
+    Ok(())
+}
+
+pub fn serialize_bundled_schema(schema: &Value) -> Result<String, Box<dyn Error>> {
+    Ok(serde_json::to_string_pretty(schema)?)
+}
+
+/// Bundled JSON schema together with the origin of its definitions
+pub struct Bundle {
+    pub schema: Value,
+    /// Definition key to the key of the schema file it comes from, e.g. "port" to "common"
+    pub sources: BTreeMap<String, String>,
+    /// Definition key to the key of the loaded file that has the definition and its pointer there,
+    /// e.g. "port" to "common" and "/$defs/port", for messages
+    pub locations: BTreeMap<String, (String, String)>,
 }
 
-/// Accumulates all *.schema.json files from [schemas_path] without [skip_file_names]
-/// into one JSON schema
+/// Accumulates the schema files of [schemas_config] under [schemas_path] into one JSON schema,
+/// a pre-bundled schema file as [schemas_path] is only validated
 pub fn get_bundled_schema(
     schemas_path: &OsString,
-    skip_file_names: &HashSet<OsString>
+    schemas_config: &SchemasConfig
 ) -> Result<Value, Box<dyn Error>> {
-    let schemas_entries = std::fs::read_dir(&schemas_path)?;
-
-    let mut file_names = schemas_entries
-        .map(|entry| entry.unwrap().file_name())
-        .filter(|file_name| {
-            let file_name_str = file_name.to_str().unwrap();
-            file_name_str.ends_with(".schema.json") && !skip_file_names.contains(file_name)
-        })
-        .collect::<Vec<OsString>>();
-    file_names.sort();
+    bundle_schemas(schemas_path, schemas_config).map(|bundle| bundle.schema)
+}
 
+/// Reads the schema files of [schemas_config] under [schemas_path], see [discover_schema_files],
+/// returns the schemas by file key, the relative path without the suffix, and their paths and
+/// positions for error messages. JSON, JSON5 and YAML files give the same values.
+/// Files of the catalog are added as they are referenced by URI, see [resolve_ids].
+/// A file as [schemas_path] is a pre-bundled schema, see [load_bundled_schema_file].
+pub fn load_schemas(
+    schemas_path: &OsString,
+    schemas_config: &SchemasConfig
+) -> Result<(BTreeMap<String, Value>, SourceMap), Box<dyn Error>> {
+    if Path::new(schemas_path).is_file() {
+        return load_bundled_schema_file(Path::new(schemas_path), schemas_config);
+    }
     let mut schemas: BTreeMap<String, Value> = BTreeMap::new();
-    for file_name in file_names {
-        let key = file_name.to_str().unwrap().trim_end_matches(".schema.json").to_string();
-        let path = PathBuf::from(&schemas_path).join(file_name);
-        let file = File::open(&path).unwrap();
-        let schema_value: Value = serde_json::from_reader(file).unwrap();
-        schemas.insert(key, schema_value);
+    let mut source_map = SourceMap::new(&schemas_config.suffix);
+    for (key, relative_path) in discover_schema_files(Path::new(schemas_path), schemas_config)? {
+        let path = PathBuf::from(schemas_path).join(&relative_path);
+        let content = get_file_content(path.as_os_str())?;
+        let (schema_value, positions) = parse_source(&content, SourceFormat::of(&relative_path))
+            .map_err(|err| format!("{}:{err}", path.display()))?;
+        schemas.insert(key.clone(), schema_value);
+        source_map.insert(&key, &relative_path, positions);
     }
+    let catalog = Catalog::new(&schemas_config.catalog)?;
+    resolve_ids(&mut schemas, &mut source_map, &catalog, Path::new(schemas_path))?;
+    Ok((schemas, source_map))
+}
 
+/// Reads pre-bundled schema file [path], e.g. `bundled.schema.json` of another pipeline, as the only
+/// schema. Its file key is the file name without the suffix, e.g. `bundled`. Its refs are already
+/// local to its `$defs`, so the catalog is not used.
+pub fn load_bundled_schema_file(
+    path: &Path,
+    schemas_config: &SchemasConfig
+) -> Result<(BTreeMap<String, Value>, SourceMap), Box<dyn Error>> {
+    let file_name = path.file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| format!("bundled schema path {} has no UTF-8 file name", path.display()))?;
+    let file_key = definition_key(SourceFormat::strip_suffix(file_name, &schemas_config.suffix));
+    let content = get_file_content(path.as_os_str())?;
+    let (schema, positions) = parse_source(&content, SourceFormat::of(file_name))
+        .map_err(|err| format!("{}:{err}", path.display()))?;
+    let mut source_map = SourceMap::new(&schemas_config.suffix);
+    source_map.insert(&file_key, file_name, positions);
+    Ok((BTreeMap::from([(file_key, schema)]), source_map))
+}
+
+/// Same as [get_bundled_schema], but also tells which file each definition comes from
+pub fn bundle_schemas(
+    schemas_path: &OsString,
+    schemas_config: &SchemasConfig
+) -> Result<Bundle, Box<dyn Error>> {
+    let (schemas, source_map) = load_schemas(schemas_path, schemas_config)?;
+    let lowered = lower_schemas(&schemas, &source_map)?;
+    let bundle = if Path::new(schemas_path).is_file() {
+        let [(file_key, schema)] = Vec::from_iter(&lowered).try_into().expect("a pre-bundled schema is loaded as one document");
+        bundle_prebundled_schema(file_key, schema)
+    } else {
+        bundle_loaded_schemas(&lowered, &schemas_config.suffix)
+    };
+    check_bundle_layout(&bundle.schema)?;
+    Ok(bundle)
+}
+
+/// Bundles draft-07 [schemas] by file key into one JSON schema, later drafts are lowered
+/// with [lower_schemas] first. `$ref` paths end with [suffix].
+pub fn bundle_loaded_schemas(schemas: &BTreeMap<String, Value>, suffix: &str) -> Bundle {
     // Build definitions with rewritten documents and hoisted local definitions
     let mut defs = serde_json::Map::new();
-    for (key, schema) in &schemas {
+    let mut sources = BTreeMap::new();
+    let mut locations = BTreeMap::new();
+    for (key, schema) in schemas {
         let mut hoisted_defs = serde_json::Map::new();
-        let transformed = transform(schema, key, &mut hoisted_defs);
-        defs.insert(key.clone(), transformed);
+        let mut transformed = transform(schema, key, suffix, &mut hoisted_defs);
+        // refs by `$id` are resolved on load, in the bundle an `$id` would change the base URI of the definition
+        if let Value::Object(map) = &mut transformed {
+            map.remove("$id");
+        }
+        defs.insert(definition_key(key), transformed);
+        sources.insert(definition_key(key), key.clone());
+        locations.insert(definition_key(key), (key.clone(), String::new()));
 
         for (k, v) in hoisted_defs.into_iter() {
-            // workaround for "oneOf" arrays in different objects
+            sources.entry(k.clone()).or_insert_with(|| key.clone());
+            locations.entry(k.clone()).or_insert_with(|| {
+                let pointer = definition_pointer(schema, &k).unwrap_or_else(|| format!("/$defs/{}", escape_pointer(&k)));
+                (key.clone(), pointer)
+            });
+            // a definition with `oneOf` in several files, e.g. `node` of the graph and the subgraph,
+            // gets the branches of all of them
             if let Some(old_value) = defs.get(&k) &&
                 let Value::Object(old_value_map) = old_value &&
                 let Value::Object(new_value_map) = &v &&
@@ -154,15 +547,18 @@ pub fn get_bundled_schema(
                 let Value::Array(old_value_vec) = old_value_arr &&
                 let Value::Array(new_value_vec) = new_value_arr
             {
-                let mut values_set: HashSet<Value> = HashSet::new();
-                old_value_vec.iter().for_each(|value| { values_set.insert(value.clone()); () });
-                new_value_vec.iter().for_each(|value| { values_set.insert(value.clone()); () });
-                let values_vec= values_set.into_iter().collect::<Vec<Value>>();
+                // entries of the first file come first, so the bundle is the same on every run
+                let mut values_vec = old_value_vec.clone();
+                for value in new_value_vec {
+                    if !values_vec.contains(value) {
+                        values_vec.push(value.clone());
+                    }
+                }
                 let values_arr = Value::Array(values_vec);
 
-                let mut one_of_map = serde_json::Map::new();
-                one_of_map.insert(k.clone(), values_arr);
-                defs.insert(k, Value::Object(one_of_map));
+                let mut merged = old_value_map.clone();
+                merged.insert("oneOf".to_string(), values_arr);
+                defs.insert(k, Value::Object(merged));
 
                 continue;
             }
@@ -172,11 +568,135 @@ pub fn get_bundled_schema(
     }
 
     let bundled: Value = json!({
-        "$schema": "http://json-schema.org/draft-07/schema",
+        "$schema": DRAFT_07_SCHEMA,
         "$defs": defs,
     });
 
-    Ok(bundled)
+    Bundle { schema: bundled, sources, locations }
+}
+
+/// Pointer of the local definition [def_name] of [schema] however deep its `$defs` or `definitions` are
+fn definition_pointer(schema: &Value, def_name: &str) -> Option<String> {
+    let Value::Object(map) = schema else {
+        return None;
+    };
+    for keyword in ["$defs", "definitions"] {
+        if let Some(Value::Object(defs)) = map.get(keyword) {
+            if defs.contains_key(def_name) {
+                return Some(format!("/{keyword}/{}", escape_pointer(def_name)));
+            }
+            for (name, def) in defs {
+                if let Some(pointer) = definition_pointer(def, def_name) {
+                    return Some(format!("/{keyword}/{}{pointer}", escape_pointer(name)));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Bundle of pre-bundled [schema] read from the file [file_key], see [load_bundled_schema_file].
+/// Roots of schema files are bundled with their `$schema`, so a definition with `$schema` and the ones
+/// after it up to the next such definition come from the file it is the root of, as [bundle_loaded_schemas]
+/// orders them. Definitions before the first one come from [file_key]. A bundle pruned to roots may have lost
+/// the root of a file, its other definitions then go with the file before it, only the modules differ.
+pub fn bundle_prebundled_schema(file_key: &str, schema: &Value) -> Bundle {
+    let mut sources = BTreeMap::new();
+    let mut locations = BTreeMap::new();
+    let mut source = file_key.to_string();
+    for (def_key, def) in schema.get("$defs").and_then(Value::as_object).into_iter().flatten() {
+        if def.get("$schema").is_some() {
+            source = def_key.clone();
+        }
+        sources.insert(def_key.clone(), source.clone());
+        locations.insert(def_key.clone(), (file_key.to_string(), format!("/$defs/{}", escape_pointer(def_key))));
+    }
+    Bundle { schema: schema.clone(), sources, locations }
+}
+
+/// Checks that bundled [schema] has the `$defs` layout typify reads: every `$ref` points to
+/// `#/$defs/{key}` of an existing definition, and keys have no `/` as typify reads only
+/// the last segment of a `$ref`
+pub fn check_bundle_layout(schema: &Value) -> Result<(), Box<dyn Error>> {
+    let defs = schema.get("$defs")
+        .and_then(Value::as_object)
+        .ok_or("bundled schema has no $defs")?;
+    if let Some(def_key) = defs.keys().find(|def_key| def_key.contains('/')) {
+        return Err(format!("definition key `{def_key}` of the bundled schema contains `/`").into());
+    }
+    check_def_refs(schema, "", defs).map_err(Into::into)
+}
+
+fn check_def_refs(value: &Value, pointer: &str, defs: &serde_json::Map<String, Value>) -> Result<(), String> {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let pointer = format!("{pointer}/{}", lint::escape_pointer(k));
+                if k == "$ref" && let Some(reference) = v.as_str() {
+                    if !reference.strip_prefix("#/$defs/").is_some_and(|def_key| defs.contains_key(def_key)) {
+                        return Err(format!(
+                            "`$ref` `{reference}` at #{pointer} of the bundled schema is not a definition, expected `#/$defs/{{key}}`"
+                        ));
+                    }
+                } else {
+                    check_def_refs(v, &pointer, defs)?;
+                }
+            }
+            Ok(())
+        }
+        Value::Array(values) => values.iter().enumerate()
+            .try_for_each(|(index, v)| check_def_refs(v, &format!("{pointer}/{index}"), defs)),
+        _ => Ok(()),
+    }
+}
+
+/// Removes from bundled [schema] definitions not reachable by `$ref`s from [roots],
+/// returns keys of the removed definitions
+pub fn prune_unreachable(schema: &mut Value, roots: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let defs = schema.get_mut("$defs")
+        .and_then(Value::as_object_mut)
+        .ok_or("bundled schema has no $defs")?;
+
+    let mut reachable = BTreeSet::new();
+    let mut pending = Vec::new();
+    for root in roots {
+        if !defs.contains_key(root) {
+            return Err(format!("root definition `{root}` is not in the bundled schema").into());
+        }
+        pending.push(root.clone());
+    }
+    while let Some(key) = pending.pop() {
+        if !reachable.insert(key.clone()) {
+            continue;
+        }
+        let mut refs = Vec::new();
+        collect_def_refs(&defs[&key], &mut refs);
+        pending.extend(refs.into_iter().filter(|key| defs.contains_key(key)));
+    }
+
+    let mut pruned = defs.keys()
+        .filter(|key| !reachable.contains(*key))
+        .cloned()
+        .collect::<Vec<String>>();
+    pruned.sort();
+    defs.retain(|key, _| reachable.contains(key));
+    Ok(pruned)
+}
+
+fn collect_def_refs(value: &Value, refs: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                if k == "$ref" && let Some(key) = v.as_str().and_then(|s| s.strip_prefix("#/$defs/")) {
+                    refs.push(key.to_string());
+                } else {
+                    collect_def_refs(v, refs);
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_def_refs(v, refs)),
+        _ => {}
+    }
 }
 
 /// Generates Rust types from [schema] and saves them to [output_path]/types.rs
@@ -184,78 +704,140 @@ pub fn generate_rs_structures(
     schema: Value,
     output_path: &OsString,
 ) -> Result<(), Box<dyn Error>> {
-    let mut type_space_settings = typify::TypeSpaceSettings::default();
-    type_space_settings.with_unknown_crates(typify::UnknownPolicy::Allow);
-    type_space_settings.with_struct_builder(false);
-    type_space_settings.with_derive("PartialEq".to_string());
+    let types_config = TypesConfig::default();
+    let passes = default_passes(&schema, &types_config)?;
+    let bundle = Bundle { schema, sources: BTreeMap::new(), locations: BTreeMap::new() };
+    let generated_types = generate_rs_code(&bundle, &types_config, &passes)?;
+    for file in generated_types.files {
+        let types_rs_path = PathBuf::from(output_path).join(file.path.strip_prefix("src")?);
+        let mut types_rs_file = File::create(types_rs_path)?;
+        types_rs_file.write_all(file.content.as_bytes())?;
+    }
+    Ok(())
+}
 
-    let mut type_space = typify::TypeSpace::new(&type_space_settings);
-    let root_schema: RootSchema = serde_json::from_value(schema)?;
-    type_space.add_root_schema(root_schema)?;
+/// Generated `types` module of the crate
+pub struct GeneratedTypes {
+    /// src/types.rs, or src/types/mod.rs and a module per schema file
+    pub files: Vec<GeneratedFile>,
+    /// Derives from the config that the generated types do not allow
+    pub skipped_derives: Vec<SkippedDerive>,
+    /// Renamed struct fields by type name, see [rename_runtime_fields]
+    pub field_renames: BTreeMap<String, BTreeMap<String, String>>,
+}
 
-    let type_space_file = syn::parse2::<syn::File>(type_space.to_stream())?;
-    let mut code = prettyplease::unparse(&type_space_file);
-    code = cleanup_rs_code(&code);
+/// Generates src/types.rs from the schema of [bundle] with typify settings from [types_config],
+/// the typify output is rewritten by [passes], see [default_passes]
+pub fn generate_rs_code(
+    bundle: &Bundle,
+    types_config: &TypesConfig,
+    passes: &[Box<dyn AstPass>]
+) -> Result<GeneratedTypes, Box<dyn Error>> {
+    let (type_space_file, replacement_tests, skipped_derives, field_renames) = generate_type_space_file(bundle, types_config, passes)?;
+    let mut code = print_file(&type_space_file);
+    code.push_str(&replacement_tests);
+    let files = vec![GeneratedFile { path: PathBuf::from("src").join("types.rs"), content: code }];
+    Ok(GeneratedTypes { files, skipped_derives, field_renames })
+}
 
-    let types_rs_path = PathBuf::from(output_path).join("types.rs");
-    let mut types_rs_file = File::create(types_rs_path)?;
-    types_rs_file.write_all(code.as_bytes())?;
+/// Generates src/types/mod.rs and a module for each schema file of the [bundle], e.g. src/types/agent.rs,
+/// types from the modules are re-exported by `types`
+pub fn generate_rs_modules(
+    bundle: &Bundle,
+    types_config: &TypesConfig,
+    passes: &[Box<dyn AstPass>]
+) -> Result<GeneratedTypes, Box<dyn Error>> {
+    let (type_space_file, replacement_tests, skipped_derives, field_renames) = generate_type_space_file(bundle, types_config, passes)?;
+    let (root_file, module_files) = modules::split_by_source(type_space_file, &bundle.sources, types_config);
+
+    let types_path = PathBuf::from("src").join("types");
+    let mut files = Vec::new();
+    let mut mod_rs_content = String::new();
+    for (file_key, module_file) in module_files {
+        // one flat module per schema file, named as the definition of its root
+        let module_name = definition_key(&file_key);
+        let module_ident = modules::module_ident(&module_name);
+        mod_rs_content.push_str(&format!("pub mod {module_ident};\npub use {module_ident}::*;\n"));
+        let code = format!("#[allow(unused_imports)]\nuse super::*;\n\n{}", print_file(&module_file));
+        files.push(GeneratedFile { path: types_path.join(format!("{module_name}.rs")), content: code });
+    }
+    mod_rs_content.push('\n');
+    mod_rs_content.push_str(&print_file(&root_file));
+    mod_rs_content.push_str(&replacement_tests);
+    files.insert(0, GeneratedFile { path: types_path.join("mod.rs"), content: mod_rs_content });
 
-    Ok(())
+    Ok(GeneratedTypes { files, skipped_derives, field_renames })
 }
 
-fn cleanup_rs_code(code: &str) -> String {
-    let code = cleanup_comment_blocks(code);
-    code.replace("::std::convert::From", "From")
-        .replace("::std::convert::TryFrom", "TryFrom")
-        .replace("::std::option::Option", "Option")
-        .replace("::std::result::Result", "Result")
-        .replace("::std::string::String", "String")
-        .replace("::std::vec::Vec", "Vec")
-        .replace(" ::", " ")
+/// Rewrites struct expressions and patterns of renamed fields in src/lib.rs and the runtime modules of [files],
+/// they are hand-written against the generated field names
+pub fn rename_runtime_fields(
+    files: &mut [GeneratedFile],
+    field_renames: &BTreeMap<String, BTreeMap<String, String>>
+) -> Result<(), Box<dyn Error>> {
+    if field_renames.is_empty() {
+        return Ok(());
+    }
+    let src_path = Path::new("src");
+    let runtime_paths = RUNTIME_MODULES.iter()
+        .map(|module_name| src_path.join(format!("{module_name}.rs")))
+        .chain([src_path.join("lib.rs")])
+        .collect::<Vec<_>>();
+    for file in files.iter_mut().filter(|file| runtime_paths.contains(&file.path)) {
+        file.content = field_uses::rename_struct_fields(&file.content, field_renames)
+            .map_err(|err| format!("failed to rename fields in {}: {err}", file.path.display()))?;
+    }
+    Ok(())
 }
 
-fn cleanup_comment_blocks(input: &str) -> String {
-    let mut result = String::new();
-    let mut in_comment_block = false;
-    let mut first_comment_block = true;
-
-    for line in input.lines() {
-        if line.starts_with("///") {
-            if !in_comment_block {
-                in_comment_block = true;
-                // prevent '\n' at the first file line
-                if first_comment_block {
-                    first_comment_block = false;
-                } else {
-                    result.push('\n');
-                }
-            }
-        } else {
-            in_comment_block = false;
-            result.push_str(line);
-            result.push('\n');
-        }
+/// Runs typify on the schema of [bundle] and applies the config rules and [passes] to its output,
+/// returns the file with the types, tests of replaced types, skipped derives and renamed fields
+#[allow(clippy::type_complexity)]
+fn generate_type_space_file(
+    bundle: &Bundle,
+    types_config: &TypesConfig,
+    passes: &[Box<dyn AstPass>]
+) -> Result<(syn::File, String, Vec<SkippedDerive>, BTreeMap<String, BTreeMap<String, String>>), Box<dyn Error>> {
+    let mut type_space_settings = typify::TypeSpaceSettings::default();
+    type_space_settings.with_unknown_crates(types_config.unknown_crates.into());
+    type_space_settings.with_struct_builder(types_config.struct_builder);
+    for (type_name, rename) in &types_config.renames {
+        type_space_settings.with_patch(type_name, typify::TypeSpacePatch::default().with_rename(rename));
     }
+    // without the runtime modules no hand-written code reads the generated fields
+    let runtime_sources = if bundle.sources.contains_key(RUNTIME_ROOT) { RUNTIME_SOURCES.as_slice() } else { &[] };
+    let definition_rules = DefinitionRules::resolve(types_config, bundle, runtime_sources)?;
+    definition_rules.configure(&mut type_space_settings);
+    let replacement_tests = definition_rules.replacement_tests(&bundle.schema);
+
+    let mut type_space = typify::TypeSpace::new(&type_space_settings);
+    let root_schema: RootSchema = serde_json::from_value(bundle.schema.clone())?;
+    type_space.add_root_schema(root_schema)?;
 
-    result
+    let mut type_space_file = syn::parse2::<syn::File>(type_space.to_stream())?;
+    let field_renames = definition_rules.apply(&mut type_space_file)?;
+    let skipped_derives = apply_derives(&mut type_space_file, types_config)?;
+    apply_passes(&mut type_space_file, passes)?;
+    Ok((type_space_file, replacement_tests, skipped_derives, field_renames))
 }
 
 // Transform a schema document by hoisting any local definitions to the top-level
-// and rewriting refs to point at the hoisted entries.
+// and rewriting refs to point at the hoisted entries. [file_key] is the key of the document.
 fn transform(
     value: &Value,
-    current_doc: &str,
+    file_key: &str,
+    suffix: &str,
     hoisted: &mut serde_json::Map<String, Value>
 ) -> Value {
     match value {
         Value::Object(map) => {
             let mut out = serde_json::Map::with_capacity(map.len());
             for (k, v) in map.iter() {
-                if k == "$defs" {
+                // `definitions` is the draft-07 name of `$defs`
+                if k == "$defs" || k == "definitions" {
                     if let Value::Object(defs_map) = v {
                         for (def_name, def_val) in defs_map.iter() {
-                            let rewritten_def = transform(def_val, current_doc, hoisted);
+                            let rewritten_def = transform(def_val, file_key, suffix, hoisted);
                             hoisted.insert(def_name.clone(), rewritten_def);
                         }
                     }
@@ -263,48 +845,37 @@ fn transform(
                 }
 
                 if k == "$ref" && let Value::String(s) = v {
-                    let new_ref = rewrite_ref_target(s);
+                    let new_ref = rewrite_ref_target(s, file_key, suffix);
                     out.insert(k.clone(), Value::String(new_ref));
                     continue;
                 }
 
-                out.insert(k.clone(), transform(v, current_doc, hoisted));
+                out.insert(k.clone(), transform(v, file_key, suffix, hoisted));
             }
             Value::Object(out)
         }
         Value::Array(arr) => {
-            Value::Array(arr.iter().map(|v| transform(v, current_doc, hoisted)).collect())
+            Value::Array(arr.iter().map(|v| transform(v, file_key, suffix, hoisted)).collect())
         },
         _ => value.clone(),
     }
 }
 
-fn rewrite_ref_target(s: &str) -> String {
-    if let Some(rest) = s.strip_prefix("./") {
-        let (file_part, frag_opt) = match rest.split_once('#') {
-            Some((file, frag)) => (file, Some(frag)),
-            None => (rest, None),
-        };
-
-        let base = file_part.trim_end_matches(".schema.json");
-        if let Some(frag) = frag_opt {
-            let frag = frag.trim_start_matches('/');
-            if let Some(name) = frag.strip_prefix("$defs/") {
-                return format!("#/$defs/{name}");
-            }
-        }
+fn rewrite_ref_target(s: &str, file_key: &str, suffix: &str) -> String {
+    let (path, frag) = s.split_once('#').unwrap_or((s, ""));
+    // paths are relative to the directory of the document
+    let Some(target_file_key) = resolve_file_ref(file_key, path, suffix) else {
+        return s.to_string();
+    };
 
-        return format!("#/$defs/{base}");
+    let frag = frag.trim_start_matches('/');
+    if let Some(name) = frag.strip_prefix("$defs/").or_else(|| frag.strip_prefix("definitions/")) {
+        return format!("#/$defs/{name}");
     }
-
-    if let Some(frag) = s.strip_prefix('#') {
-        let frag = frag.trim_start_matches('/');
-        if let Some(name) = frag.strip_prefix("$defs/") {
-            return format!("#/$defs/{name}");
-        }
-        return s.to_string();
+    // the document root is bundled as the definition of the file
+    if frag.is_empty() {
+        return format!("#/$defs/{}", definition_key(&target_file_key));
     }
-
     s.to_string()
 }
 
@@ -0,0 +1,230 @@
+use crate::config::{LintLevel, LintsConfig};
+use crate::discovery::resolve_file_ref;
+use crate::sources::{Position, SourceMap};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Problem found in a schema file by a lint
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintIssue {
+    /// Name of the lint, as in the `[lints]` config section
+    pub lint: &'static str,
+    pub level: LintLevel,
+    /// Schema file path relative to the schemas directory, e.g. "agent.schema.json"
+    pub file: String,
+    /// JSON pointer to the problem in the file
+    pub pointer: String,
+    /// Line and column of [pointer] in the file, if the loader recorded them
+    pub position: Option<Position>,
+    pub message: String,
+}
+
+/// Schema at [pointer] of a file, the file root if [pointer] is empty
+type Location = (String, String);
+
+/// Lints loaded [schemas] by file key, [source_map] has their paths and positions.
+/// Issues of lints allowed in [lints_config] are left out.
+pub fn lint_schemas(schemas: &BTreeMap<String, Value>, source_map: &SourceMap, lints_config: &LintsConfig) -> Vec<LintIssue> {
+    let mut linter = Linter { schemas, source_map, lints_config, issues: Vec::new() };
+    for (file_key, schema) in schemas {
+        linter.check_required(file_key, schema, "");
+    }
+    linter.check_references();
+    linter.check_nodes();
+
+    let mut issues = linter.issues;
+    issues.retain(|issue| issue.level != LintLevel::Allow);
+    issues.sort_by(|a, b| (&a.file, &a.pointer, a.lint).cmp(&(&b.file, &b.pointer, b.lint)));
+    issues
+}
+
+struct Linter<'a> {
+    schemas: &'a BTreeMap<String, Value>,
+    source_map: &'a SourceMap,
+    lints_config: &'a LintsConfig,
+    issues: Vec<LintIssue>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, lint: &'static str, level: LintLevel, (file_key, pointer): &Location, message: String) {
+        // catalog files are maintained elsewhere, their issues can not be fixed here
+        if self.source_map.catalog_uri(file_key).is_some() {
+            return;
+        }
+        let file = self.source_map.path(file_key);
+        let position = self.source_map.position(file_key, pointer);
+        self.issues.push(LintIssue { lint, level, file, pointer: pointer.clone(), position, message });
+    }
+
+    /// `required` entries missing from `properties` in [schema] and its subschemas
+    fn check_required(&mut self, file_key: &str, schema: &Value, pointer: &str) {
+        match schema {
+            Value::Object(map) => {
+                if let Some(Value::Array(required)) = map.get("required") {
+                    let properties = map.get("properties").and_then(Value::as_object);
+                    for (index, name) in required.iter().enumerate() {
+                        let Some(name) = name.as_str() else {
+                            continue;
+                        };
+                        if properties.is_none_or(|properties| !properties.contains_key(name)) {
+                            let location = (file_key.to_string(), format!("{pointer}/required/{index}"));
+                            let message = format!("`{name}` is required, but it is not in `properties`");
+                            self.report("required_without_property", self.lints_config.required_without_property, &location, message);
+                        }
+                    }
+                }
+                for (key, value) in map {
+                    // values of these keywords are instances, not schemas
+                    if !matches!(key.as_str(), "const" | "enum" | "default" | "examples") {
+                        self.check_required(file_key, value, &format!("{pointer}/{}", escape_pointer(key)));
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    self.check_required(file_key, value, &format!("{pointer}/{index}"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Definitions no `$ref` of any file points to
+    fn check_references(&mut self) {
+        let mut referenced = BTreeSet::new();
+        for (file_key, schema) in self.schemas {
+            collect_refs(schema, &mut |reference| {
+                if let Some(location) = self.resolve_ref(file_key, reference) {
+                    referenced.insert(location);
+                }
+            });
+        }
+
+        for (file_key, schema) in self.schemas {
+            for keyword in ["$defs", "definitions"] {
+                for (def_key, def) in schema.get(keyword).and_then(Value::as_object).into_iter().flatten() {
+                    let location = (file_key.clone(), format!("/{keyword}/{}", escape_pointer(def_key)));
+                    // roots of the files of a pre-bundled schema keep their `$schema`, they are entry points
+                    if !referenced.contains(&location) && def.get("$schema").is_none() {
+                        let message = format!("definition `{def_key}` is not referenced by any schema");
+                        self.report("unreferenced_definition", self.lints_config.unreferenced_definition, &location, message);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Shape and discriminators of node schemas, the `oneOf` branches of `node` definitions
+    fn check_nodes(&mut self) {
+        let mut nodes = BTreeSet::new();
+        for (file_key, schema) in self.schemas {
+            let branches = schema.pointer("/$defs/node/oneOf").and_then(Value::as_array).into_iter().flatten();
+            for reference in branches.filter_map(|branch| branch.get("$ref").and_then(Value::as_str)) {
+                nodes.extend(self.resolve_ref(file_key, reference));
+            }
+        }
+
+        let mut configs = BTreeSet::new();
+        let mut node_types: BTreeMap<String, Location> = BTreeMap::new();
+        for node in &nodes {
+            let Some(schema) = self.schema_at(node) else {
+                continue;
+            };
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let required = schema.get("required").and_then(Value::as_array);
+            for property in ["id", "type", "ports", "config"] {
+                if properties.is_none_or(|properties| !properties.contains_key(property)) {
+                    let message = format!("node schema has no `{property}` property");
+                    self.report("node_shape", self.lints_config.node_shape, node, message);
+                } else if required.is_none_or(|required| !required.iter().any(|name| name == property)) {
+                    let message = format!("node schema does not require `{property}`");
+                    self.report("node_shape", self.lints_config.node_shape, node, message);
+                }
+            }
+
+            let type_location = (node.0.clone(), format!("{}/properties/type", node.1));
+            let Some(node_type) = schema.pointer("/properties/type/const").and_then(Value::as_str) else {
+                let message = "node `type` has no string `const` discriminator".to_string();
+                self.report("node_discriminator", self.lints_config.node_discriminator, &type_location, message);
+                continue;
+            };
+            if let Some(other) = node_types.get(node_type) {
+                let message = format!("node type `{node_type}` is also used by {}#{}", self.source_map.path(&other.0), other.1);
+                self.report("node_discriminator", self.lints_config.node_discriminator, &type_location, message);
+            } else {
+                node_types.insert(node_type.to_string(), node.clone());
+            }
+
+            let config = schema.pointer("/properties/config/$ref").and_then(Value::as_str)
+                .and_then(|reference| self.resolve_ref(&node.0, reference));
+            if let Some(config) = config {
+                let kind = self.schema_at(&config).and_then(|schema| schema.pointer("/properties/kind/const"));
+                if let Some(kind) = kind && kind.as_str() != Some(node_type) {
+                    let location = (config.0.clone(), format!("{}/properties/kind/const", config.1));
+                    let message = format!("config `kind` is {kind}, but the node `type` is \"{node_type}\"");
+                    self.report("node_discriminator", self.lints_config.node_discriminator, &location, message);
+                }
+                configs.insert(config);
+            }
+        }
+
+        self.check_additional_properties(&nodes, "node schemas");
+        self.check_additional_properties(&configs, "node configs");
+    }
+
+    /// Schemas of [group] without `additionalProperties: false` while others in the group have it
+    fn check_additional_properties(&mut self, group: &BTreeSet<Location>, group_name: &str) {
+        let closed = |location: &Location| {
+            self.schema_at(location).and_then(|schema| schema.get("additionalProperties")) == Some(&Value::Bool(false))
+        };
+        if !group.iter().any(closed) {
+            return;
+        }
+        for location in group.iter().filter(|location| !closed(location)).collect::<Vec<_>>() {
+            let message = format!("`additionalProperties: false` is missing, other {group_name} set it");
+            self.report("missing_additional_properties", self.lints_config.missing_additional_properties, location, message);
+        }
+    }
+
+    fn schema_at(&self, (file_key, pointer): &Location) -> Option<&'a Value> {
+        self.schemas.get(file_key)?.pointer(pointer)
+    }
+
+    /// File key and pointer of [reference] made in file [file_key]
+    fn resolve_ref(&self, file_key: &str, reference: &str) -> Option<Location> {
+        let (path, fragment) = reference.split_once('#').unwrap_or((reference, ""));
+        let target_file = resolve_file_ref(file_key, path, self.source_map.suffix())?;
+        Some((target_file, fragment.to_string()))
+    }
+}
+
+/// Calls [f] with every `$ref` of [value]
+fn collect_refs(value: &Value, f: &mut impl FnMut(&str)) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::String(reference) if key == "$ref" => f(reference),
+                    _ => collect_refs(value, f),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| collect_refs(value, f)),
+        _ => {}
+    }
+}
+
+/// Escapes [key] for a JSON pointer
+pub fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{}:{position}: #{}: {} [{}]", self.file, self.pointer, self.message, self.lint),
+            None => write!(f, "{}#{}: {} [{}]", self.file, self.pointer, self.message, self.lint),
+        }
+    }
+}
@@ -1,45 +1,175 @@
-use clap::Parser;
-use rust_generator::{generate_crate_structure, generate_rs_structures, get_bundled_schema};
-use std::collections::HashSet;
+use clap::{Args, Parser, Subcommand};
+use rust_generator::compat::compare_bundled_schemas;
+use rust_generator::config::{GeneratorConfig, LintLevel, TypesLayout, CONFIG_FILE_NAME};
+use rust_generator::docs::lint_missing_descriptions;
+use rust_generator::drafts::lower_schemas;
+use rust_generator::lint::lint_schemas;
+use rust_generator::meta_schema::{validate_bundled_meta_schemas, validate_meta_schemas};
+use rust_generator::passes::default_passes;
+use rust_generator::sources::SourceMap;
+use rust_generator::{
+    bundle_loaded_schemas, bundle_prebundled_schema, check_bundle_layout, diff_crate_files, generate_crate_files,
+    generate_rs_code, generate_rs_modules, get_bundled_schema, load_schemas, prune_unreachable, rename_runtime_fields,
+    serialize_bundled_schema, stale_crate_files, write_crate_files, Bundle, GeneratedFile, RUNTIME_ROOT,
+};
 use std::env;
 use std::ffi::OsString;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 /// Rust crate generator
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Input directory containing schema files
+#[command(version, about, long_about = None, after_help = FMT_HELP)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// The formatter needs the generated types, so it is a binary of the generated crate, not a subcommand here
+const FMT_HELP: &str = "Pipeline files are formatted by `pipeline fmt`, the binary src/bin/pipeline.rs of the generated crate:
+  cargo run --manifest-path <OUTPUT>/rust/Cargo.toml --bin pipeline -- fmt [--check] <FILE>...";
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate Rust crate from schema files
+    Generate(Box<GenerateArgs>),
+    /// Validate schema files and save them bundled into one JSON schema without generating the crate
+    Bundle(BundleArgs),
+    /// Compare two schema directories and report breaking and additive changes and their Rust API changes
+    Compat(CompatArgs),
+}
+
+/// Input schemas and how they are bundled, shared by `generate` and `bundle`
+#[derive(Args, Debug)]
+struct SchemasArgs {
+    /// Input directory containing schema files, or a pre-bundled schema file such as bundled.schema.json
     #[arg(short, long)]
     input: OsString,
 
+    /// Generator config, defaults to rust-generator.toml in the current directory if it exists
+    #[arg(long)]
+    config: Option<OsString>,
+
+    /// Suffix of schema file names, overrides `schemas.suffix`
+    #[arg(long)]
+    schema_suffix: Option<String>,
+
+    /// Glob of schema file paths relative to the input directory to read, repeatable,
+    /// overrides `schemas.include`
+    #[arg(long = "include", value_name = "GLOB")]
+    includes: Vec<String>,
+
+    /// Glob of schema file paths relative to the input directory to leave out, repeatable,
+    /// overrides `schemas.exclude`
+    #[arg(long = "exclude", value_name = "GLOB")]
+    excludes: Vec<String>,
+
+    /// Local file of a schema `$id` URI, e.g. `https://schemas.example.com/common/v1.json=vendor/common.json`,
+    /// repeatable, added to `schemas.catalog`. The path is relative to the current directory.
+    #[arg(long = "catalog", value_name = "URI=PATH", value_parser = parse_catalog_entry)]
+    catalog: Vec<(String, PathBuf)>,
+
+    /// Definition to generate types for together with the definitions it references, repeatable,
+    /// overrides `types.roots`. Without roots types are generated for every definition.
+    #[arg(long = "root", value_name = "DEFINITION")]
+    roots: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct GenerateArgs {
+    #[command(flatten)]
+    schemas: SchemasArgs,
+
     /// Output directory for generated Rust crate
     #[arg(short, long)]
     output: OsString,
 
-    /// Name of the generated Rust crate
+    /// Name of the generated Rust crate, overrides `package.name`
+    #[arg(long)]
+    package_name: Option<String>,
+
+    /// Version of the generated Rust crate, overrides `package.version`
     #[arg(long)]
-    package_name: String,
+    package_version: Option<String>,
+
+    /// Pipeline schema version carried into the crate as `SCHEMA_VERSION`, overrides `package.schema_version`
+    #[arg(long)]
+    schema_version: Option<String>,
+
+    /// Generate builders for structs, overrides `types.struct_builder`, `--struct-builder=false` turns them off
+    #[arg(long, num_args = 0..=1, default_missing_value = "true", require_equals = true)]
+    struct_builder: Option<bool>,
 
-    /// Version of the generated Rust crate
+    /// Directory of the generated crate inside the output directory, overrides `output.crate_dir`
     #[arg(long)]
-    package_version: String,
+    crate_dir: Option<String>,
+
+    /// Additionally save the bundled schema to this path, the generated crate always carries its own copy
+    #[arg(long)]
+    bundle_output: Option<OsString>,
+
+    /// Generate everything in memory and compare it with the crate on disk instead of writing,
+    /// exits with an error and prints a unified diff if anything differs
+    #[arg(long, default_value_t = false)]
+    check: bool,
 
     /// Enable verbose output
     #[arg(short, long, default_value_t = false)]
     verbose: bool
 }
 
-// cargo run -- \
+#[derive(Args, Debug)]
+struct BundleArgs {
+    #[command(flatten)]
+    schemas: SchemasArgs,
+
+    /// Path of the bundled schema file, `generate` accepts it as input
+    #[arg(short, long)]
+    output: OsString,
+
+    /// Enable verbose output
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool
+}
+
+#[derive(Args, Debug)]
+struct CompatArgs {
+    /// Directory with schema files of the released version
+    #[arg(long)]
+    old: OsString,
+
+    /// Directory with changed schema files
+    #[arg(long)]
+    new: OsString,
+
+    /// Generator config, defaults to rust-generator.toml in the current directory if it exists
+    #[arg(long)]
+    config: Option<OsString>,
+
+    /// Exit with an error if there are breaking changes
+    #[arg(long, default_value_t = false)]
+    deny_breaking: bool,
+}
+
+fn main() -> ExitCode {
+    match Cli::parse().command {
+        Command::Generate(args) => generate(*args),
+        Command::Bundle(args) => bundle(args),
+        Command::Compat(args) => compat(args),
+    }
+}
+
+// cargo run -- generate \
 //  -i ../../schemas \
 //  -o ../../packages \
 //  --package-name agentic-pipeline-schemas \
 //  --package-version "0.0.1" \
+//  --schema-version "1" \
 //  -v
-fn main() {
-    let args = Args::parse();
+// add --check to verify that the crate on disk is up to date
+fn generate(args: GenerateArgs) -> ExitCode {
     if args.verbose {
         println!("{:?}", args);
     }
@@ -48,41 +178,288 @@ fn main() {
         println!("Generating Rust crate...");
     }
 
+    let mut config = load_schemas_config(&args.schemas);
+    if let Some(package_name) = &args.package_name {
+        config.package.name = Some(package_name.clone());
+    }
+    if let Some(package_version) = &args.package_version {
+        config.package.version = Some(package_version.clone());
+    }
+    if let Some(schema_version) = &args.schema_version {
+        config.package.schema_version = Some(schema_version.clone());
+    }
+    if let Some(struct_builder) = args.struct_builder {
+        config.types.struct_builder = struct_builder;
+    }
+    if let Some(crate_dir) = &args.crate_dir {
+        config.output.crate_dir = crate_dir.clone();
+    }
+
+    let Some((bundle, source_map)) = validated_bundle(&args.schemas, &config, args.verbose) else {
+        return ExitCode::FAILURE;
+    };
+    let with_runtime = bundle.sources.contains_key(RUNTIME_ROOT);
+    if args.verbose && !with_runtime {
+        println!("Definition `{RUNTIME_ROOT}` is pruned, the crate is generated without runtime modules");
+    }
+
     let curr_path = env::current_dir().unwrap_or_else(|err| panic!("Failed to get current dir: {err}"));
-    let crate_structure = generate_crate_structure(
-        &curr_path.join("src").into_os_string(),
-        &args.output,
-        &args.package_name,
-        &args.package_version
-    ).unwrap_or_else(|err| panic!("Failed to generate crate structure: {err}"));
+    let mut files = generate_crate_files(&curr_path.join("src").into_os_string(), &config, with_runtime)
+        .unwrap_or_else(|err| panic!("Failed to generate crate structure: {err}"));
 
-    if args.verbose {
-        println!("Generating bundled schema...");
-    }
-    let bundled_file_name = "bundled.schema.json";
-    let skip_file_names = HashSet::from([OsString::from(bundled_file_name)]);
-    let bundled_schema = get_bundled_schema(&args.input, &skip_file_names)
-        .unwrap_or_else(|err| panic!("Failed to get bundled schema: {err}"));
-
-    // for debugging purposes: save bundled schema to [bundled_file_name]
-    let bundled_schema_filepath = PathBuf::from(&args.input).join(bundled_file_name);
-    let mut file = File::create(&bundled_schema_filepath)
-        .unwrap_or_else(|err| {
-            let filepath = bundled_schema_filepath.to_str().unwrap();
-            panic!("Failed to create bundled schema file {filepath}: {err}")
-        });
-    let serialized = serde_json::to_string_pretty(&bundled_schema)
+    let serialized = serialize_bundled_schema(&bundle.schema)
         .unwrap_or_else(|err| panic!("Failed to serialize bundled schema: {err}"));
-    file.write_all(serialized.as_bytes())
-        .unwrap_or_else(|err| panic!("Failed to write bundled schema to file: {err}"));
+
+    if !args.check && let Some(bundle_output) = &args.bundle_output {
+        let mut file = File::create(bundle_output)
+            .unwrap_or_else(|err| panic!("Failed to create bundled schema file {}: {err}", bundle_output.display()));
+        file.write_all(serialized.as_bytes())
+            .unwrap_or_else(|err| panic!("Failed to write bundled schema to file: {err}"));
+    }
+    files.push(GeneratedFile { path: PathBuf::from("bundled.schema.json"), content: serialized });
 
     if args.verbose {
         println!("Generating rust structures...");
     }
-    generate_rs_structures(bundled_schema, &crate_structure.src_path)
-        .unwrap_or_else(|err| panic!("Failed to generate Rust structures: {}", err));
+    let description_issues = lint_missing_descriptions(&bundle, &source_map, &config.lints);
+    for issue in &description_issues {
+        match issue.level {
+            LintLevel::Deny => eprintln!("error: {issue}"),
+            _ => eprintln!("warning: {issue}"),
+        }
+    }
+    if description_issues.iter().any(|issue| issue.level == LintLevel::Deny) {
+        eprintln!("Schemas have {} definition(s) without description", description_issues.len());
+        return ExitCode::FAILURE;
+    }
+    let passes = default_passes(&bundle.schema, &config.types)
+        .unwrap_or_else(|err| panic!("Failed to configure rewrites of generated code: {err}"));
+    let generated_types = match config.output.types_layout {
+        TypesLayout::Modules => generate_rs_modules(&bundle, &config.types, &passes),
+        TypesLayout::Single => generate_rs_code(&bundle, &config.types, &passes),
+    };
+    let generated_types = match generated_types {
+        Ok(generated_types) => generated_types,
+        Err(err) => {
+            eprintln!("error: {err}");
+            eprintln!("Rust structures can not be generated");
+            return ExitCode::FAILURE;
+        }
+    };
+    for skipped_derive in &generated_types.skipped_derives {
+        eprintln!("warning: {skipped_derive}");
+    }
+    if let Err(err) = rename_runtime_fields(&mut files, &generated_types.field_renames) {
+        eprintln!("error: {err}");
+        eprintln!("Runtime modules can not use the renamed fields");
+        return ExitCode::FAILURE;
+    }
+    files.extend(generated_types.files);
+
+    let crate_path = PathBuf::from(&args.output).join(&config.output.crate_dir);
+    let stale_files = stale_crate_files(&crate_path, &files)
+        .unwrap_or_else(|err| panic!("Failed to list type files in {}: {err}", crate_path.display()));
+    if args.check {
+        let diffs = diff_crate_files(&crate_path, &files)
+            .unwrap_or_else(|err| panic!("Failed to compare generated crate with {}: {err}", crate_path.display()));
+        if !diffs.is_empty() || !stale_files.is_empty() {
+            for diff in &diffs {
+                print!("{diff}");
+            }
+            for stale_file in &stale_files {
+                println!("Stale file {}", stale_file.display());
+            }
+            eprintln!("Generated crate is out of date: {} file(s) differ", diffs.len() + stale_files.len());
+            return ExitCode::FAILURE;
+        }
+        if args.verbose {
+            println!("Generated crate is up to date");
+        }
+        return ExitCode::SUCCESS;
+    }
+    write_crate_files(&crate_path, &files)
+        .unwrap_or_else(|err| panic!("Failed to write crate files: {err}"));
+    for stale_file in &stale_files {
+        if args.verbose {
+            println!("Removing stale file {}", stale_file.display());
+        }
+        std::fs::remove_file(stale_file)
+            .unwrap_or_else(|err| panic!("Failed to remove stale file {}: {err}", stale_file.display()));
+    }
+    // directories left empty after switching to a single types.rs or to roots without the runtime
+    let _ = std::fs::remove_dir(crate_path.join("src").join("types"));
+    let _ = std::fs::remove_dir(crate_path.join("src").join("bin"));
 
     if args.verbose {
         println!("Done!");
     }
-}
\ No newline at end of file
+    ExitCode::SUCCESS
+}
+
+// cargo run -- bundle -i ../../schemas -o ../../bundled.schema.json
+// the bundle is accepted by `generate -i ../../bundled.schema.json`, prune it with `--root` there
+// to keep the types in the same modules as generating from the directory
+fn bundle(args: BundleArgs) -> ExitCode {
+    if args.verbose {
+        println!("{:?}", args);
+    }
+
+    let config = load_schemas_config(&args.schemas);
+    let Some((bundle, _)) = validated_bundle(&args.schemas, &config, args.verbose) else {
+        return ExitCode::FAILURE;
+    };
+    let serialized = serialize_bundled_schema(&bundle.schema)
+        .unwrap_or_else(|err| panic!("Failed to serialize bundled schema: {err}"));
+    let mut file = File::create(&args.output)
+        .unwrap_or_else(|err| panic!("Failed to create bundled schema file {}: {err}", args.output.display()));
+    file.write_all(serialized.as_bytes())
+        .unwrap_or_else(|err| panic!("Failed to write bundled schema to file: {err}"));
+
+    if args.verbose {
+        println!("Saved {} definition(s) to {}", bundle.sources.len(), args.output.display());
+    }
+    ExitCode::SUCCESS
+}
+
+// cargo run -- compat --old ../../released-schemas --new ../../schemas
+fn compat(args: CompatArgs) -> ExitCode {
+    let config = load_config(args.config.as_ref());
+    let old_schema = get_bundled_schema(&args.old, &config.schemas)
+        .unwrap_or_else(|err| panic!("Failed to get bundled schema of {}: {err}", args.old.display()));
+    let new_schema = get_bundled_schema(&args.new, &config.schemas)
+        .unwrap_or_else(|err| panic!("Failed to get bundled schema of {}: {err}", args.new.display()));
+
+    let report = compare_bundled_schemas(&old_schema, &new_schema);
+    print!("{report}");
+
+    if args.deny_breaking && !report.breaking.is_empty() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Loads the config of [args] with the overrides of its flags
+fn load_schemas_config(args: &SchemasArgs) -> GeneratorConfig {
+    let mut config = load_config(args.config.as_ref());
+    if let Some(schema_suffix) = &args.schema_suffix {
+        config.schemas.suffix = schema_suffix.clone();
+    }
+    if !args.includes.is_empty() {
+        config.schemas.include = args.includes.clone();
+    }
+    if !args.excludes.is_empty() {
+        config.schemas.exclude = args.excludes.clone();
+    }
+    for (uri, path) in &args.catalog {
+        let path = std::path::absolute(path).unwrap_or_else(|err| panic!("Failed to resolve {}: {err}", path.display()));
+        config.schemas.catalog.insert(uri.clone(), path.to_string_lossy().into_owned());
+    }
+    if !args.roots.is_empty() {
+        config.types.roots = args.roots.clone();
+    }
+    config
+}
+
+/// Reads the schemas of [args], validates, lowers and lints them, then bundles them and prunes the bundle
+/// to `types.roots` of [config]. A pre-bundled schema file goes through the same checks and is not bundled
+/// again. Returns the bundle with the source map of the read files, none if there are errors,
+/// they are printed together with lint warnings.
+fn validated_bundle(args: &SchemasArgs, config: &GeneratorConfig, verbose: bool) -> Option<(Bundle, SourceMap)> {
+    let (schemas, source_map) = match load_schemas(&args.input, &config.schemas) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("error: {err}");
+            eprintln!("Schemas can not be read");
+            return None;
+        }
+    };
+    if verbose {
+        println!("Linting schemas...");
+    }
+    let bundled_input = Path::new(&args.input).is_file();
+    let mut lint_issues = if bundled_input {
+        let [(file_key, schema)] = Vec::from_iter(&schemas).try_into().expect("a pre-bundled schema is loaded as one document");
+        validate_bundled_meta_schemas(file_key, schema, &source_map, &config.schemas, &config.lints)
+    } else {
+        validate_meta_schemas(&schemas, &source_map, &config.schemas, &config.lints)
+    };
+    let schemas = if lint_issues.iter().any(|issue| issue.level == LintLevel::Deny) {
+        // files that are not valid for their draft can not be lowered, linting the rest is still useful
+        schemas
+    } else {
+        match lower_schemas(&schemas, &source_map) {
+            Ok(lowered) => lowered,
+            Err(err) => {
+                eprintln!("error: {err}");
+                eprintln!("Schemas can not be lowered to draft-07");
+                return None;
+            }
+        }
+    };
+    lint_issues.extend(lint_schemas(&schemas, &source_map, &config.lints));
+    for issue in &lint_issues {
+        match issue.level {
+            LintLevel::Deny => eprintln!("error: {issue}"),
+            _ => eprintln!("warning: {issue}"),
+        }
+    }
+    let lint_errors = lint_issues.iter().filter(|issue| issue.level == LintLevel::Deny).count();
+    if lint_errors > 0 {
+        eprintln!("Schemas have {lint_errors} lint error(s)");
+        return None;
+    }
+
+    let mut bundle = if bundled_input {
+        if verbose {
+            println!("Reading pre-bundled schema...");
+        }
+        let [(file_key, schema)] = Vec::from_iter(&schemas).try_into().expect("a pre-bundled schema is loaded as one document");
+        bundle_prebundled_schema(file_key, schema)
+    } else {
+        if verbose {
+            println!("Generating bundled schema...");
+        }
+        bundle_loaded_schemas(&schemas, &config.schemas.suffix)
+    };
+    if let Err(err) = check_bundle_layout(&bundle.schema) {
+        eprintln!("error: {err}");
+        return None;
+    }
+    if !config.types.roots.is_empty() {
+        let pruned = match prune_unreachable(&mut bundle.schema, &config.types.roots) {
+            Ok(pruned) => pruned,
+            Err(err) => {
+                eprintln!("error: types.roots: {err}");
+                return None;
+            }
+        };
+        bundle.sources.retain(|def_key, _| !pruned.contains(def_key));
+        bundle.locations.retain(|def_key, _| !pruned.contains(def_key));
+        if verbose {
+            println!("Roots {} leave out {} definition(s)", config.types.roots.join(", "), pruned.len());
+            for def_key in &pruned {
+                println!("Pruned definition `{def_key}`");
+            }
+        }
+    }
+    Some((bundle, source_map))
+}
+
+/// Parses a `--catalog` value, the URI and the path are separated by the last `=`
+fn parse_catalog_entry(value: &str) -> Result<(String, PathBuf), String> {
+    match value.rsplit_once('=') {
+        Some((uri, path)) if !uri.is_empty() && !path.is_empty() => Ok((uri.to_string(), PathBuf::from(path))),
+        _ => Err(format!("expected URI=PATH, found `{value}`")),
+    }
+}
+
+/// Loads config from [path] or from [CONFIG_FILE_NAME] if it exists, otherwise returns the default one
+fn load_config(path: Option<&OsString>) -> GeneratorConfig {
+    let path = match path {
+        Some(path) => Path::new(path),
+        None if Path::new(CONFIG_FILE_NAME).exists() => Path::new(CONFIG_FILE_NAME),
+        None => return GeneratorConfig::default(),
+    };
+    GeneratorConfig::load(path).unwrap_or_else(|err| panic!("Failed to load config: {err}"))
+}
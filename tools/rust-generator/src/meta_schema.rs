@@ -0,0 +1,288 @@
+use crate::config::{LintLevel, LintsConfig, SchemasConfig};
+use crate::drafts::Draft;
+use crate::lint::{escape_pointer, LintIssue};
+use crate::sources::SourceMap;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Meta-schema embedded in the generator, schema files are checked against it offline
+struct MetaSchema {
+    draft: Draft,
+    schema: Value,
+    /// Keywords validated as other keywords of the meta-schema
+    aliases: &'static [(&'static str, &'static str)],
+}
+
+fn meta_schemas() -> Vec<MetaSchema> {
+    let parse = |json: &str| serde_json::from_str::<Value>(json).expect("embedded meta-schema");
+    vec![
+        MetaSchema {
+            draft: Draft::Draft07,
+            schema: parse(include_str!("meta_schemas/draft-07.json")),
+            // draft-07 files of this repo keep their definitions in `$defs` as later drafts do,
+            // the bundler and typify read them from there
+            aliases: &[("$defs", "definitions")],
+        },
+        MetaSchema { draft: Draft::Draft201909, schema: parse(include_str!("meta_schemas/draft-2019-09.json")), aliases: &[] },
+        MetaSchema { draft: Draft::Draft202012, schema: parse(include_str!("meta_schemas/draft-2020-12.json")), aliases: &[] },
+    ]
+}
+
+/// Validates loaded [schemas] by file key against the meta-schemas declared by their `$schema`.
+/// Reports keyword values the meta-schema does not allow and keywords it does not define,
+/// except vendor keywords of [schemas_config]. [source_map] has the paths and positions of the files.
+pub fn validate_meta_schemas(
+    schemas: &BTreeMap<String, Value>,
+    source_map: &SourceMap,
+    schemas_config: &SchemasConfig,
+    lints_config: &LintsConfig
+) -> Vec<LintIssue> {
+    let meta_schemas = meta_schemas();
+    let mut issues = Vec::new();
+    for (file_key, schema) in schemas {
+        let draft = match Draft::of(schema) {
+            Ok(draft) => draft,
+            Err(message) => {
+                issues.push(lint_issue("invalid_schema", "/$schema".to_string(), message, file_key, source_map, lints_config));
+                continue;
+            }
+        };
+        let document_issues = validate_document(&meta_schemas, draft, schema, "", &schemas_config.vendor_keywords);
+        issues.extend(document_issues.into_iter()
+            .map(|(lint, pointer, message)| lint_issue(lint, pointer, message, file_key, source_map, lints_config)));
+    }
+    sorted_issues(issues)
+}
+
+/// Same as [validate_meta_schemas] for the pre-bundled [schema] read from the file [file_key]. Each
+/// definition of its `$defs` is validated against the meta-schema of its own `$schema`, or of the bundle's
+/// one.
+pub fn validate_bundled_meta_schemas(
+    file_key: &str,
+    schema: &Value,
+    source_map: &SourceMap,
+    schemas_config: &SchemasConfig,
+    lints_config: &LintsConfig
+) -> Vec<LintIssue> {
+    let meta_schemas = meta_schemas();
+    let mut issues = Vec::new();
+    let issue = |(lint, pointer, message)| lint_issue(lint, pointer, message, file_key, source_map, lints_config);
+    let bundle_draft = match Draft::of(schema) {
+        Ok(draft) => draft,
+        Err(message) => return sorted_issues(vec![issue(("invalid_schema", "/$schema".to_string(), message))]),
+    };
+    let Some(defs) = schema.get("$defs").and_then(Value::as_object) else {
+        return sorted_issues(vec![issue(("invalid_schema", String::new(), "bundled schema has no `$defs`".to_string()))]);
+    };
+    for (def_key, def) in defs {
+        let pointer = format!("/$defs/{}", escape_pointer(def_key));
+        let draft = match def.get("$schema").map(|_| Draft::of(def)).unwrap_or(Ok(bundle_draft)) {
+            Ok(draft) => draft,
+            Err(message) => {
+                issues.push(issue(("invalid_schema", format!("{pointer}/$schema"), message)));
+                continue;
+            }
+        };
+        issues.extend(validate_document(&meta_schemas, draft, def, &pointer, &schemas_config.vendor_keywords).into_iter().map(issue));
+    }
+    sorted_issues(issues)
+}
+
+/// Lints, pointers and messages of issues of [document] at [pointer] against the meta-schema of [draft]
+fn validate_document(
+    meta_schemas: &[MetaSchema],
+    draft: Draft,
+    document: &Value,
+    pointer: &str,
+    vendor_keywords: &[String]
+) -> Vec<(&'static str, String, String)> {
+    let meta_schema = meta_schemas.iter().find(|meta_schema| meta_schema.draft == draft).expect("meta-schema of every draft");
+    let uri = draft.uri();
+
+    let mut validator = Validator { meta_schema, vendor_keywords, unknown_keywords: Vec::new() };
+    let mut issues = validator.validate(document, &meta_schema.schema, pointer)
+        .into_iter()
+        .map(|(pointer, message)| ("invalid_schema", pointer, message))
+        .collect::<Vec<_>>();
+    for (pointer, keyword) in validator.unknown_keywords {
+        issues.push(("unknown_keyword", pointer, format!("`{keyword}` is not a keyword of {uri}")));
+    }
+    issues
+}
+
+/// Issue of [lint] at [pointer] of the file [file_key] with the level of [lints_config]
+fn lint_issue(lint: &'static str, pointer: String, message: String, file_key: &str, source_map: &SourceMap, lints_config: &LintsConfig) -> LintIssue {
+    let level = match lint {
+        "unknown_keyword" => lints_config.unknown_keyword,
+        _ => lints_config.invalid_schema,
+    };
+    LintIssue { lint, level, file: source_map.path(file_key), position: source_map.position(file_key, &pointer), pointer, message }
+}
+
+/// [issues] without allowed ones, ordered by file and pointer
+fn sorted_issues(mut issues: Vec<LintIssue>) -> Vec<LintIssue> {
+    issues.retain(|issue| issue.level != LintLevel::Allow);
+    issues.sort_by(|a, b| (&a.file, &a.pointer, a.lint).cmp(&(&b.file, &b.pointer, b.lint)));
+    issues.dedup();
+    issues
+}
+
+/// Validator of the subset of JSON Schema the embedded meta-schemas use
+struct Validator<'a> {
+    meta_schema: &'a MetaSchema,
+    vendor_keywords: &'a [String],
+    /// Pointers and names of keywords the meta-schema does not define
+    unknown_keywords: Vec<(String, String)>,
+}
+
+impl Validator<'_> {
+    /// Pointers and messages of errors of [instance] at [pointer] against [meta]
+    fn validate(&mut self, instance: &Value, meta: &Value, pointer: &str) -> Vec<(String, String)> {
+        let meta = match meta {
+            Value::Bool(true) => return Vec::new(),
+            Value::Bool(false) => return vec![(pointer.to_string(), "no value is allowed here".to_string())],
+            Value::Object(meta) => meta,
+            _ => return Vec::new(),
+        };
+
+        let mut errors = Vec::new();
+        if let Some(Value::String(reference)) = meta.get("$ref") {
+            let root = &self.meta_schema.schema;
+            let target = reference.strip_prefix('#').and_then(|fragment| root.pointer(fragment));
+            match target {
+                Some(target) => errors.extend(self.validate(instance, target, pointer)),
+                None => panic!("unresolved `$ref` {reference} in meta-schema {}", self.meta_schema.draft.uri()),
+            }
+        }
+        for branch in meta.get("allOf").and_then(Value::as_array).into_iter().flatten() {
+            errors.extend(self.validate(instance, branch, pointer));
+        }
+        if let Some(Value::Array(branches)) = meta.get("anyOf") {
+            let branch_errors = branches.iter()
+                .map(|branch| self.validate(instance, branch, pointer))
+                .collect::<Vec<_>>();
+            if !branch_errors.iter().any(Vec::is_empty) {
+                errors.extend(branch_errors.into_iter().min_by_key(Vec::len).unwrap_or_default());
+            }
+        }
+
+        if let Some(expected) = meta.get("type") && !matches_type(instance, expected) {
+            let expected = match expected {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(" or "),
+                _ => expected.as_str().unwrap_or_default().to_string(),
+            };
+            errors.push((pointer.to_string(), format!("expected {expected}, found {}", type_name(instance))));
+            return errors;
+        }
+        if let Some(Value::Array(values)) = meta.get("enum") && !values.contains(instance) {
+            let values = values.iter().map(Value::to_string).collect::<Vec<_>>().join(", ");
+            errors.push((pointer.to_string(), format!("{instance} is not one of {values}")));
+        }
+
+        match instance {
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                if let Some(minimum) = meta.get("minimum").and_then(Value::as_f64) && number < minimum {
+                    errors.push((pointer.to_string(), format!("{number} is less than {minimum}")));
+                }
+                if let Some(minimum) = meta.get("exclusiveMinimum").and_then(Value::as_f64) && number <= minimum {
+                    errors.push((pointer.to_string(), format!("{number} is not greater than {minimum}")));
+                }
+            }
+            Value::String(string) => {
+                if meta.get("format").and_then(Value::as_str) == Some("regex")
+                    && let Err(err) = regress::Regex::new(string)
+                {
+                    errors.push((pointer.to_string(), format!("invalid regular expression: {err}")));
+                }
+                if let Some(pattern) = meta.get("pattern").and_then(Value::as_str) {
+                    let regex = regress::Regex::new(pattern).expect("pattern of the embedded meta-schema");
+                    if regex.find(string).is_none() {
+                        errors.push((pointer.to_string(), format!("\"{string}\" does not match `{pattern}`")));
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(min_items) = meta.get("minItems").and_then(Value::as_u64) && (items.len() as u64) < min_items {
+                    errors.push((pointer.to_string(), format!("expected at least {min_items} item(s)")));
+                }
+                if meta.get("uniqueItems") == Some(&Value::Bool(true)) {
+                    for (index, item) in items.iter().enumerate() {
+                        if items[..index].contains(item) {
+                            errors.push((format!("{pointer}/{index}"), format!("{item} is a duplicate")));
+                        }
+                    }
+                }
+                if let Some(items_meta) = meta.get("items") {
+                    for (index, item) in items.iter().enumerate() {
+                        errors.extend(self.validate(item, items_meta, &format!("{pointer}/{index}")));
+                    }
+                }
+            }
+            Value::Object(object) => errors.extend(self.validate_object(object, meta, pointer)),
+            _ => {}
+        }
+        errors
+    }
+
+    fn validate_object(
+        &mut self,
+        object: &serde_json::Map<String, Value>,
+        meta: &serde_json::Map<String, Value>,
+        pointer: &str
+    ) -> Vec<(String, String)> {
+        let is_schema = self.meta_schema.schema.as_object().is_some_and(|root| std::ptr::eq(root, meta));
+        let properties = meta.get("properties").and_then(Value::as_object);
+        let mut errors = Vec::new();
+        for (key, value) in object {
+            let key_pointer = format!("{pointer}/{}", escape_pointer(key));
+            if let Some(property_names) = meta.get("propertyNames") {
+                errors.extend(self.validate(&Value::String(key.clone()), property_names, &key_pointer));
+            }
+
+            let alias = is_schema
+                .then(|| self.meta_schema.aliases.iter().find(|(alias, _)| alias == key))
+                .flatten()
+                .map(|(_, keyword)| *keyword);
+            let property_meta = properties.and_then(|properties| properties.get(alias.unwrap_or(key)));
+            if let Some(property_meta) = property_meta {
+                errors.extend(self.validate(value, property_meta, &key_pointer));
+            } else if let Some(additional) = meta.get("additionalProperties") {
+                errors.extend(self.validate(value, additional, &key_pointer));
+            } else if is_schema && !self.is_vendor_keyword(key) {
+                self.unknown_keywords.push((key_pointer, key.clone()));
+            }
+        }
+        errors
+    }
+
+    fn is_vendor_keyword(&self, keyword: &str) -> bool {
+        self.vendor_keywords.iter().any(|vendor_keyword| match vendor_keyword.strip_suffix('*') {
+            Some(prefix) => keyword.starts_with(prefix),
+            None => keyword == vendor_keyword,
+        })
+    }
+}
+
+fn matches_type(instance: &Value, expected: &Value) -> bool {
+    match expected {
+        Value::String(expected) => match expected.as_str() {
+            "integer" => instance.as_f64().is_some_and(|number| number.fract() == 0.0),
+            "number" => instance.is_number(),
+            expected => type_name(instance) == expected,
+        },
+        Value::Array(types) => types.iter().any(|expected| matches_type(instance, expected)),
+        _ => true,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
@@ -0,0 +1,84 @@
+use crate::config::TypesConfig;
+use crate::definitions::{definition_type_name, generated_type_name};
+use crate::passes::type_name;
+use std::collections::{BTreeMap, VecDeque};
+use syn::visit::Visit;
+use syn::Item;
+
+/// Splits generated [file] into a module per schema file by [sources] of the definitions.
+/// Definition types go to the module of the file their definition comes from, under their name
+/// after renames and under the generated name an alias keeps. Types typify generates for schemas
+/// nested in a definition, e.g. `AgentConfigOutputMapping`, go with the first definition type
+/// that uses them, impls go with their type. Everything else, such as the `error` and `builder`
+/// modules, stays in the returned root file.
+pub fn split_by_source(
+    file: syn::File,
+    sources: &BTreeMap<String, String>,
+    types_config: &TypesConfig
+) -> (syn::File, BTreeMap<String, syn::File>) {
+    let mut owners = BTreeMap::new();
+    for (def_key, source) in sources {
+        owners.insert(generated_type_name(def_key), source.clone());
+        owners.insert(definition_type_name(def_key, types_config), source.clone());
+    }
+
+    // types used by the fields, variants and aliases of each type, in the order of the file
+    let uses = file.items.iter()
+        .filter(|item| matches!(item, Item::Struct(_) | Item::Enum(_) | Item::Type(_)))
+        .filter_map(|item| {
+            let mut collector = TypeUses::default();
+            collector.visit_item(item);
+            Some((type_name(item)?, collector.names))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut queue = file.items.iter()
+        .filter_map(type_name)
+        .filter(|name| owners.contains_key(name))
+        .collect::<VecDeque<_>>();
+    while let Some(name) = queue.pop_front() {
+        let source = owners[&name].clone();
+        for used in uses.get(&name).into_iter().flatten() {
+            if uses.contains_key(used) && !owners.contains_key(used) {
+                owners.insert(used.clone(), source.clone());
+                queue.push_back(used.clone());
+            }
+        }
+    }
+
+    let mut root = syn::File { shebang: file.shebang, attrs: file.attrs, items: Vec::new() };
+    let mut modules: BTreeMap<String, syn::File> = BTreeMap::new();
+    for item in file.items {
+        match type_name(&item).and_then(|type_name| owners.get(&type_name)) {
+            Some(source) => modules.entry(source.clone())
+                .or_insert_with(|| syn::File { shebang: None, attrs: Vec::new(), items: Vec::new() })
+                .items
+                .push(item),
+            None => root.items.push(item),
+        }
+    }
+    (root, modules)
+}
+
+/// Identifier of the module for schema file [name], raw for keywords such as `while`
+pub fn module_ident(name: &str) -> String {
+    if syn::parse_str::<syn::Ident>(name).is_ok() {
+        name.to_string()
+    } else {
+        format!("r#{name}")
+    }
+}
+
+/// Last path segments of the types an item uses, e.g. `Port` of `Vec<Port>`
+#[derive(Default)]
+struct TypeUses {
+    names: Vec<String>,
+}
+
+impl<'ast> Visit<'ast> for TypeUses {
+    fn visit_type_path(&mut self, type_path: &'ast syn::TypePath) {
+        if let Some(segment) = type_path.path.segments.last() {
+            self.names.push(segment.ident.to_string());
+        }
+        syn::visit::visit_type_path(self, type_path);
+    }
+}
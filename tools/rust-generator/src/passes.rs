@@ -0,0 +1,362 @@
+use crate::config::TypesConfig;
+use crate::definitions::definition_type_name;
+use crate::docs::SchemaDocs;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::visit_mut::VisitMut;
+use syn::{Attribute, Item, Token};
+
+/// Rewrite of the typify output done on the syntax tree before it is printed
+pub trait AstPass {
+    fn apply(&self, file: &mut syn::File) -> Result<(), Box<dyn Error>>;
+}
+
+/// Passes the generator runs by default on types generated from bundled [schema], in order
+pub fn default_passes(schema: &Value, types_config: &TypesConfig) -> Result<Vec<Box<dyn AstPass>>, Box<dyn Error>> {
+    let mut passes: Vec<Box<dyn AstPass>> = vec![
+        Box::new(StripDocComments),
+        Box::new(SchemaDocs::new(schema, types_config)),
+        Box::new(DiscriminatedUnions::new(schema, types_config)),
+        Box::new(SimplifyPaths),
+    ];
+    if !types_config.attributes.is_empty() {
+        passes.push(Box::new(InjectAttributes::parse(None, &types_config.attributes)?));
+    }
+    for (type_name, patch) in &types_config.patches {
+        if !patch.attributes.is_empty() {
+            passes.push(Box::new(InjectAttributes::parse(Some(type_name.clone()), &patch.attributes)?));
+        }
+    }
+    passes.push(Box::new(SortItems));
+    Ok(passes)
+}
+
+pub fn apply_passes(file: &mut syn::File, passes: &[Box<dyn AstPass>]) -> Result<(), Box<dyn Error>> {
+    for pass in passes {
+        pass.apply(file)?;
+    }
+    Ok(())
+}
+
+/// Removes the doc comments typify writes on generated items, fields and variants,
+/// they repeat the JSON schema of the type. [SchemaDocs] writes docs from the schema instead.
+pub struct StripDocComments;
+
+impl AstPass for StripDocComments {
+    fn apply(&self, file: &mut syn::File) -> Result<(), Box<dyn Error>> {
+        let is_doc = |attr: &Attribute| attr.path().is_ident("doc");
+        for item in &mut file.items {
+            match item {
+                Item::Struct(item) => {
+                    item.attrs.retain(|attr| !is_doc(attr));
+                    item.fields.iter_mut().for_each(|field| field.attrs.retain(|attr| !is_doc(attr)));
+                }
+                Item::Enum(item) => {
+                    item.attrs.retain(|attr| !is_doc(attr));
+                    for variant in &mut item.variants {
+                        variant.attrs.retain(|attr| !is_doc(attr));
+                        variant.fields.iter_mut().for_each(|field| field.attrs.retain(|attr| !is_doc(attr)));
+                    }
+                }
+                Item::Type(item) => item.attrs.retain(|attr| !is_doc(attr)),
+                Item::Impl(item) => item.attrs.retain(|attr| !is_doc(attr)),
+                Item::Mod(item) => item.attrs.retain(|attr| !is_doc(attr)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes `oneOf` unions of `$ref`s by a discriminator instead of trying each variant in turn.
+/// Every referenced definition must require the same property with a distinct string `const`,
+/// e.g. `type` of the nodes. typify derives `Deserialize` on such a union with `#[serde(untagged)]`,
+/// which picks the first variant that accepts the value, a node with only the required fields may
+/// match the wrong one. The derive is replaced with an impl that matches on the discriminator.
+pub struct DiscriminatedUnions {
+    /// Type name of the union to the discriminator property and the variant type name by its value
+    unions: BTreeMap<String, (String, BTreeMap<String, String>)>,
+}
+
+impl DiscriminatedUnions {
+    pub fn new(schema: &Value, types_config: &TypesConfig) -> Self {
+        let empty = Map::new();
+        let defs = schema.get("$defs").and_then(Value::as_object).unwrap_or(&empty);
+        let unions = defs.iter()
+            .filter_map(|(def_key, def)| {
+                let discriminated = discriminator(def, defs, types_config)?;
+                Some((definition_type_name(def_key, types_config), discriminated))
+            })
+            .collect();
+        DiscriminatedUnions { unions }
+    }
+}
+
+/// Discriminator property of the `oneOf` of `$ref`s [def] and the type names of its branches by value
+fn discriminator(def: &Value, defs: &Map<String, Value>, types_config: &TypesConfig) -> Option<(String, BTreeMap<String, String>)> {
+    let branches = def.get("oneOf")?.as_array()?
+        .iter()
+        .map(|branch| {
+            let def_key = branch.get("$ref")?.as_str()?.strip_prefix("#/$defs/")?;
+            Some((def_key, defs.get(def_key)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let (_, first) = branches.first()?;
+    let required = |branch: &Value, property: &str| {
+        branch.get("required").and_then(Value::as_array).is_some_and(|required| required.iter().any(|name| name == property))
+    };
+    first.get("properties")?.as_object()?.keys().find_map(|property| {
+        let mut variants = BTreeMap::new();
+        for (def_key, branch) in &branches {
+            let value = branch.pointer(&format!("/properties/{property}/const"))?.as_str()?;
+            if !required(branch, property) || variants.insert(value.to_string(), definition_type_name(def_key, types_config)).is_some() {
+                return None;
+            }
+        }
+        Some((property.clone(), variants))
+    })
+}
+
+impl AstPass for DiscriminatedUnions {
+    fn apply(&self, file: &mut syn::File) -> Result<(), Box<dyn Error>> {
+        let mut impls = Vec::new();
+        for item in &mut file.items {
+            let Item::Enum(item) = item else {
+                continue;
+            };
+            let Some((property, variants)) = self.unions.get(&item.ident.to_string()) else {
+                continue;
+            };
+            // variants wrap the type of their branch, e.g. `Agent(Agent)`
+            let mut arms = Vec::new();
+            for variant in &item.variants {
+                let field_type = match &variant.fields {
+                    syn::Fields::Unnamed(fields) => match fields.unnamed.first().map(|field| &field.ty) {
+                        Some(syn::Type::Path(field_type)) => field_type.path.segments.last().map(|segment| segment.ident.to_string()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let Some((value, _)) = variants.iter().find(|(_, type_name)| Some(*type_name) == field_type.as_ref()) else {
+                    return Err(format!("variant `{}::{}` has no `{property}` value", item.ident, variant.ident).into());
+                };
+                let ident = &variant.ident;
+                let arm: syn::Arm = syn::parse_quote! {
+                    Some(#value) => ::serde_json::from_value(value).map(Self::#ident).map_err(::serde::de::Error::custom),
+                };
+                arms.push(arm);
+            }
+
+            for attr in &mut item.attrs {
+                if attr.path().is_ident("derive")
+                    && let Ok(paths) = attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+                {
+                    let paths = paths.into_iter()
+                        .filter(|path| path.segments.last().is_none_or(|segment| segment.ident != "Deserialize"))
+                        .collect::<Punctuated<syn::Path, Token![,]>>();
+                    *attr = syn::parse_quote!(#[derive(#paths)]);
+                }
+            }
+            let ident = &item.ident;
+            let expected = variants.keys().map(|value| format!("`{value}`")).collect::<Vec<_>>().join(", ");
+            let unknown = format!("unknown {property} `{{other}}`, expected one of {expected}");
+            impls.push(syn::parse_quote! {
+                impl<'de> ::serde::Deserialize<'de> for #ident {
+                    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {
+                        let value = <::serde_json::Value as ::serde::Deserialize>::deserialize(deserializer)?;
+                        match value.get(#property).and_then(::serde_json::Value::as_str) {
+                            #(#arms)*
+                            Some(other) => Err(::serde::de::Error::custom(format!(#unknown))),
+                            None => Err(::serde::de::Error::missing_field(#property)),
+                        }
+                    }
+                }
+            });
+        }
+        file.items.extend(impls);
+        Ok(())
+    }
+}
+
+/// Shortens fully qualified paths: `::std::string::String` becomes `String` and
+/// `::serde_json::Value` becomes `serde_json::Value`. Paths stay qualified where a
+/// generated item has the same name, e.g. a schema type called `String` or `Result`.
+pub struct SimplifyPaths;
+
+/// Paths of prelude items that are written by their name
+const PRELUDE_PATHS: [&str; 7] = [
+    "std::string::String",
+    "std::vec::Vec",
+    "std::option::Option",
+    "std::result::Result",
+    "std::boxed::Box",
+    "std::convert::From",
+    "std::convert::TryFrom",
+];
+
+impl AstPass for SimplifyPaths {
+    fn apply(&self, file: &mut syn::File) -> Result<(), Box<dyn Error>> {
+        let item_names = file.items.iter().filter_map(item_name).collect();
+        PathSimplifier { item_names }.visit_file_mut(file);
+        Ok(())
+    }
+}
+
+struct PathSimplifier {
+    item_names: BTreeSet<String>,
+}
+
+impl VisitMut for PathSimplifier {
+    fn visit_attribute_mut(&mut self, attr: &mut Attribute) {
+        // derive paths are tokens of the attribute, they are parsed to be simplified as well
+        if attr.path().is_ident("derive")
+            && let Ok(mut paths) = attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+        {
+            paths.iter_mut().for_each(|path| self.visit_path_mut(path));
+            *attr = syn::parse_quote!(#[derive(#paths)]);
+        }
+    }
+
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        syn::visit_mut::visit_path_mut(self, path);
+        if path.leading_colon.is_none() {
+            return;
+        }
+
+        let idents = path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>();
+        let last = path.segments.last().map(|segment| segment.ident.to_string()).unwrap_or_default();
+        let full_path = idents.join("::");
+        if PRELUDE_PATHS.contains(&full_path.as_str()) && !self.item_names.contains(&last) {
+            let segment = path.segments.pop().expect("prelude path").into_value();
+            path.segments.clear();
+            path.segments.push(segment);
+            path.leading_colon = None;
+        } else if !self.item_names.contains(&idents[0]) {
+            path.leading_colon = None;
+        }
+    }
+}
+
+/// Adds attributes, e.g. `#[serde(...)]` or `#[cfg_attr(...)]`, to generated structs and enums,
+/// to all of them or only to the type with the given name
+pub struct InjectAttributes {
+    type_name: Option<String>,
+    attributes: Vec<Attribute>,
+}
+
+impl InjectAttributes {
+    pub fn new(type_name: Option<String>, attributes: Vec<Attribute>) -> Self {
+        InjectAttributes { type_name, attributes }
+    }
+
+    /// Parses [attributes] written as in source code, e.g. `#[serde(deny_unknown_fields)]`
+    pub fn parse(type_name: Option<String>, attributes: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = Vec::new();
+        for attribute in attributes {
+            let attrs = Attribute::parse_outer.parse_str(attribute)
+                .map_err(|err| format!("invalid attribute `{attribute}`: {err}"))?;
+            parsed.extend(attrs);
+        }
+        Ok(Self::new(type_name, parsed))
+    }
+}
+
+impl AstPass for InjectAttributes {
+    fn apply(&self, file: &mut syn::File) -> Result<(), Box<dyn Error>> {
+        let mut found = false;
+        for item in &mut file.items {
+            let (ident, attrs) = match item {
+                Item::Struct(item) => (&item.ident, &mut item.attrs),
+                Item::Enum(item) => (&item.ident, &mut item.attrs),
+                _ => continue,
+            };
+            if self.type_name.as_ref().is_none_or(|type_name| ident == type_name) {
+                attrs.extend(self.attributes.iter().cloned());
+                found = true;
+            }
+        }
+        if let Some(type_name) = &self.type_name && !found {
+            return Err(format!("there is no generated type `{type_name}` to add attributes to").into());
+        }
+        Ok(())
+    }
+}
+
+/// Sorts generated types by name, each followed by its impls. Other items, such as the
+/// `error` module, keep their place before or after the types.
+pub struct SortItems;
+
+impl AstPass for SortItems {
+    fn apply(&self, file: &mut syn::File) -> Result<(), Box<dyn Error>> {
+        let mut leading = Vec::new();
+        let mut trailing = Vec::new();
+        let mut groups: BTreeMap<String, Vec<Item>> = BTreeMap::new();
+        for item in file.items.drain(..) {
+            match type_name(&item) {
+                Some(type_name) => groups.entry(type_name).or_default().push(item),
+                None if groups.is_empty() => leading.push(item),
+                None => trailing.push(item),
+            }
+        }
+        for items in groups.values_mut() {
+            // the type declaration first, impls in their original order
+            items.sort_by_key(|item| matches!(item, Item::Impl(_)));
+        }
+        file.items = leading.into_iter()
+            .chain(groups.into_values().flatten())
+            .chain(trailing)
+            .collect();
+        Ok(())
+    }
+}
+
+/// Prints [file] with a blank line between each type together with its impls and other items
+pub fn print_file(file: &syn::File) -> String {
+    let mut groups: Vec<Vec<Item>> = Vec::new();
+    let mut current_type = None;
+    for item in &file.items {
+        let item_type = type_name(item);
+        let continues_group = matches!(item, Item::Impl(_)) && item_type.is_some() && item_type == current_type;
+        match groups.last_mut() {
+            Some(group) if continues_group => group.push(item.clone()),
+            _ => groups.push(vec![item.clone()]),
+        }
+        current_type = item_type;
+    }
+
+    groups.into_iter()
+        .map(|items| prettyplease::unparse(&syn::File { shebang: None, attrs: Vec::new(), items }))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Name of the type [item] declares or implements
+pub fn type_name(item: &Item) -> Option<String> {
+    match item {
+        Item::Struct(item) => Some(item.ident.to_string()),
+        Item::Enum(item) => Some(item.ident.to_string()),
+        Item::Type(item) => Some(item.ident.to_string()),
+        Item::Impl(item) => match &*item.self_ty {
+            syn::Type::Path(path) => path.path.segments.last().map(|segment| segment.ident.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Name of the item [item] declares
+fn item_name(item: &Item) -> Option<String> {
+    match item {
+        Item::Struct(item) => Some(item.ident.to_string()),
+        Item::Enum(item) => Some(item.ident.to_string()),
+        Item::Type(item) => Some(item.ident.to_string()),
+        Item::Mod(item) => Some(item.ident.to_string()),
+        Item::Fn(item) => Some(item.sig.ident.to_string()),
+        Item::Const(item) => Some(item.ident.to_string()),
+        Item::Trait(item) => Some(item.ident.to_string()),
+        _ => None,
+    }
+}
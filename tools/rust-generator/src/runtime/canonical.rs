@@ -0,0 +1,351 @@
+use crate::node::node_id;
+use crate::types::Pipeline;
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::process::ExitCode;
+use std::sync::OnceLock;
+
+const INDENT: &str = "  ";
+
+/// Prints [pipeline] in the canonical form:
+/// - object keys follow the schema property order, keys unknown to the schema are sorted;
+/// - nodes are sorted topologically from the start node, ties are broken by id;
+/// - edges are sorted by the position of their source node, then by source port and id;
+/// - two-space indentation and a trailing newline.
+pub fn canonicalize(pipeline: &Pipeline) -> String {
+    let mut value = serde_json::to_value(pipeline).expect("generated types always serialize to JSON");
+    let start_node_id = value.get("start_node_id").and_then(Value::as_str).map(str::to_string);
+    if let Some(graph) = value.get_mut("graph") {
+        sort_graph(graph, start_node_id.as_deref());
+    }
+
+    let root = bundled_schema();
+    let schema = root.get("$defs").and_then(|defs| defs.get("pipeline"));
+    let mut out = String::new();
+    write_value(&value, schema, root, 0, &mut out);
+    out.push('\n');
+    out
+}
+
+/// Entry point of the `pipeline fmt [--check] <FILE>...` command. Rewrites files in place,
+/// or with `--check` only reports files that aren't canonical.
+pub fn fmt_main(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut check = false;
+    let mut file_paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => file_paths.push(arg),
+        }
+    }
+    if file_paths.is_empty() {
+        eprintln!("Usage: pipeline fmt [--check] <FILE>...");
+        return ExitCode::from(2);
+    }
+
+    let mut failed = false;
+    for file_path in &file_paths {
+        let content = match std::fs::read_to_string(file_path) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("{file_path}: failed to read: {err}");
+                failed = true;
+                continue;
+            }
+        };
+        let pipeline = match serde_json::from_str::<Pipeline>(&content) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                eprintln!("{file_path}: failed to parse pipeline: {err}");
+                failed = true;
+                continue;
+            }
+        };
+
+        let canonical = canonicalize(&pipeline);
+        if canonical == content {
+            continue;
+        }
+        if check {
+            println!("{file_path}: not in canonical form");
+            failed = true;
+        } else if let Err(err) = std::fs::write(file_path, canonical) {
+            eprintln!("{file_path}: failed to write: {err}");
+            failed = true;
+        }
+    }
+
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
+
+/// Sorts nodes of [graph] topologically and edges by source node, recursively for while bodies
+fn sort_graph(graph: &mut Value, start_node_id: Option<&str>) {
+    let Some(graph) = graph.as_object_mut() else {
+        return;
+    };
+
+    if let Some(Value::Array(nodes)) = graph.get_mut("nodes") {
+        for node in nodes.iter_mut() {
+            if let Some(body) = node.pointer_mut("/config/body") {
+                sort_graph(body, None);
+            }
+        }
+    }
+
+    let edges = graph.get("edges").and_then(Value::as_array).cloned().unwrap_or_default();
+    let endpoints = |edge: &Value, name: &str| edge.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+
+    let Some(Value::Array(nodes)) = graph.get_mut("nodes") else {
+        return;
+    };
+    let ids = nodes.iter().map(|node| node_id(node).unwrap_or_default().to_string()).collect::<BTreeSet<_>>();
+    let mut in_degrees = ids.iter().map(|id| (id.clone(), 0usize)).collect::<BTreeMap<_, _>>();
+    let mut successors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for edge in &edges {
+        let source = endpoints(edge, "source_node_id");
+        let target = endpoints(edge, "target_node_id");
+        if ids.contains(&source) && let Some(in_degree) = in_degrees.get_mut(&target) {
+            *in_degree += 1;
+            successors.entry(source).or_default().push(target);
+        }
+    }
+
+    // Kahn's algorithm seeded with the start node; unreachable nodes and cycles
+    // are resumed from the smallest id, preferring nodes without incoming edges
+    let mut order: Vec<String> = Vec::with_capacity(ids.len());
+    let mut visited = BTreeSet::new();
+    let mut ready = BTreeSet::new();
+    if let Some(start_node_id) = start_node_id && ids.contains(start_node_id) {
+        ready.insert(start_node_id.to_string());
+    }
+    while order.len() < ids.len() {
+        if ready.is_empty() {
+            let remaining = in_degrees.iter().filter(|(id, _)| !visited.contains(*id));
+            let next = remaining.clone().find(|(_, in_degree)| **in_degree == 0)
+                .or_else(|| remaining.clone().next())
+                .map(|(id, _)| id.clone());
+            match next {
+                Some(next) => ready.insert(next),
+                None => break,
+            };
+        }
+        let Some(id) = ready.pop_first() else {
+            break;
+        };
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+        for successor in successors.get(&id).into_iter().flatten() {
+            if let Some(in_degree) = in_degrees.get_mut(successor) {
+                *in_degree = in_degree.saturating_sub(1);
+                if *in_degree == 0 && !visited.contains(successor) {
+                    ready.insert(successor.clone());
+                }
+            }
+        }
+        order.push(id);
+    }
+
+    let positions = order.iter().enumerate().map(|(position, id)| (id.as_str(), position)).collect::<BTreeMap<_, _>>();
+    let position = |id: &str| positions.get(id).copied().unwrap_or(usize::MAX);
+    nodes.sort_by_key(|node| (position(node_id(node).unwrap_or_default()), node_id(node).unwrap_or_default().to_string()));
+
+    if let Some(Value::Array(edges)) = graph.get_mut("edges") {
+        edges.sort_by_key(|edge| (
+            position(&endpoints(edge, "source_node_id")),
+            endpoints(edge, "source_port_id"),
+            endpoints(edge, "id"),
+        ));
+    }
+}
+
+fn write_value(value: &Value, schema: Option<&OrderedValue>, root: &OrderedValue, indent: usize, out: &mut String) {
+    let schema = schema.and_then(|schema| resolve_schema(schema, value, root));
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            let properties = schema.and_then(|schema| schema.get("properties"));
+            let mut keys = properties
+                .map(OrderedValue::keys)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|key| map.contains_key(*key))
+                .collect::<Vec<_>>();
+            keys.extend(map.keys().map(String::as_str).filter(|key| !keys.contains(key)).collect::<BTreeSet<_>>());
+
+            out.push_str("{\n");
+            for (index, key) in keys.iter().enumerate() {
+                let child_schema = properties
+                    .and_then(|properties| properties.get(key))
+                    .or_else(|| schema.and_then(|schema| schema.get("additionalProperties")));
+                push_indent(indent + 1, out);
+                out.push_str(&Value::String(key.to_string()).to_string());
+                out.push_str(": ");
+                write_value(&map[*key], child_schema, root, indent + 1, out);
+                if index + 1 < keys.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            push_indent(indent, out);
+            out.push('}');
+        }
+        Value::Array(vec) if !vec.is_empty() => {
+            let items = schema.and_then(|schema| schema.get("items"));
+            out.push_str("[\n");
+            for (index, item) in vec.iter().enumerate() {
+                push_indent(indent + 1, out);
+                write_value(item, items, root, indent + 1, out);
+                if index + 1 < vec.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            push_indent(indent, out);
+            out.push(']');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+/// Follows `$ref`s and picks the `oneOf`/`anyOf` branch matching [value] by its
+/// `type` or `kind` discriminator
+fn resolve_schema<'a>(schema: &'a OrderedValue, value: &Value, root: &'a OrderedValue) -> Option<&'a OrderedValue> {
+    let schema = resolve_ref(schema, root)?;
+    for keyword in ["oneOf", "anyOf"] {
+        if let Some(OrderedValue::Array(branches)) = schema.get(keyword) {
+            return branches.iter()
+                .filter_map(|branch| resolve_ref(branch, root))
+                .find(|branch| matches_discriminator(branch, value))
+                .or_else(|| branches.iter().filter_map(|branch| resolve_ref(branch, root)).find(|branch| matches_type(branch, value)));
+        }
+    }
+    Some(schema)
+}
+
+fn resolve_ref<'a>(schema: &'a OrderedValue, root: &'a OrderedValue) -> Option<&'a OrderedValue> {
+    let mut schema = schema;
+    for _ in 0..32 {
+        let Some(OrderedValue::Other(Value::String(reference))) = schema.get("$ref") else {
+            return Some(schema);
+        };
+        let name = reference.strip_prefix("#/$defs/")?;
+        schema = root.get("$defs")?.get(name)?;
+    }
+    None
+}
+
+fn matches_discriminator(schema: &OrderedValue, value: &Value) -> bool {
+    ["type", "kind"].iter().any(|discriminator| {
+        let expected = schema.get("properties")
+            .and_then(|properties| properties.get(discriminator))
+            .and_then(|property| property.get("const"));
+        matches!(expected, Some(OrderedValue::Other(expected)) if value.get(discriminator) == Some(expected))
+    })
+}
+
+fn matches_type(schema: &OrderedValue, value: &Value) -> bool {
+    let Some(OrderedValue::Other(Value::String(schema_type))) = schema.get("type") else {
+        return false;
+    };
+    match value {
+        Value::Object(_) => schema_type == "object",
+        Value::Array(_) => schema_type == "array",
+        Value::String(_) => schema_type == "string",
+        Value::Bool(_) => schema_type == "boolean",
+        Value::Number(_) => schema_type == "number" || schema_type == "integer",
+        Value::Null => schema_type == "null",
+    }
+}
+
+fn push_indent(indent: usize, out: &mut String) {
+    for _ in 0..indent {
+        out.push_str(INDENT);
+    }
+}
+
+fn bundled_schema() -> &'static OrderedValue {
+    static SCHEMA: OnceLock<OrderedValue> = OnceLock::new();
+    SCHEMA.get_or_init(|| serde_json::from_str(crate::BUNDLED_SCHEMA).expect("bundled schema is valid JSON"))
+}
+
+/// JSON value that keeps object keys in document order, so that
+/// the schema property order survives parsing
+enum OrderedValue {
+    Object(Vec<(String, OrderedValue)>),
+    Array(Vec<OrderedValue>),
+    Other(Value),
+}
+
+impl OrderedValue {
+    fn get(&self, key: &str) -> Option<&OrderedValue> {
+        match self {
+            OrderedValue::Object(entries) => entries.iter().find(|(entry_key, _)| entry_key == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        match self {
+            OrderedValue::Object(entries) => entries.iter().map(|(key, _)| key.as_str()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderedValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(OrderedValueVisitor)
+    }
+}
+
+struct OrderedValueVisitor;
+
+impl<'de> Visitor<'de> for OrderedValueVisitor {
+    type Value = OrderedValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Other(Value::Bool(value)))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Other(Value::from(value)))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Other(Value::from(value)))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Other(Value::from(value)))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Other(Value::String(value.to_string())))
+    }
+
+    fn visit_unit<E>(self) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Other(Value::Null))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OrderedValue, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(OrderedValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderedValue, A::Error> {
+        let mut entries = Vec::new();
+        while let Some((key, value)) = map.next_entry::<String, OrderedValue>()? {
+            entries.push((key, value));
+        }
+        Ok(OrderedValue::Object(entries))
+    }
+}
@@ -0,0 +1,472 @@
+use crate::node::{node_id, node_type};
+use crate::normalize::spec_parts;
+use crate::types::Pipeline;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Id of a node or an edge together with ids of the while nodes whose bodies contain it
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ScopedId {
+    pub scope: Vec<String>,
+    pub id: String,
+}
+
+/// Change of a single JSON value addressed by a JSON pointer
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldChange {
+    Added { pointer: String, value: Value },
+    Removed { pointer: String, value: Value },
+    Changed { pointer: String, old: Value, new: Value },
+}
+
+/// Expression of an `ExpressionSpec` or of a shorthand string, which has an empty format
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpressionValue {
+    pub expression: String,
+    pub format: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpressionChange {
+    pub pointer: String,
+    pub old: Option<ExpressionValue>,
+    pub new: Option<ExpressionValue>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortChange {
+    Added { id: String, direction: Value },
+    Removed { id: String, direction: Value },
+    DirectionChanged { id: String, old: Value, new: Value },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeDiff {
+    pub id: ScopedId,
+    /// Changes of node fields other than `config` and `ports`, e.g. `/type`
+    pub fields: Vec<FieldChange>,
+    /// Config changes except expressions and while bodies, pointers are relative to `config`
+    pub config: Vec<FieldChange>,
+    pub ports: Vec<PortChange>,
+    /// Changes of expression fields, pointers are relative to `config`
+    pub expressions: Vec<ExpressionChange>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdgeDiff {
+    pub id: ScopedId,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PipelineDiff {
+    /// Changes of pipeline fields other than `graph`, e.g. `/state_schema/variables/count`
+    pub pipeline: Vec<FieldChange>,
+    pub added_nodes: Vec<(ScopedId, Value)>,
+    pub removed_nodes: Vec<(ScopedId, Value)>,
+    pub changed_nodes: Vec<NodeDiff>,
+    pub added_edges: Vec<(ScopedId, Value)>,
+    pub removed_edges: Vec<(ScopedId, Value)>,
+    pub changed_edges: Vec<EdgeDiff>,
+}
+
+/// Structurally compares two pipelines. Nodes and edges are matched by id within
+/// the graph or while body that contains them, so reordering produces no changes.
+pub fn diff(old: &Pipeline, new: &Pipeline) -> PipelineDiff {
+    let old_value = to_json(old);
+    let new_value = to_json(new);
+    let mut result = PipelineDiff::default();
+
+    diff_values(&without_keys(&old_value, &["graph"]), &without_keys(&new_value, &["graph"]), "", &mut result.pipeline);
+
+    let old_graph = GraphElements::collect(&old_value["graph"]);
+    let new_graph = GraphElements::collect(&new_value["graph"]);
+
+    for (id, old_node) in &old_graph.nodes {
+        match new_graph.nodes.get(id) {
+            Some(new_node) => {
+                let node_diff = diff_nodes(id, old_node, new_node);
+                if !node_diff.is_empty() {
+                    result.changed_nodes.push(node_diff);
+                }
+            }
+            None => result.removed_nodes.push((id.clone(), without_body(old_node))),
+        }
+    }
+    for (id, new_node) in &new_graph.nodes {
+        if !old_graph.nodes.contains_key(id) {
+            result.added_nodes.push((id.clone(), without_body(new_node)));
+        }
+    }
+
+    for (id, old_edge) in &old_graph.edges {
+        match new_graph.edges.get(id) {
+            Some(new_edge) => {
+                let mut fields = Vec::new();
+                diff_values(old_edge, new_edge, "", &mut fields);
+                if !fields.is_empty() {
+                    result.changed_edges.push(EdgeDiff { id: id.clone(), fields });
+                }
+            }
+            None => result.removed_edges.push((id.clone(), (*old_edge).clone())),
+        }
+    }
+    for (id, new_edge) in &new_graph.edges {
+        if !old_graph.edges.contains_key(id) {
+            result.added_edges.push((id.clone(), (*new_edge).clone()));
+        }
+    }
+
+    result
+}
+
+/// Appends to [changes] field-level differences between [old] and [new].
+/// Objects are compared by key and arrays by index, pointers are prefixed with [pointer].
+pub fn diff_values(old: &Value, new: &Value, pointer: &str, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child_pointer = format!("{pointer}/{}", escape_pointer_token(key));
+                match new_map.get(key) {
+                    Some(new_value) => diff_values(old_value, new_value, &child_pointer, changes),
+                    None => changes.push(FieldChange::Removed { pointer: child_pointer, value: old_value.clone() }),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    let child_pointer = format!("{pointer}/{}", escape_pointer_token(key));
+                    changes.push(FieldChange::Added { pointer: child_pointer, value: new_value.clone() });
+                }
+            }
+        }
+        (Value::Array(old_vec), Value::Array(new_vec)) => {
+            for (index, old_value) in old_vec.iter().enumerate() {
+                let child_pointer = format!("{pointer}/{index}");
+                match new_vec.get(index) {
+                    Some(new_value) => diff_values(old_value, new_value, &child_pointer, changes),
+                    None => changes.push(FieldChange::Removed { pointer: child_pointer, value: old_value.clone() }),
+                }
+            }
+            for (index, new_value) in new_vec.iter().enumerate().skip(old_vec.len()) {
+                changes.push(FieldChange::Added { pointer: format!("{pointer}/{index}"), value: new_value.clone() });
+            }
+        }
+        _ => {
+            if old != new {
+                changes.push(FieldChange::Changed { pointer: pointer.to_string(), old: old.clone(), new: new.clone() });
+            }
+        }
+    }
+}
+
+pub fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+impl PipelineDiff {
+    pub fn is_empty(&self) -> bool {
+        self.pipeline.is_empty()
+            && self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.changed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.changed_edges.is_empty()
+    }
+}
+
+impl NodeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.config.is_empty() && self.ports.is_empty() && self.expressions.is_empty()
+    }
+}
+
+impl FieldChange {
+    pub fn pointer(&self) -> &str {
+        match self {
+            FieldChange::Added { pointer, .. } => pointer,
+            FieldChange::Removed { pointer, .. } => pointer,
+            FieldChange::Changed { pointer, .. } => pointer,
+        }
+    }
+}
+
+/// Nodes and edges of a graph and of all nested while bodies
+struct GraphElements<'a> {
+    nodes: BTreeMap<ScopedId, &'a Value>,
+    edges: BTreeMap<ScopedId, &'a Value>,
+}
+
+impl<'a> GraphElements<'a> {
+    fn collect(graph: &'a Value) -> Self {
+        let mut elements = GraphElements { nodes: BTreeMap::new(), edges: BTreeMap::new() };
+        elements.collect_scope(graph, &[]);
+        elements
+    }
+
+    fn collect_scope(&mut self, graph: &'a Value, scope: &[String]) {
+        let nodes = graph.get("nodes").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+        for node in nodes {
+            let id = node_id(node).unwrap_or_default().to_string();
+            if let Some(body) = node.pointer("/config/body") {
+                let mut body_scope = scope.to_vec();
+                body_scope.push(id.clone());
+                self.collect_scope(body, &body_scope);
+            }
+            self.nodes.insert(ScopedId { scope: scope.to_vec(), id }, node);
+        }
+
+        let edges = graph.get("edges").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+        for edge in edges {
+            let id = edge.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+            self.edges.insert(ScopedId { scope: scope.to_vec(), id }, edge);
+        }
+    }
+}
+
+fn diff_nodes(id: &ScopedId, old: &Value, new: &Value) -> NodeDiff {
+    let mut node_diff = NodeDiff { id: id.clone(), ..NodeDiff::default() };
+
+    let ignored = ["config", "ports"];
+    diff_values(&without_keys(old, &ignored), &without_keys(new, &ignored), "", &mut node_diff.fields);
+
+    node_diff.ports = diff_ports(old.get("ports"), new.get("ports"));
+
+    let old_config = without_keys(old.get("config").unwrap_or(&Value::Null), &["body"]);
+    let new_config = without_keys(new.get("config").unwrap_or(&Value::Null), &["body"]);
+    let mut config_changes = Vec::new();
+    diff_values(&old_config, &new_config, "", &mut config_changes);
+
+    // changes inside expressions are reported once per expression field
+    let old_expressions = collect_expressions(&old_config, "");
+    let new_expressions = collect_expressions(&new_config, "");
+    for (pointer, old_expression) in &old_expressions {
+        let new_expression = new_expressions.get(pointer);
+        if new_expression != Some(old_expression) {
+            node_diff.expressions.push(ExpressionChange {
+                pointer: pointer.clone(),
+                old: Some(old_expression.clone()),
+                new: new_expression.cloned(),
+            });
+        }
+    }
+    for (pointer, new_expression) in &new_expressions {
+        if !old_expressions.contains_key(pointer) {
+            node_diff.expressions.push(ExpressionChange {
+                pointer: pointer.clone(),
+                old: None,
+                new: Some(new_expression.clone()),
+            });
+        }
+    }
+    node_diff.expressions.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+
+    node_diff.config = config_changes.into_iter()
+        .filter(|change| {
+            let pointer = change.pointer();
+            !old_expressions.keys().chain(new_expressions.keys())
+                .any(|expression_pointer| is_same_or_child_pointer(pointer, expression_pointer))
+        })
+        .collect();
+
+    node_diff
+}
+
+fn diff_ports(old: Option<&Value>, new: Option<&Value>) -> Vec<PortChange> {
+    let collect = |ports: Option<&Value>| -> BTreeMap<String, Value> {
+        ports.and_then(Value::as_array).into_iter().flatten()
+            .map(|port| {
+                let id = port.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+                (id, port.get("direction").cloned().unwrap_or(Value::Null))
+            })
+            .collect()
+    };
+    let old_ports = collect(old);
+    let new_ports = collect(new);
+
+    let mut changes = Vec::new();
+    for (id, old_direction) in &old_ports {
+        match new_ports.get(id) {
+            Some(new_direction) if new_direction != old_direction => changes.push(PortChange::DirectionChanged {
+                id: id.clone(),
+                old: old_direction.clone(),
+                new: new_direction.clone(),
+            }),
+            Some(_) => {}
+            None => changes.push(PortChange::Removed { id: id.clone(), direction: old_direction.clone() }),
+        }
+    }
+    for (id, new_direction) in &new_ports {
+        if !old_ports.contains_key(id) {
+            changes.push(PortChange::Added { id: id.clone(), direction: new_direction.clone() });
+        }
+    }
+    changes
+}
+
+/// Collects expression fields of a node config: `ExpressionSpec` objects
+/// and shorthand strings of `condition` and `expression` fields
+fn collect_expressions(value: &Value, pointer: &str) -> BTreeMap<String, ExpressionValue> {
+    let mut expressions = BTreeMap::new();
+    collect_expressions_into(value, pointer, None, &mut expressions);
+    expressions
+}
+
+fn collect_expressions_into(
+    value: &Value,
+    pointer: &str,
+    key: Option<&str>,
+    expressions: &mut BTreeMap<String, ExpressionValue>
+) {
+    if let Some((expression, format)) = spec_parts(value) {
+        expressions.insert(pointer.to_string(), ExpressionValue { expression: expression.to_string(), format: format.to_string() });
+        return;
+    }
+    match value {
+        Value::String(expression) if matches!(key, Some("condition") | Some("expression")) => {
+            expressions.insert(pointer.to_string(), ExpressionValue {
+                expression: expression.clone(),
+                format: String::new(),
+            });
+        }
+        Value::Object(map) => {
+            for (child_key, child) in map {
+                let child_pointer = format!("{pointer}/{}", escape_pointer_token(child_key));
+                collect_expressions_into(child, &child_pointer, Some(child_key), expressions);
+            }
+        }
+        Value::Array(vec) => {
+            for (index, child) in vec.iter().enumerate() {
+                collect_expressions_into(child, &format!("{pointer}/{index}"), None, expressions);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_same_or_child_pointer(pointer: &str, parent: &str) -> bool {
+    pointer == parent || pointer.strip_prefix(parent).is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn without_keys(value: &Value, keys: &[&str]) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| !keys.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Node without nested body nodes, which are reported separately
+fn without_body(node: &Value) -> Value {
+    let mut node = node.clone();
+    if let Some(config) = node.get_mut("config").and_then(Value::as_object_mut) {
+        config.remove("body");
+    }
+    node
+}
+
+fn to_json(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("generated types always serialize to JSON")
+}
+
+impl fmt::Display for ScopedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for scope in &self.scope {
+            write!(f, "{scope}/")?;
+        }
+        f.write_str(&self.id)
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldChange::Added { pointer, value } => write!(f, "+ {pointer}: {value}"),
+            FieldChange::Removed { pointer, value } => write!(f, "- {pointer}: {value}"),
+            FieldChange::Changed { pointer, old, new } => write!(f, "~ {pointer}: {old} -> {new}"),
+        }
+    }
+}
+
+impl fmt::Display for PortChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortChange::Added { id, direction } => write!(f, "+ port {id} ({})", display_str(direction)),
+            PortChange::Removed { id, direction } => write!(f, "- port {id} ({})", display_str(direction)),
+            PortChange::DirectionChanged { id, old, new } =>
+                write!(f, "~ port {id}: {} -> {}", display_str(old), display_str(new)),
+        }
+    }
+}
+
+impl fmt::Display for ExpressionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let display_expression = |expression: &Option<ExpressionValue>| match expression {
+            Some(ExpressionValue { expression, format }) if format.is_empty() => format!("`{expression}`"),
+            Some(ExpressionValue { expression, format }) => format!("`{expression}` ({format})"),
+            None => "none".to_string(),
+        };
+        write!(f, "~ expression {}: {} -> {}", self.pointer, display_expression(&self.old), display_expression(&self.new))
+    }
+}
+
+/// Renders the diff as a human-readable report, one element per line
+impl fmt::Display for PipelineDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.pipeline {
+            writeln!(f, "pipeline {change}")?;
+        }
+        for (id, node) in &self.removed_nodes {
+            writeln!(f, "- node {id} ({})", node_type(node).unwrap_or("unknown"))?;
+        }
+        for (id, node) in &self.added_nodes {
+            writeln!(f, "+ node {id} ({})", node_type(node).unwrap_or("unknown"))?;
+        }
+        for node_diff in &self.changed_nodes {
+            writeln!(f, "~ node {}", node_diff.id)?;
+            for change in &node_diff.fields {
+                writeln!(f, "    {change}")?;
+            }
+            for change in &node_diff.ports {
+                writeln!(f, "    {change}")?;
+            }
+            for change in &node_diff.config {
+                writeln!(f, "    config {change}")?;
+            }
+            for change in &node_diff.expressions {
+                writeln!(f, "    {change}")?;
+            }
+        }
+        for (id, edge) in &self.removed_edges {
+            writeln!(f, "- edge {id}: {}", display_edge(edge))?;
+        }
+        for (id, edge) in &self.added_edges {
+            writeln!(f, "+ edge {id}: {}", display_edge(edge))?;
+        }
+        for edge_diff in &self.changed_edges {
+            writeln!(f, "~ edge {}", edge_diff.id)?;
+            for change in &edge_diff.fields {
+                writeln!(f, "    {change}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn display_edge(edge: &Value) -> String {
+    let field = |name: &str| edge.get(name).and_then(Value::as_str).unwrap_or("?").to_string();
+    format!(
+        "{}.{} -> {}.{}",
+        field("source_node_id"), field("source_port_id"), field("target_node_id"), field("target_port_id")
+    )
+}
+
+fn display_str(value: &Value) -> String {
+    value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string())
+}
@@ -0,0 +1,357 @@
+use crate::diff::{escape_pointer_token, ScopedId};
+use crate::node::node_id;
+use crate::types::Pipeline;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Pipeline element a conflict belongs to
+#[derive(Clone, Debug, PartialEq)]
+pub enum MergeElement {
+    Pipeline,
+    Node(ScopedId),
+    Edge(ScopedId),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Conflict {
+    /// Both sides changed the same value differently, `None` means the value is absent
+    Content {
+        element: MergeElement,
+        pointer: String,
+        base: Option<Value>,
+        ours: Option<Value>,
+        theirs: Option<Value>,
+    },
+    /// Merged edge points to a node or a port that no longer exists
+    DanglingEdge { edge: ScopedId, endpoint: String },
+    /// Merged document doesn't match the pipeline schema
+    InvalidDocument(String),
+}
+
+/// Merges pipelines [ours] and [theirs] derived from [base]. Nodes and edges are matched by id,
+/// including inside while bodies, state variables by name, and configs field by field.
+/// A conflict is reported only when the same value changed differently on both sides.
+pub fn merge3(base: &Pipeline, ours: &Pipeline, theirs: &Pipeline) -> Result<Pipeline, Vec<Conflict>> {
+    let base = to_json(base);
+    let ours = to_json(ours);
+    let theirs = to_json(theirs);
+    let mut conflicts = Vec::new();
+
+    let strip_graph = |value: &Value| {
+        let mut value = value.clone();
+        if let Some(map) = value.as_object_mut() {
+            map.remove("graph");
+        }
+        value
+    };
+    let merged = merge_values(
+        Some(&strip_graph(&base)),
+        Some(&strip_graph(&ours)),
+        Some(&strip_graph(&theirs)),
+        &MergeElement::Pipeline,
+        "",
+        &mut conflicts
+    );
+    let mut merged = merged.unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+    let merged_graph = merge_graph(Some(&base["graph"]), &ours["graph"], &theirs["graph"], &[], &mut conflicts);
+    if let Some(map) = merged.as_object_mut() {
+        map.insert("graph".to_string(), merged_graph);
+    }
+
+    check_edges(&merged["graph"], &[], &mut conflicts);
+    if !conflicts.is_empty() {
+        return Err(conflicts);
+    }
+
+    serde_json::from_value(merged).map_err(|err| vec![Conflict::InvalidDocument(err.to_string())])
+}
+
+fn merge_graph(
+    base: Option<&Value>,
+    ours: &Value,
+    theirs: &Value,
+    scope: &[String],
+    conflicts: &mut Vec<Conflict>
+) -> Value {
+    let items = |graph: Option<&Value>, key: &str| -> Vec<Value> {
+        graph.and_then(|graph| graph.get(key)).and_then(Value::as_array).cloned().unwrap_or_default()
+    };
+
+    let nodes = merge_by_id(
+        &items(base, "nodes"),
+        &items(Some(ours), "nodes"),
+        &items(Some(theirs), "nodes"),
+        |id, base, ours, theirs| merge_node(scope, id, base, ours, theirs, conflicts)
+    );
+    let mut edge_conflicts = Vec::new();
+    let edges = merge_by_id(
+        &items(base, "edges"),
+        &items(Some(ours), "edges"),
+        &items(Some(theirs), "edges"),
+        |id, base, ours, theirs| {
+            let element = MergeElement::Edge(ScopedId { scope: scope.to_vec(), id: id.to_string() });
+            merge_values(base, ours, theirs, &element, "", &mut edge_conflicts)
+        }
+    );
+    conflicts.append(&mut edge_conflicts);
+
+    let mut graph = ours.as_object().cloned().unwrap_or_default();
+    graph.insert("nodes".to_string(), Value::Array(nodes));
+    graph.insert("edges".to_string(), Value::Array(edges));
+    Value::Object(graph)
+}
+
+fn merge_node(
+    scope: &[String],
+    id: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    conflicts: &mut Vec<Conflict>
+) -> Option<Value> {
+    let element = MergeElement::Node(ScopedId { scope: scope.to_vec(), id: id.to_string() });
+    let (Some(ours), Some(theirs)) = (ours, theirs) else {
+        return merge_values(base, ours, theirs, &element, "", conflicts);
+    };
+
+    let (base_node, base_body) = split_body(base);
+    let (ours_node, ours_body) = split_body(Some(ours));
+    let (theirs_node, theirs_body) = split_body(Some(theirs));
+
+    let base_ports = base_node.as_ref().and_then(|node| node.get("ports")).and_then(Value::as_array).cloned();
+    let ours_ports = ours_node.as_ref().and_then(|node| node.get("ports")).and_then(Value::as_array).cloned();
+    let theirs_ports = theirs_node.as_ref().and_then(|node| node.get("ports")).and_then(Value::as_array).cloned();
+
+    let without_ports = |node: Option<Value>| node.map(|mut node| {
+        if let Some(map) = node.as_object_mut() {
+            map.remove("ports");
+        }
+        node
+    });
+    let mut merged = merge_values(
+        without_ports(base_node).as_ref(),
+        without_ports(ours_node).as_ref(),
+        without_ports(theirs_node).as_ref(),
+        &element,
+        "",
+        conflicts
+    )?;
+
+    // ports are matched by id, so that both sides can add ports to the same node
+    if let (Some(ours_ports), Some(theirs_ports)) = (&ours_ports, &theirs_ports) {
+        let mut port_conflicts = Vec::new();
+        let ports = merge_by_id(
+            base_ports.as_deref().unwrap_or_default(),
+            ours_ports,
+            theirs_ports,
+            |port_id, base, ours, theirs| {
+                let pointer = format!("/ports/{}", escape_pointer_token(port_id));
+                merge_values(base, ours, theirs, &element, &pointer, &mut port_conflicts)
+            }
+        );
+        conflicts.append(&mut port_conflicts);
+        if let Some(map) = merged.as_object_mut() {
+            map.insert("ports".to_string(), Value::Array(ports));
+        }
+    } else if let Some(ports) = merge_values(
+        base_ports.map(Value::Array).as_ref(),
+        ours_ports.map(Value::Array).as_ref(),
+        theirs_ports.map(Value::Array).as_ref(),
+        &element,
+        "/ports",
+        conflicts
+    ) && let Some(map) = merged.as_object_mut() {
+        map.insert("ports".to_string(), ports);
+    }
+
+    // while bodies are merged as nested graphs
+    let body = match (&ours_body, &theirs_body) {
+        (Some(ours_body), Some(theirs_body)) => {
+            let mut body_scope = scope.to_vec();
+            body_scope.push(id.to_string());
+            Some(merge_graph(base_body.as_ref(), ours_body, theirs_body, &body_scope, conflicts))
+        }
+        _ => merge_values(base_body.as_ref(), ours_body.as_ref(), theirs_body.as_ref(), &element, "/config/body", conflicts),
+    };
+    if let Some(body) = body && let Some(config) = merged.get_mut("config").and_then(Value::as_object_mut) {
+        config.insert("body".to_string(), body);
+    }
+
+    Some(merged)
+}
+
+/// Merges lists of objects with an `id` field. Result keeps the order of [ours]
+/// followed by items added in [theirs].
+fn merge_by_id(
+    base: &[Value],
+    ours: &[Value],
+    theirs: &[Value],
+    mut merge_item: impl FnMut(&str, Option<&Value>, Option<&Value>, Option<&Value>) -> Option<Value>
+) -> Vec<Value> {
+    let index = |items: &[Value]| -> BTreeMap<String, Value> {
+        items.iter().map(|item| (node_id(item).unwrap_or_default().to_string(), item.clone())).collect()
+    };
+    let base_items = index(base);
+    let ours_items = index(ours);
+    let theirs_items = index(theirs);
+
+    let mut ids = Vec::new();
+    let mut seen = BTreeSet::new();
+    for item in ours.iter().chain(theirs) {
+        let id = node_id(item).unwrap_or_default().to_string();
+        if seen.insert(id.clone()) {
+            ids.push(id);
+        }
+    }
+
+    ids.iter()
+        .filter_map(|id| merge_item(id, base_items.get(id), ours_items.get(id), theirs_items.get(id)))
+        .collect()
+}
+
+/// Three-way merge of JSON values: objects are merged key by key, any other value
+/// is taken from the side that changed it. `None` stands for an absent value.
+fn merge_values(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    element: &MergeElement,
+    pointer: &str,
+    conflicts: &mut Vec<Conflict>
+) -> Option<Value> {
+    if ours == theirs || base == theirs {
+        return ours.cloned();
+    }
+    if base == ours {
+        return theirs.cloned();
+    }
+
+    if let (Some(Value::Object(ours_map)), Some(Value::Object(theirs_map))) = (ours, theirs) {
+        let base_map = match base {
+            Some(Value::Object(base_map)) => Some(base_map),
+            None => None,
+            Some(_) => {
+                conflicts.push(content_conflict(element, pointer, base, ours, theirs));
+                return ours.cloned();
+            }
+        };
+        let keys = ours_map.keys().chain(theirs_map.keys()).collect::<BTreeSet<_>>();
+        let mut merged = serde_json::Map::new();
+        for key in keys {
+            let child_pointer = format!("{pointer}/{}", escape_pointer_token(key));
+            let merged_value = merge_values(
+                base_map.and_then(|map| map.get(key)),
+                ours_map.get(key),
+                theirs_map.get(key),
+                element,
+                &child_pointer,
+                conflicts
+            );
+            if let Some(merged_value) = merged_value {
+                merged.insert(key.clone(), merged_value);
+            }
+        }
+        return Some(Value::Object(merged));
+    }
+
+    conflicts.push(content_conflict(element, pointer, base, ours, theirs));
+    ours.cloned()
+}
+
+fn content_conflict(
+    element: &MergeElement,
+    pointer: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>
+) -> Conflict {
+    Conflict::Content {
+        element: element.clone(),
+        pointer: pointer.to_string(),
+        base: base.cloned(),
+        ours: ours.cloned(),
+        theirs: theirs.cloned(),
+    }
+}
+
+/// Splits a node into the node without `config.body` and the body itself
+fn split_body(node: Option<&Value>) -> (Option<Value>, Option<Value>) {
+    let Some(node) = node else {
+        return (None, None);
+    };
+    let mut node = node.clone();
+    let body = node.get_mut("config")
+        .and_then(Value::as_object_mut)
+        .and_then(|config| config.remove("body"));
+    (Some(node), body)
+}
+
+/// Reports edges whose endpoints are missing in the graph or while body that contains them
+fn check_edges(graph: &Value, scope: &[String], conflicts: &mut Vec<Conflict>) {
+    let nodes = graph.get("nodes").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let mut ports: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for node in nodes {
+        let node_ports = node.get("ports").and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|port| port.get("id").and_then(Value::as_str));
+        ports.insert(node_id(node).unwrap_or_default(), node_ports.collect());
+
+        if let Some(body) = node.pointer("/config/body") {
+            let mut body_scope = scope.to_vec();
+            body_scope.push(node_id(node).unwrap_or_default().to_string());
+            check_edges(body, &body_scope, conflicts);
+        }
+    }
+
+    let edges = graph.get("edges").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    for edge in edges {
+        let field = |name: &str| edge.get(name).and_then(Value::as_str).unwrap_or_default();
+        let endpoints = [
+            (field("source_node_id"), field("source_port_id")),
+            (field("target_node_id"), field("target_port_id")),
+        ];
+        for (endpoint_node_id, endpoint_port_id) in endpoints {
+            let exists = ports.get(endpoint_node_id).is_some_and(|node_ports| node_ports.contains(endpoint_port_id));
+            if !exists {
+                conflicts.push(Conflict::DanglingEdge {
+                    edge: ScopedId { scope: scope.to_vec(), id: field("id").to_string() },
+                    endpoint: format!("{endpoint_node_id}.{endpoint_port_id}"),
+                });
+            }
+        }
+    }
+}
+
+fn to_json(pipeline: &Pipeline) -> Value {
+    serde_json::to_value(pipeline).expect("generated types always serialize to JSON")
+}
+
+impl fmt::Display for MergeElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeElement::Pipeline => f.write_str("pipeline"),
+            MergeElement::Node(id) => write!(f, "node {id}"),
+            MergeElement::Edge(id) => write!(f, "edge {id}"),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let display_value = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "absent".to_string(),
+        };
+        match self {
+            Conflict::Content { element, pointer, base, ours, theirs } => {
+                write!(f, "{element}")?;
+                if !pointer.is_empty() {
+                    write!(f, " {pointer}")?;
+                }
+                write!(f, ": base {}, ours {}, theirs {}", display_value(base), display_value(ours), display_value(theirs))
+            }
+            Conflict::DanglingEdge { edge, endpoint } => write!(f, "edge {edge}: endpoint {endpoint} doesn't exist"),
+            Conflict::InvalidDocument(message) => write!(f, "merged pipeline is invalid: {message}"),
+        }
+    }
+}
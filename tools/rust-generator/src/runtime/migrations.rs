@@ -0,0 +1,175 @@
+use crate::diff::{diff_values, FieldChange};
+use crate::types::Pipeline;
+use crate::SCHEMA_VERSION;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+
+/// Step that transforms a pipeline document from schema version `from` to `to`.
+/// `schema_version` is updated by the registry after the step succeeds.
+#[derive(Clone, Copy)]
+pub struct Migration {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> Result<(), String>,
+}
+
+/// Migrations shipped with the crate, one per schema version change
+pub const MIGRATIONS: [Migration; 0] = [];
+
+/// Schema versions released before [SCHEMA_VERSION], oldest first. A version without a migration
+/// from it upgrades unchanged to the next one, add the current version here when bumping it.
+pub const SCHEMA_VERSIONS: [&str; 0] = [];
+
+pub struct MigrationRegistry {
+    latest_version: String,
+    /// Versions before the latest one, oldest first
+    versions: Vec<String>,
+    migrations: Vec<Migration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppliedMigration {
+    pub from: String,
+    pub to: String,
+    pub description: String,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MigrationReport {
+    pub from_version: String,
+    pub to_version: String,
+    pub applied: Vec<AppliedMigration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MigrationError {
+    MissingVersion,
+    UnknownVersion(String),
+    /// Migrations lead back to an already visited version
+    Cycle(String),
+    StepFailed { from: String, to: String, message: String },
+    InvalidDocument(String),
+}
+
+/// Upgrades a stored pipeline document to [SCHEMA_VERSION] with the [MIGRATIONS]
+pub fn upgrade_to_latest(value: Value) -> Result<Pipeline, MigrationError> {
+    upgrade_to_latest_with_report(value).map(|(pipeline, _)| pipeline)
+}
+
+/// Same as [upgrade_to_latest], but also reports applied steps and what they changed
+pub fn upgrade_to_latest_with_report(value: Value) -> Result<(Pipeline, MigrationReport), MigrationError> {
+    MigrationRegistry::builtin().upgrade(value)
+}
+
+impl MigrationRegistry {
+    pub fn new(latest_version: impl Into<String>) -> Self {
+        MigrationRegistry { latest_version: latest_version.into(), versions: Vec::new(), migrations: Vec::new() }
+    }
+
+    /// Registry of [SCHEMA_VERSIONS] and [MIGRATIONS] leading to [SCHEMA_VERSION]
+    pub fn builtin() -> Self {
+        let registry = SCHEMA_VERSIONS.iter().fold(Self::new(SCHEMA_VERSION), |registry, version| registry.with_version(*version));
+        MIGRATIONS.iter().fold(registry, |registry, migration| registry.with_migration(*migration))
+    }
+
+    /// Registers [version] as the one released after the previously registered versions
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.versions.push(version.into());
+        self
+    }
+
+    pub fn with_migration(mut self, migration: Migration) -> Self {
+        self.migrations.push(migration);
+        self
+    }
+
+    /// Detects `schema_version` of [value] and applies steps until the latest version
+    pub fn upgrade(&self, mut value: Value) -> Result<(Pipeline, MigrationReport), MigrationError> {
+        let from_version = value.get("schema_version")
+            .and_then(Value::as_str)
+            .ok_or(MigrationError::MissingVersion)?
+            .to_string();
+        let mut report = MigrationReport {
+            from_version: from_version.clone(),
+            to_version: self.latest_version.clone(),
+            applied: Vec::new(),
+        };
+
+        let mut version = from_version;
+        while version != self.latest_version {
+            let (to, description, apply) = match self.migrations.iter().find(|migration| migration.from == version) {
+                Some(migration) => (migration.to.to_string(), migration.description, migration.apply),
+                None => (self.next_version(&version)?, "no schema changes", keep_unchanged as fn(&mut Value) -> Result<(), String>),
+            };
+            if report.applied.len() >= self.migrations.len() + self.versions.len() {
+                return Err(MigrationError::Cycle(version));
+            }
+
+            let before = value.clone();
+            apply(&mut value).map_err(|message| MigrationError::StepFailed {
+                from: version.clone(),
+                to: to.clone(),
+                message,
+            })?;
+            if let Some(map) = value.as_object_mut() {
+                map.insert("schema_version".to_string(), Value::String(to.clone()));
+            }
+
+            let mut changes = Vec::new();
+            diff_values(&before, &value, "", &mut changes);
+            report.applied.push(AppliedMigration {
+                from: version,
+                to: to.clone(),
+                description: description.to_string(),
+                changes,
+            });
+            version = to;
+        }
+
+        let pipeline = serde_json::from_value(value).map_err(|err| MigrationError::InvalidDocument(err.to_string()))?;
+        Ok((pipeline, report))
+    }
+
+    /// Version registered after [version], the latest one after the last registered version
+    fn next_version(&self, version: &str) -> Result<String, MigrationError> {
+        let index = self.versions.iter()
+            .position(|known| known == version)
+            .ok_or_else(|| MigrationError::UnknownVersion(version.to_string()))?;
+        Ok(self.versions.get(index + 1).unwrap_or(&self.latest_version).clone())
+    }
+}
+
+/// Step between versions without a migration, their schemas are the same
+fn keep_unchanged(_: &mut Value) -> Result<(), String> {
+    Ok(())
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "schema version {} -> {}", self.from_version, self.to_version)?;
+        for migration in &self.applied {
+            writeln!(f, "  {} -> {}: {}", migration.from, migration.to, migration.description)?;
+            for change in &migration.changes {
+                writeln!(f, "    {change}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::MissingVersion => f.write_str("document has no `schema_version`"),
+            MigrationError::UnknownVersion(version) => write!(f, "no migration from schema version `{version}`"),
+            MigrationError::Cycle(version) => write!(f, "migrations from schema version `{version}` form a cycle"),
+            MigrationError::StepFailed { from, to, message } => write!(f, "migration {from} -> {to} failed: {message}"),
+            MigrationError::InvalidDocument(message) => write!(f, "migrated document is invalid: {message}"),
+        }
+    }
+}
+
+impl Error for MigrationError {}
@@ -0,0 +1,299 @@
+use crate::normalize::default_format_spec;
+use crate::types::{
+    Agent, Approval, Classifier, Edge, End, IfElse, Node, PortDirection, Router, SetState, Start, Transform, While,
+};
+use serde_json::{json, Value};
+use std::marker::PhantomData;
+
+/// Node under construction, [K] is the generated type of the node, e.g. [Agent].
+/// Constructors such as [Node::agent] fill in `type`, `config.kind` and the default ports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeBuilder<K> {
+    node: Value,
+    kind: PhantomData<K>,
+}
+
+impl Node {
+    /// Value of the node `id` field
+    pub fn id(&self) -> &str {
+        match self {
+            Node::Start(node) => &node.id,
+            Node::Agent(node) => &node.id,
+            Node::Router(node) => &node.id,
+            Node::Classifier(node) => &node.id,
+            Node::IfElse(node) => &node.id,
+            Node::While(node) => &node.id,
+            Node::Transform(node) => &node.id,
+            Node::SetState(node) => &node.id,
+            Node::Approval(node) => &node.id,
+            Node::End(node) => &node.id,
+        }
+    }
+
+    /// Value of the node `type` discriminator, e.g. "agent" or "while"
+    pub fn node_type(&self) -> &'static str {
+        match self {
+            Node::Start(_) => "start",
+            Node::Agent(_) => "agent",
+            Node::Router(_) => "router",
+            Node::Classifier(_) => "classifier",
+            Node::IfElse(_) => "if_else",
+            Node::While(_) => "while",
+            Node::Transform(_) => "transform",
+            Node::SetState(_) => "set_state",
+            Node::Approval(_) => "approval",
+            Node::End(_) => "end",
+        }
+    }
+
+    /// Nodes of the while body, empty for any other node
+    pub fn body_nodes(&self) -> &[Node] {
+        match self {
+            Node::While(node) => node.config.body.as_ref().map(|body| body.nodes.as_slice()).unwrap_or_default(),
+            _ => &[],
+        }
+    }
+
+    /// Start node with an `out` port
+    pub fn start(id: impl Into<String>) -> NodeBuilder<Start> {
+        NodeBuilder::new(id, "start", &[("out", PortDirection::Output)])
+    }
+
+    /// End node with an `in` port
+    pub fn end(id: impl Into<String>) -> NodeBuilder<End> {
+        NodeBuilder::new(id, "end", &[("in", PortDirection::Input)])
+    }
+
+    /// Agent node running [agent_id], with `in` and `out` ports
+    pub fn agent(id: impl Into<String>, agent_id: impl Into<String>) -> NodeBuilder<Agent> {
+        NodeBuilder::new(id, "agent", &[("in", PortDirection::Input), ("out", PortDirection::Output)])
+            .set("agent_id", json!(agent_id.into()))
+    }
+
+    /// Classifier node running [classifier_id], with an `in` port, output ports are added by [NodeBuilder::port]
+    pub fn classifier(id: impl Into<String>, classifier_id: impl Into<String>) -> NodeBuilder<Classifier> {
+        NodeBuilder::new(id, "classifier", &[("in", PortDirection::Input)])
+            .set("classifier_id", json!(classifier_id.into()))
+    }
+
+    /// Router node running [router_id], with an `in` port, output ports are added by [NodeBuilder::port]
+    pub fn router(id: impl Into<String>, router_id: impl Into<String>) -> NodeBuilder<Router> {
+        NodeBuilder::new(id, "router", &[("in", PortDirection::Input)])
+            .set("router_id", json!(router_id.into()))
+    }
+
+    /// Transform node evaluating [expression] in the [DEFAULT_EXPRESSION_FORMAT], with `in` and `out` ports
+    pub fn transform(id: impl Into<String>, expression: impl Into<String>) -> NodeBuilder<Transform> {
+        NodeBuilder::new(id, "transform", &[("in", PortDirection::Input), ("out", PortDirection::Output)])
+            .set("mode", json!("expression"))
+            .set("expr", default_format_spec(expression))
+    }
+
+    /// Set state node without assignments, with `in` and `out` ports
+    pub fn set_state(id: impl Into<String>) -> NodeBuilder<SetState> {
+        NodeBuilder::new(id, "set_state", &[("in", PortDirection::Input), ("out", PortDirection::Output)])
+            .set("assignments", json!([]))
+    }
+
+    /// If-else node without cases, with an `in` port
+    pub fn if_else(id: impl Into<String>) -> NodeBuilder<IfElse> {
+        NodeBuilder::new(id, "if_else", &[("in", PortDirection::Input)])
+            .set("cases", json!([]))
+    }
+
+    /// Approval node with `in`, `on_approve` and `on_reject` ports
+    pub fn approval(id: impl Into<String>) -> NodeBuilder<Approval> {
+        let ports = [("in", PortDirection::Input), ("on_approve", PortDirection::Output), ("on_reject", PortDirection::Output)];
+        NodeBuilder::new(id, "approval", &ports)
+    }
+
+    /// While node looping while [condition] holds, with `in` and `exit` ports
+    pub fn while_loop(id: impl Into<String>, condition: impl Into<String>) -> NodeBuilder<While> {
+        NodeBuilder::new(id, "while", &[("in", PortDirection::Input), ("exit", PortDirection::Output)])
+            .set("condition", default_format_spec(condition))
+    }
+}
+
+impl<K> NodeBuilder<K> {
+    fn new(id: impl Into<String>, node_type: &str, ports: &[(&str, PortDirection)]) -> Self {
+        let node = json!({
+            "id": id.into(),
+            "type": node_type,
+            "ports": ports.iter().map(|(id, direction)| json!({ "id": id, "direction": direction })).collect::<Vec<_>>(),
+            "config": { "kind": node_type },
+        });
+        NodeBuilder { node, kind: PhantomData }
+    }
+
+    /// Sets [key] of the node `config`
+    fn set(mut self, key: &str, value: Value) -> Self {
+        self.node["config"][key] = value;
+        self
+    }
+
+    /// Appends [value] to the `config` array [key]
+    fn push(mut self, key: &str, value: Value) -> Self {
+        match self.node["config"][key].as_array_mut() {
+            Some(values) => values.push(value),
+            None => self.node["config"][key] = json!([value]),
+        }
+        self
+    }
+
+    /// Adds a port, replacing the port with the same id
+    pub fn port(mut self, id: impl Into<String>, direction: PortDirection) -> Self {
+        let id = id.into();
+        if let Some(ports) = self.node["ports"].as_array_mut() {
+            ports.retain(|port| node_id(port) != Some(id.as_str()));
+            ports.push(json!({ "id": id, "direction": direction }));
+        }
+        self
+    }
+
+    pub fn build(self) -> Node {
+        serde_json::from_value(self.node).expect("node constructors build valid nodes")
+    }
+}
+
+impl<K> From<NodeBuilder<K>> for Node {
+    fn from(builder: NodeBuilder<K>) -> Self {
+        builder.build()
+    }
+}
+
+impl NodeBuilder<Start> {
+    pub fn initial_state(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.node["config"]["initial_state"][key] = value.into();
+        self
+    }
+}
+
+impl NodeBuilder<End> {
+    /// Selects the pipeline output with [expression] in the [DEFAULT_EXPRESSION_FORMAT]
+    pub fn output_expression(self, expression: impl Into<String>) -> Self {
+        self.set("output_selector", json!({ "mode": "expression", "expression": default_format_spec(expression) }))
+    }
+
+    /// Uses the pipeline state as its output
+    pub fn output_state(self) -> Self {
+        self.set("output_selector", json!({ "mode": "state" }))
+    }
+}
+
+impl NodeBuilder<Agent> {
+    pub fn model(self, model: impl Into<String>) -> Self {
+        self.set("model", json!(model.into()))
+    }
+
+    /// Sets model parameter [key], e.g. `temperature`
+    pub fn param(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.node["config"]["params"][key] = value.into();
+        self
+    }
+
+    /// Maps agent input [field] to the [template], e.g. `{{workflow.input_as_text}}`
+    pub fn input(self, field: &str, template: impl Into<String>) -> Self {
+        input_field(self, field, template)
+    }
+
+    /// Stores agent output [output_key] into state variable [state_key]
+    pub fn output_to_state(mut self, state_key: &str, output_key: impl Into<String>) -> Self {
+        self.node["config"]["output_mapping"]["to_state"][state_key] = json!(output_key.into());
+        self
+    }
+}
+
+impl NodeBuilder<Classifier> {
+    /// Maps classifier input [field] to the [template]
+    pub fn input(self, field: &str, template: impl Into<String>) -> Self {
+        input_field(self, field, template)
+    }
+}
+
+impl NodeBuilder<Router> {
+    /// Maps router input [field] to the [template]
+    pub fn input(self, field: &str, template: impl Into<String>) -> Self {
+        input_field(self, field, template)
+    }
+}
+
+impl NodeBuilder<SetState> {
+    /// Assigns [expression] in the [DEFAULT_EXPRESSION_FORMAT] to state variable [name]
+    pub fn assign(self, name: impl Into<String>, expression: impl Into<String>) -> Self {
+        self.push("assignments", json!({ "name": name.into(), "expression": default_format_spec(expression) }))
+    }
+}
+
+impl NodeBuilder<IfElse> {
+    /// Adds a case routed to a new output port named after [label]
+    pub fn case(self, label: impl Into<String>, predicate: impl Into<String>) -> Self {
+        let label = label.into();
+        self.port(label.clone(), PortDirection::Output)
+            .push("cases", json!({ "label": label, "output_port_id": label, "predicate": default_format_spec(predicate) }))
+    }
+
+    /// Sets the fallback routed to a new output port named after [label]
+    pub fn fallback(self, label: impl Into<String>) -> Self {
+        let label = label.into();
+        self.port(label.clone(), PortDirection::Output)
+            .set("fallback", json!({ "label": label, "output_port_id": label }))
+    }
+}
+
+impl NodeBuilder<Approval> {
+    pub fn message(self, message: impl Into<String>) -> Self {
+        self.set("message", json!(message.into()))
+    }
+
+    /// Exposes [expression] in the [DEFAULT_EXPRESSION_FORMAT] to the message as [name]
+    pub fn variable(self, name: impl Into<String>, expression: impl Into<String>) -> Self {
+        self.push("variable_mapping", json!({ "name": name.into(), "expression": default_format_spec(expression) }))
+    }
+}
+
+impl NodeBuilder<While> {
+    pub fn max_iterations(self, max_iterations: i64) -> Self {
+        self.set("max_iterations", json!(max_iterations))
+    }
+
+    /// Sets the loop body graph
+    pub fn body(self, nodes: impl IntoIterator<Item = Node>, edges: impl IntoIterator<Item = Edge>) -> Self {
+        let nodes = nodes.into_iter().collect::<Vec<_>>();
+        let edges = edges.into_iter().collect::<Vec<_>>();
+        self.set("body", json!({ "nodes": nodes, "edges": edges }))
+    }
+}
+
+fn input_field<K>(mut builder: NodeBuilder<K>, field: &str, template: impl Into<String>) -> NodeBuilder<K> {
+    builder.node["config"]["input_mapping"]["fields"][field] = json!(template.into());
+    builder
+}
+
+/// Returns `id` of a node stored as raw JSON
+pub fn node_id(node: &Value) -> Option<&str> {
+    node.get("id").and_then(Value::as_str)
+}
+
+/// Returns `type` of a node stored as raw JSON
+pub fn node_type(node: &Value) -> Option<&str> {
+    node.get("type").and_then(Value::as_str)
+}
+
+/// Calls [visitor] for each of [nodes] and for every node nested in while bodies
+pub fn visit_nodes<'a>(nodes: &'a [Node], visitor: &mut impl FnMut(&'a Node)) {
+    for node in nodes {
+        visitor(node);
+        visit_nodes(node.body_nodes(), visitor);
+    }
+}
+
+/// Calls [visitor] for each of [nodes] and for every node nested in while bodies,
+/// nested nodes are visited after the node that contains them was updated
+pub fn visit_nodes_mut(nodes: &mut [Node], visitor: &mut impl FnMut(&mut Node)) {
+    for node in nodes {
+        visitor(node);
+        if let Node::While(node) = node && let Some(body) = &mut node.config.body {
+            visit_nodes_mut(&mut body.nodes, visitor);
+        }
+    }
+}
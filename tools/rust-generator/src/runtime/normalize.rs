@@ -0,0 +1,98 @@
+use crate::node::visit_nodes_mut;
+use crate::types::{
+    ExpressionSpec, OutputSelector, OutputSelectorExpression, Pipeline, WhileConfig, WhileConfigCondition,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Format assigned to expressions written as bare strings
+pub const DEFAULT_EXPRESSION_FORMAT: &str = "cel";
+
+/// Node fields that accept either a bare string or an `ExpressionSpec`, by node type
+const SHORTHAND_FIELDS: [(&str, &str); 2] = [
+    ("while", "/config/condition"),
+    ("end", "/config/output_selector/expression"),
+];
+
+/// JSON of an `ExpressionSpec` of [expression] in the [DEFAULT_EXPRESSION_FORMAT].
+/// Expression specs are handled as JSON, so the runtime works with a replaced `ExpressionSpec` type too.
+pub fn default_format_spec(expression: impl Into<String>) -> Value {
+    json!({ "expression": expression.into(), "format": DEFAULT_EXPRESSION_FORMAT })
+}
+
+/// Expression and format of the `ExpressionSpec` JSON [value], none for any other value
+pub fn spec_parts(value: &Value) -> Option<(&str, &str)> {
+    let spec = value.as_object().filter(|spec| spec.len() == 2)?;
+    Some((spec.get("expression")?.as_str()?, spec.get("format")?.as_str()?))
+}
+
+/// Explicit spec of the value of a shorthand field, a bare string gets the [DEFAULT_EXPRESSION_FORMAT]
+fn shorthand_spec<S: DeserializeOwned>(shorthand: &impl Serialize) -> S {
+    let spec = match serde_json::to_value(shorthand).expect("generated types always serialize to JSON") {
+        Value::String(expression) => default_format_spec(expression),
+        spec => spec,
+    };
+    serde_json::from_value(spec).expect("both forms of a shorthand field are valid")
+}
+
+impl WhileConfigCondition {
+    pub fn to_spec(&self) -> ExpressionSpec {
+        shorthand_spec(self)
+    }
+}
+
+impl OutputSelectorExpression {
+    pub fn to_spec(&self) -> ExpressionSpec {
+        shorthand_spec(self)
+    }
+}
+
+impl WhileConfig {
+    /// Loop condition, a shorthand string gets the default format
+    pub fn condition_spec(&self) -> ExpressionSpec {
+        self.condition.to_spec()
+    }
+}
+
+impl OutputSelector {
+    /// Selector expression, a shorthand string gets the default format
+    pub fn expression_spec(&self) -> Option<ExpressionSpec> {
+        self.expression.as_ref().map(OutputSelectorExpression::to_spec)
+    }
+}
+
+/// Rewrites every shorthand expression string of [pipeline], including while bodies,
+/// into an explicit `ExpressionSpec` with the [DEFAULT_EXPRESSION_FORMAT]
+pub fn normalize(pipeline: &mut Pipeline) {
+    visit_shorthand_fields(pipeline, |field| {
+        if let Value::String(expression) = field {
+            *field = default_format_spec(expression.as_str());
+        }
+    });
+}
+
+/// Reverse of [normalize]: rewrites `ExpressionSpec`s in the [DEFAULT_EXPRESSION_FORMAT]
+/// back into bare strings where the schema accepts them
+pub fn compact(pipeline: &mut Pipeline) {
+    visit_shorthand_fields(pipeline, |field| {
+        if let Some((expression, DEFAULT_EXPRESSION_FORMAT)) = spec_parts(field) {
+            *field = Value::String(expression.to_string());
+        }
+    });
+}
+
+fn visit_shorthand_fields(pipeline: &mut Pipeline, mut visitor: impl FnMut(&mut Value)) {
+    visit_nodes_mut(&mut pipeline.graph.nodes, &mut |node| {
+        for (node_type, pointer) in SHORTHAND_FIELDS {
+            if node.node_type() != node_type {
+                continue;
+            }
+            let mut value = serde_json::to_value(&*node).expect("generated types always serialize to JSON");
+            if let Some(field) = value.pointer_mut(pointer) {
+                visitor(field);
+                *node = serde_json::from_value(value).expect("both forms of a shorthand field are valid");
+            }
+        }
+    });
+}
@@ -0,0 +1,262 @@
+use crate::node::visit_nodes;
+use crate::types::{Graph, Node, Pipeline};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// Local catalog of known models, their parameters and per-environment model lists.
+///
+/// ```json
+/// {
+///   "models": {
+///     "gpt-4.1-mini": {
+///       "params": {
+///         "temperature": { "type": "number", "minimum": 0, "maximum": 2 }
+///       }
+///     }
+///   },
+///   "environments": {
+///     "production": { "allow": ["gpt-4.1-mini"], "deny": [] }
+///   }
+/// }
+/// ```
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelCatalog {
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelSpec>,
+    #[serde(default)]
+    pub environments: BTreeMap<String, EnvironmentPolicy>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelSpec {
+    /// Allowed parameter names, any other parameter is reported
+    #[serde(default)]
+    pub params: BTreeMap<String, ParamSpec>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ParamSpec {
+    #[serde(rename = "type", default)]
+    pub value_type: Option<ParamType>,
+    #[serde(default)]
+    pub minimum: Option<f64>,
+    #[serde(default)]
+    pub maximum: Option<f64>,
+    #[serde(rename = "enum", default)]
+    pub allowed_values: Option<Vec<Value>>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    Number,
+    Integer,
+    String,
+    Boolean,
+}
+
+/// Models allowed or denied in an environment.
+/// Without `allow` every catalog model is allowed; `deny` always wins.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentPolicy {
+    #[serde(default)]
+    pub allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyViolation {
+    pub node_id: String,
+    pub kind: ViolationKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViolationKind {
+    UnknownModel { model: String },
+    ForbiddenModel { model: String, environment: String },
+    UnknownParam { model: String, param: String },
+    InvalidParamType { param: String, expected: ParamType, value: Value },
+    ParamOutOfRange { param: String, value: f64, minimum: Option<f64>, maximum: Option<f64> },
+    ParamNotAllowed { param: String, value: Value },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PolicyError {
+    UnknownEnvironment(String),
+}
+
+impl ModelCatalog {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Self::from_json(&content)?)
+    }
+
+    /// Reports agent nodes of [pipeline], including while bodies, that use unknown
+    /// or forbidden models or out-of-range params. [environment] enables allow/deny lists.
+    pub fn check_pipeline(
+        &self,
+        pipeline: &Pipeline,
+        environment: Option<&str>
+    ) -> Result<Vec<PolicyViolation>, PolicyError> {
+        self.check_graph(&pipeline.graph, environment)
+    }
+
+    pub fn check_graph(
+        &self,
+        graph: &Graph,
+        environment: Option<&str>
+    ) -> Result<Vec<PolicyViolation>, PolicyError> {
+        let environment = match environment {
+            Some(name) => {
+                let policy = self.environments.get(name)
+                    .ok_or_else(|| PolicyError::UnknownEnvironment(name.to_string()))?;
+                Some((name, policy))
+            }
+            None => None,
+        };
+
+        let mut violations = Vec::new();
+        visit_nodes(&graph.nodes, &mut |node| {
+            let Node::Agent(agent) = node else {
+                return;
+            };
+            let mut report = |kind: ViolationKind| {
+                violations.push(PolicyViolation { node_id: agent.id.clone(), kind });
+            };
+            let Some(model) = agent.config.model.as_deref() else {
+                return;
+            };
+            let Some(model_spec) = self.models.get(model) else {
+                report(ViolationKind::UnknownModel { model: model.to_string() });
+                return;
+            };
+            if let Some((environment_name, policy)) = environment && !policy.allows(model) {
+                report(ViolationKind::ForbiddenModel {
+                    model: model.to_string(),
+                    environment: environment_name.to_string(),
+                });
+            }
+            for (param, value) in &agent.config.params {
+                match model_spec.params.get(param) {
+                    Some(param_spec) => {
+                        if let Some(kind) = param_spec.check(param, value) {
+                            report(kind);
+                        }
+                    }
+                    None => report(ViolationKind::UnknownParam {
+                        model: model.to_string(),
+                        param: param.clone(),
+                    }),
+                }
+            }
+        });
+
+        Ok(violations)
+    }
+}
+
+impl EnvironmentPolicy {
+    pub fn allows(&self, model: &str) -> bool {
+        let allowed = match &self.allow {
+            Some(allow) => allow.iter().any(|name| name == model),
+            None => true,
+        };
+        allowed && !self.deny.iter().any(|name| name == model)
+    }
+}
+
+impl ParamSpec {
+    fn check(&self, param: &str, value: &Value) -> Option<ViolationKind> {
+        if let Some(expected) = self.value_type {
+            let matches = match expected {
+                ParamType::Number => value.is_number(),
+                ParamType::Integer => value.is_i64() || value.is_u64(),
+                ParamType::String => value.is_string(),
+                ParamType::Boolean => value.is_boolean(),
+            };
+            if !matches {
+                return Some(ViolationKind::InvalidParamType {
+                    param: param.to_string(),
+                    expected,
+                    value: value.clone(),
+                });
+            }
+        }
+
+        if let Some(allowed_values) = &self.allowed_values && !allowed_values.contains(value) {
+            return Some(ViolationKind::ParamNotAllowed { param: param.to_string(), value: value.clone() });
+        }
+
+        if let Some(number) = value.as_f64() {
+            let below = self.minimum.is_some_and(|minimum| number < minimum);
+            let above = self.maximum.is_some_and(|maximum| number > maximum);
+            if below || above {
+                return Some(ViolationKind::ParamOutOfRange {
+                    param: param.to_string(),
+                    value: number,
+                    minimum: self.minimum,
+                    maximum: self.maximum,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParamType::Number => "number",
+            ParamType::Integer => "integer",
+            ParamType::String => "string",
+            ParamType::Boolean => "boolean",
+        })
+    }
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node `{}`: ", self.node_id)?;
+        match &self.kind {
+            ViolationKind::UnknownModel { model } =>
+                write!(f, "unknown model `{model}`"),
+            ViolationKind::ForbiddenModel { model, environment } =>
+                write!(f, "model `{model}` is not allowed in `{environment}`"),
+            ViolationKind::UnknownParam { model, param } =>
+                write!(f, "param `{param}` is not supported by model `{model}`"),
+            ViolationKind::InvalidParamType { param, expected, value } =>
+                write!(f, "param `{param}` must be {expected}, got {value}"),
+            ViolationKind::ParamOutOfRange { param, value, minimum, maximum } => {
+                let minimum = minimum.map(|minimum| minimum.to_string()).unwrap_or("-inf".to_string());
+                let maximum = maximum.map(|maximum| maximum.to_string()).unwrap_or("inf".to_string());
+                write!(f, "param `{param}` = {value} is out of range [{minimum}, {maximum}]")
+            }
+            ViolationKind::ParamNotAllowed { param, value } =>
+                write!(f, "param `{param}` value {value} is not allowed"),
+        }
+    }
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::UnknownEnvironment(name) => write!(f, "unknown environment `{name}`"),
+        }
+    }
+}
+
+impl Error for PolicyError {}
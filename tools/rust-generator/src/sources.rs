@@ -0,0 +1,194 @@
+use crate::{json5, yaml};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Format of a schema source file, selected by the file name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceFormat {
+    Json,
+    Json5,
+    Yaml,
+}
+
+impl SourceFormat {
+    /// Suffixes of schema files and their formats for the configured JSON [suffix], e.g. `.schema.json`,
+    /// `.schema.json5` and `.schema.yaml`. Suffixes not ending with `.json` have only their own format.
+    pub fn suffixes(suffix: &str) -> Vec<(String, SourceFormat)> {
+        match suffix.strip_suffix(".json") {
+            Some(stem) => vec![
+                (suffix.to_string(), SourceFormat::Json),
+                (format!("{stem}.json5"), SourceFormat::Json5),
+                (format!("{stem}.yaml"), SourceFormat::Yaml),
+            ],
+            None => vec![(suffix.to_string(), SourceFormat::of(suffix))],
+        }
+    }
+
+    /// [path] without a schema file suffix of [suffix], see [SourceFormat::suffixes], or without its
+    /// JSON, JSON5 or YAML extension, e.g. `common/v1` for `common/v1.json`
+    pub fn strip_suffix<'a>(path: &'a str, suffix: &str) -> &'a str {
+        SourceFormat::suffixes(suffix).iter()
+            .find_map(|(suffix, _)| path.strip_suffix(suffix.as_str()))
+            .or_else(|| [".json5", ".json", ".yaml", ".yml"].iter().find_map(|extension| path.strip_suffix(extension)))
+            .unwrap_or(path)
+    }
+
+    /// Format of the file [path] by its extension, JSON for unknown extensions
+    pub fn of(path: &str) -> SourceFormat {
+        if path.ends_with(".json5") {
+            SourceFormat::Json5
+        } else if path.ends_with(".yaml") || path.ends_with(".yml") {
+            SourceFormat::Yaml
+        } else {
+            SourceFormat::Json
+        }
+    }
+}
+
+/// Line and column of a source file, both start at 1, columns count characters
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Positions of the values of a parsed file by JSON pointer. Object members are at their keys.
+pub type Positions = BTreeMap<String, Position>;
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses schema file [text] of [format] into the same value whatever the format is
+pub fn parse_source(text: &str, format: SourceFormat) -> Result<(Value, Positions), ParseError> {
+    match format {
+        SourceFormat::Json => {
+            let value = serde_json::from_str(text).map_err(|err| ParseError {
+                position: Position { line: err.line(), column: err.column() },
+                message: err.to_string().split(" at line ").next().unwrap_or_default().to_string(),
+            })?;
+            // every JSON text is JSON5, its parser only records the positions here
+            let (_, positions) = json5::parse(text)?;
+            Ok((value, positions))
+        }
+        SourceFormat::Json5 => json5::parse(text),
+        SourceFormat::Yaml => yaml::parse(text),
+    }
+}
+
+/// Paths and positions of loaded schema files by file key, for error messages
+#[derive(Clone, Debug)]
+pub struct SourceMap {
+    suffix: String,
+    files: BTreeMap<String, (String, Positions)>,
+    /// URI by file key of the files loaded through the catalog
+    catalog_uris: BTreeMap<String, String>,
+}
+
+impl SourceMap {
+    /// Source map without files, paths fall back to the file key with the JSON [suffix]
+    pub fn new(suffix: &str) -> SourceMap {
+        SourceMap { suffix: suffix.to_string(), files: BTreeMap::new(), catalog_uris: BTreeMap::new() }
+    }
+
+    /// Adds the file [file_key] read from relative [path]
+    pub fn insert(&mut self, file_key: &str, path: &str, positions: Positions) {
+        self.files.insert(file_key.to_string(), (path.to_string(), positions));
+    }
+
+    /// Adds the file [file_key] read from [path] outside the schemas directory, the catalog maps [uri] to it
+    pub fn insert_catalog_file(&mut self, file_key: &str, path: &str, uri: &str, positions: Positions) {
+        self.insert(file_key, path, positions);
+        self.catalog_uris.insert(file_key.to_string(), uri.to_string());
+    }
+
+    /// URI of the file [file_key] if it is loaded through the catalog
+    pub fn catalog_uri(&self, file_key: &str) -> Option<&str> {
+        self.catalog_uris.get(file_key).map(String::as_str)
+    }
+
+    /// Configured JSON suffix of schema file names
+    pub fn suffix(&self) -> &str {
+        &self.suffix
+    }
+
+    /// Relative path of the file [file_key]
+    pub fn path(&self, file_key: &str) -> String {
+        match self.files.get(file_key) {
+            Some((path, _)) => path.clone(),
+            None => format!("{file_key}{}", self.suffix),
+        }
+    }
+
+    /// Position of the value at [pointer] of the file [file_key], or of its closest parent
+    /// for pointers into generated values
+    pub fn position(&self, file_key: &str, pointer: &str) -> Option<Position> {
+        let (_, positions) = self.files.get(file_key)?;
+        let mut pointer = pointer;
+        loop {
+            if let Some(position) = positions.get(pointer) {
+                return Some(*position);
+            }
+            pointer = &pointer[..pointer.rfind('/')?];
+        }
+    }
+
+    /// `path:line:column: #pointer` of [pointer] in the file [file_key], `path#pointer` without a position
+    pub fn locate(&self, file_key: &str, pointer: &str) -> String {
+        match self.position(file_key, pointer) {
+            Some(position) => format!("{}:{position}: #{pointer}", self.path(file_key)),
+            None => format!("{}#{pointer}", self.path(file_key)),
+        }
+    }
+}
+
+/// Number of a JSON5 or YAML numeric literal normalized to JSON, so `1.0` is the same number in every format
+pub fn json_number(literal: &str) -> Option<serde_json::Number> {
+    let (sign, digits) = match literal.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", literal.strip_prefix('+').unwrap_or(literal)),
+    };
+    // only one sign, `from_str_radix` and `from_str` would take another one
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) && !hex.starts_with(['+', '-']) {
+        let value = u64::from_str_radix(hex, 16).ok()?;
+        return match sign {
+            "-" => i64::try_from(value).ok().map(|value| (-value).into()),
+            _ => Some(value.into()),
+        };
+    }
+    // JSON has no leading or trailing decimal points
+    let mut normalized = sign.to_string();
+    if digits.starts_with('.') {
+        normalized.push('0');
+    }
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(index) => digits.split_at(index),
+        None => (digits, ""),
+    };
+    normalized.push_str(mantissa);
+    if mantissa.ends_with('.') {
+        normalized.push('0');
+    }
+    normalized.push_str(exponent);
+    serde_json::from_str(&normalized).ok()
+}
@@ -120,4 +120,510 @@ mod tests {
         assert_eq!(graph.nodes.len(), 5);
         assert_eq!(graph.edges.len(), 4);
     }
-}
\ No newline at end of file
+
+    fn pipeline_json(nodes: &str, edges: &str) -> String {
+        format!(r#"{{
+            "id": "pipeline",
+            "schema_version": "1",
+            "start_node_id": "start",
+            "input_variable_json_schema": {{}},
+            "state_schema": {{ "variables": {{}} }},
+            "graph": {{ "nodes": {nodes}, "edges": {edges} }}
+        }}"#)
+    }
+
+    fn agent_node_json(id: &str, model: &str, params: &str) -> String {
+        format!(r#"{{
+            "id": "{id}",
+            "type": "agent",
+            "ports": [{{ "id": "in", "direction": "input" }}, {{ "id": "out", "direction": "output" }}],
+            "config": {{ "kind": "agent", "agent_id": "demo-agent", "model": "{model}", "params": {params} }}
+        }}"#)
+    }
+
+    const MODEL_CATALOG_JSON: &str = r#"{
+        "models": {
+            "gpt-4.1-mini": {
+                "params": {
+                    "temperature": { "type": "number", "minimum": 0, "maximum": 2 },
+                    "max_tokens": { "type": "integer", "minimum": 1 }
+                }
+            },
+            "gpt-4.1": {}
+        },
+        "environments": {
+            "production": { "deny": ["gpt-4.1"] }
+        }
+    }"#;
+
+    #[test]
+    fn policy_reports_unknown_and_forbidden_models() {
+        use crate::policy::*;
+
+        let while_node = format!(r#"{{
+            "id": "node_while",
+            "type": "while",
+            "ports": [],
+            "config": {{
+                "kind": "while",
+                "condition": "true",
+                "body": {{ "nodes": [{}], "edges": [] }}
+            }}
+        }}"#, agent_node_json("b_agent", "gpt-4.1", "{}"));
+        let nodes = format!("[{}, {}]", agent_node_json("agent", "gpt-5", "{}"), while_node);
+        let pipeline = parse_json::<Pipeline>(&pipeline_json(&nodes, "[]")).unwrap();
+        let catalog = ModelCatalog::from_json(MODEL_CATALOG_JSON).unwrap();
+
+        let violations = catalog.check_pipeline(&pipeline, None).unwrap();
+        assert_eq!(violations, vec![
+            PolicyViolation {
+                node_id: "agent".to_string(),
+                kind: ViolationKind::UnknownModel { model: "gpt-5".to_string() },
+            },
+        ]);
+
+        let violations = catalog.check_pipeline(&pipeline, Some("production")).unwrap();
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[1].to_string(), "node `b_agent`: model `gpt-4.1` is not allowed in `production`");
+
+        let error = catalog.check_pipeline(&pipeline, Some("staging")).unwrap_err();
+        assert_eq!(error, PolicyError::UnknownEnvironment("staging".to_string()));
+    }
+
+    #[test]
+    fn policy_reports_invalid_params() {
+        use crate::policy::*;
+
+        let params = r#"{ "temperature": 3.5, "max_tokens": "many", "top_p": 0.9 }"#;
+        let nodes = format!("[{}]", agent_node_json("agent", "gpt-4.1-mini", params));
+        let pipeline = parse_json::<Pipeline>(&pipeline_json(&nodes, "[]")).unwrap();
+        let catalog = ModelCatalog::from_json(MODEL_CATALOG_JSON).unwrap();
+
+        let violations = catalog.check_pipeline(&pipeline, None).unwrap();
+        let messages = violations.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(messages, vec![
+            "node `agent`: param `max_tokens` must be integer, got \"many\"",
+            "node `agent`: param `temperature` = 3.5 is out of range [0, 2]",
+            "node `agent`: param `top_p` is not supported by model `gpt-4.1-mini`",
+        ]);
+    }
+
+    #[test]
+    fn diff_matches_nodes_and_edges_by_id() {
+        use crate::diff::*;
+
+        let while_node = |condition: &str, body_model: &str| format!(r#"{{
+            "id": "node_while",
+            "type": "while",
+            "ports": [{{ "id": "in", "direction": "input" }}],
+            "config": {{
+                "kind": "while",
+                "condition": {{ "expression": "{condition}", "format": "cel" }},
+                "body": {{ "nodes": [{}], "edges": [] }}
+            }}
+        }}"#, agent_node_json("b_agent", body_model, "{}"));
+        let edge = |id: &str, target: &str| format!(r#"{{
+            "id": "{id}",
+            "source_node_id": "agent",
+            "source_port_id": "out",
+            "target_node_id": "{target}",
+            "target_port_id": "in"
+        }}"#);
+
+        let old_nodes = format!("[{}, {}]", agent_node_json("agent", "gpt-4.1-mini", "{}"), while_node("state.count < 3", "gpt-4.1-mini"));
+        let old_edges = format!("[{}, {}]", edge("e1", "node_while"), edge("e2", "node_while"));
+        let old = parse_json::<Pipeline>(&pipeline_json(&old_nodes, &old_edges)).unwrap();
+
+        let reordered_nodes = format!("[{}, {}]", while_node("state.count < 3", "gpt-4.1-mini"), agent_node_json("agent", "gpt-4.1-mini", "{}"));
+        let reordered_edges = format!("[{}, {}]", edge("e2", "node_while"), edge("e1", "node_while"));
+        let reordered = parse_json::<Pipeline>(&pipeline_json(&reordered_nodes, &reordered_edges)).unwrap();
+        assert!(diff(&old, &reordered).is_empty());
+
+        let new_nodes = format!("[{}, {}]", while_node("state.count < 5", "gpt-4.1"), agent_node_json("agent_2", "gpt-4.1-mini", "{}"));
+        let new_edges = format!("[{}, {}]", edge("e1", "agent_2"), edge("e3", "node_while"));
+        let new = parse_json::<Pipeline>(&pipeline_json(&new_nodes, &new_edges)).unwrap();
+
+        let pipeline_diff = diff(&old, &new);
+        assert_eq!(pipeline_diff.removed_nodes[0].0.id, "agent");
+        assert_eq!(pipeline_diff.added_nodes[0].0.id, "agent_2");
+        assert_eq!(pipeline_diff.to_string(), r#"- node agent (agent)
++ node agent_2 (agent)
+~ node node_while
+    ~ expression /condition: `state.count < 3` (cel) -> `state.count < 5` (cel)
+~ node node_while/b_agent
+    config ~ /model: "gpt-4.1-mini" -> "gpt-4.1"
+- edge e2: agent.out -> node_while.in
++ edge e3: agent.out -> node_while.in
+~ edge e1
+    ~ /target_node_id: "node_while" -> "agent_2"
+"#);
+    }
+
+    #[test]
+    fn merge3_combines_independent_changes() {
+        use crate::merge::*;
+
+        let edge = r#"{
+            "id": "e1",
+            "source_node_id": "agent",
+            "source_port_id": "out",
+            "target_node_id": "agent_2",
+            "target_port_id": "in"
+        }"#;
+        let base_nodes = format!("[{}]", agent_node_json("agent", "gpt-4.1-mini", "{}"));
+        let base = parse_json::<Pipeline>(&pipeline_json(&base_nodes, "[]")).unwrap();
+
+        let ours_nodes = format!("[{}]", agent_node_json("agent", "gpt-4.1", "{}"));
+        let ours = parse_json::<Pipeline>(&pipeline_json(&ours_nodes, "[]")).unwrap();
+
+        let theirs_nodes = format!(
+            "[{}, {}]",
+            agent_node_json("agent", "gpt-4.1-mini", r#"{ "temperature": 0.3 }"#),
+            agent_node_json("agent_2", "gpt-4.1-mini", "{}")
+        );
+        let theirs = parse_json::<Pipeline>(&pipeline_json(&theirs_nodes, &format!("[{edge}]"))).unwrap();
+
+        let merged = merge3(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged.graph.nodes.len(), 2);
+        assert_eq!(merged.graph.edges.len(), 1);
+        let Node::Agent(agent) = &merged.graph.nodes[0] else {
+            panic!("agent node expected, got {:?}", merged.graph.nodes[0]);
+        };
+        assert_eq!(agent.config.model.as_deref(), Some("gpt-4.1"));
+        assert_eq!(agent.config.params["temperature"], 0.3);
+    }
+
+    #[test]
+    fn merge3_reports_conflicts_and_dangling_edges() {
+        use crate::merge::*;
+        use crate::diff::ScopedId;
+
+        let edge = r#"{
+            "id": "e1",
+            "source_node_id": "agent",
+            "source_port_id": "out",
+            "target_node_id": "agent_2",
+            "target_port_id": "in"
+        }"#;
+        let base_nodes = format!(
+            "[{}, {}]",
+            agent_node_json("agent", "gpt-4.1-mini", "{}"),
+            agent_node_json("agent_2", "gpt-4.1-mini", "{}")
+        );
+        let base = parse_json::<Pipeline>(&pipeline_json(&base_nodes, "[]")).unwrap();
+
+        let ours_nodes = format!("[{}]", agent_node_json("agent", "gpt-4.1", "{}"));
+        let ours = parse_json::<Pipeline>(&pipeline_json(&ours_nodes, "[]")).unwrap();
+
+        let theirs_nodes = format!(
+            "[{}, {}]",
+            agent_node_json("agent", "o3", "{}"),
+            agent_node_json("agent_2", "gpt-4.1-mini", "{}")
+        );
+        let theirs = parse_json::<Pipeline>(&pipeline_json(&theirs_nodes, &format!("[{edge}]"))).unwrap();
+
+        let conflicts = merge3(&base, &ours, &theirs).unwrap_err();
+        assert_eq!(conflicts, vec![
+            Conflict::Content {
+                element: MergeElement::Node(ScopedId { scope: vec![], id: "agent".to_string() }),
+                pointer: "/config/model".to_string(),
+                base: Some(serde_json::json!("gpt-4.1-mini")),
+                ours: Some(serde_json::json!("gpt-4.1")),
+                theirs: Some(serde_json::json!("o3")),
+            },
+            Conflict::DanglingEdge {
+                edge: ScopedId { scope: vec![], id: "e1".to_string() },
+                endpoint: "agent_2.in".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn canonicalize_orders_keys_nodes_and_edges() {
+        use crate::canonical::canonicalize;
+
+        let pipeline_json = r#"{
+            "state_schema": { "variables": {} },
+            "graph": {
+                "edges": [
+                    { "target_port_id": "in", "target_node_id": "end", "source_port_id": "out", "source_node_id": "b", "id": "e2" },
+                    { "target_port_id": "in", "target_node_id": "b", "source_port_id": "out", "source_node_id": "start", "id": "e1" }
+                ],
+                "nodes": [
+                    { "type": "end", "ports": [{ "direction": "input", "id": "in" }], "id": "end", "config": { "kind": "end" } },
+                    {
+                        "type": "transform",
+                        "ports": [],
+                        "id": "b",
+                        "config": { "expr": { "format": "cel", "expression": "1" }, "mode": "expression", "kind": "transform" }
+                    },
+                    { "type": "start", "ports": [], "id": "start", "config": { "kind": "start", "initial_state": { "b": 1, "a": 2 } } }
+                ]
+            },
+            "input_variable_json_schema": {},
+            "start_node_id": "start",
+            "schema_version": "1",
+            "id": "pipeline"
+        }"#;
+        let pipeline = parse_json::<Pipeline>(pipeline_json).unwrap();
+
+        let canonical = canonicalize(&pipeline);
+        assert_eq!(canonical, r#"{
+  "id": "pipeline",
+  "schema_version": "1",
+  "start_node_id": "start",
+  "input_variable_json_schema": {},
+  "state_schema": {
+    "variables": {}
+  },
+  "graph": {
+    "nodes": [
+      {
+        "id": "start",
+        "type": "start",
+        "ports": [],
+        "config": {
+          "kind": "start",
+          "initial_state": {
+            "a": 2,
+            "b": 1
+          }
+        }
+      },
+      {
+        "id": "b",
+        "type": "transform",
+        "ports": [],
+        "config": {
+          "kind": "transform",
+          "mode": "expression",
+          "expr": {
+            "expression": "1",
+            "format": "cel"
+          }
+        }
+      },
+      {
+        "id": "end",
+        "type": "end",
+        "ports": [
+          {
+            "id": "in",
+            "direction": "input"
+          }
+        ],
+        "config": {
+          "kind": "end"
+        }
+      }
+    ],
+    "edges": [
+      {
+        "id": "e1",
+        "source_node_id": "start",
+        "source_port_id": "out",
+        "target_node_id": "b",
+        "target_port_id": "in"
+      },
+      {
+        "id": "e2",
+        "source_node_id": "b",
+        "source_port_id": "out",
+        "target_node_id": "end",
+        "target_port_id": "in"
+      }
+    ]
+  }
+}
+"#);
+        let reparsed = parse_json::<Pipeline>(&canonical).unwrap();
+        assert_eq!(canonicalize(&reparsed), canonical);
+    }
+
+    #[test]
+    fn normalize_and_compact_shorthand_expressions() {
+        use crate::normalize::*;
+
+        let end_node = r#"{
+            "id": "end",
+            "type": "end",
+            "ports": [],
+            "config": { "kind": "end", "output_selector": { "mode": "expression", "expression": "state.movies" } }
+        }"#;
+        let while_node = format!(r#"{{
+            "id": "node_while",
+            "type": "while",
+            "ports": [],
+            "config": {{
+                "kind": "while",
+                "condition": {{ "expression": "state.continue", "format": "jsonata" }},
+                "body": {{ "nodes": [{end_node}], "edges": [] }}
+            }}
+        }}"#);
+        let nodes = format!("[{while_node}, {end_node}]");
+        let original = parse_json::<Pipeline>(&pipeline_json(&nodes, "[]")).unwrap();
+
+        let mut pipeline = original.clone();
+        normalize(&mut pipeline);
+        let [Node::While(while_node), Node::End(end_node)] = pipeline.graph.nodes.as_slice() else {
+            panic!("while and end nodes expected, got {:?}", pipeline.graph.nodes);
+        };
+        let Node::End(body_end_node) = &while_node.config.body.as_ref().unwrap().nodes[0] else {
+            panic!("end node expected in the while body");
+        };
+        // expression specs are compared as JSON, `ExpressionSpec` may be replaced by another type
+        let selector_expression = &body_end_node.config.output_selector.as_ref().unwrap().expression;
+        assert_eq!(serde_json::to_value(selector_expression).unwrap(), default_format_spec("state.movies"));
+        let selector_spec = end_node.config.output_selector.as_ref().unwrap().expression_spec();
+        assert_eq!(serde_json::to_value(selector_spec).unwrap()["format"], "cel");
+        assert_eq!(serde_json::to_value(while_node.config.condition_spec()).unwrap()["format"], "jsonata");
+
+        compact(&mut pipeline);
+        assert_eq!(pipeline, original);
+    }
+
+    #[test]
+    fn migrations_upgrade_to_latest_version() {
+        use crate::migrations::*;
+
+        fn rename_agent(value: &mut serde_json::Value) -> Result<(), String> {
+            let Some(nodes) = value.pointer_mut("/graph/nodes").and_then(serde_json::Value::as_array_mut) else {
+                return Err("pipeline has no nodes".to_string());
+            };
+            for node in nodes {
+                if let Some(config) = node.get_mut("config").and_then(serde_json::Value::as_object_mut)
+                    && let Some(agent) = config.remove("agent")
+                {
+                    config.insert("agent_id".to_string(), agent);
+                }
+            }
+            Ok(())
+        }
+        fn no_op(_: &mut serde_json::Value) -> Result<(), String> {
+            Ok(())
+        }
+
+        let registry = MigrationRegistry::new("3")
+            .with_migration(Migration { from: "2", to: "3", description: "no-op", apply: no_op })
+            .with_migration(Migration { from: "1", to: "2", description: "rename agent", apply: rename_agent });
+
+        let nodes = r#"[{
+            "id": "agent",
+            "type": "agent",
+            "ports": [],
+            "config": { "kind": "agent", "agent": "demo-agent" }
+        }]"#;
+        let value = serde_json::from_str::<serde_json::Value>(&pipeline_json(nodes, "[]")).unwrap();
+        let (pipeline, report) = registry.upgrade(value.clone()).unwrap();
+        assert_eq!(pipeline.schema_version, "3");
+        let Node::Agent(agent) = &pipeline.graph.nodes[0] else {
+            panic!("agent node expected, got {:?}", pipeline.graph.nodes[0]);
+        };
+        assert_eq!(agent.config.agent_id, "demo-agent");
+        assert_eq!(report.applied.len(), 2);
+        assert_eq!(report.to_string(), r#"schema version 1 -> 3
+  1 -> 2: rename agent
+    - /graph/nodes/0/config/agent: "demo-agent"
+    + /graph/nodes/0/config/agent_id: "demo-agent"
+    ~ /schema_version: "1" -> "2"
+  2 -> 3: no-op
+    ~ /schema_version: "2" -> "3"
+"#);
+
+        let mut unknown = value.clone();
+        unknown["schema_version"] = serde_json::json!("0");
+        assert_eq!(registry.upgrade(unknown).unwrap_err(), MigrationError::UnknownVersion("0".to_string()));
+
+        // known versions without a migration between them upgrade unchanged
+        let registry = MigrationRegistry::new("4").with_version("1").with_version("2").with_version("3")
+            .with_migration(Migration { from: "2", to: "3", description: "no-op", apply: no_op });
+        let mut released = value;
+        released["schema_version"] = serde_json::json!("1");
+        released["graph"]["nodes"] = serde_json::json!([]);
+        let (pipeline, report) = registry.upgrade(released).unwrap();
+        assert_eq!(pipeline.schema_version, "4");
+        assert_eq!(report.to_string(), r#"schema version 1 -> 4
+  1 -> 2: no schema changes
+    ~ /schema_version: "1" -> "2"
+  2 -> 3: no-op
+    ~ /schema_version: "2" -> "3"
+  3 -> 4: no schema changes
+    ~ /schema_version: "3" -> "4"
+"#);
+    }
+
+    #[test]
+    fn builtin_migrations_upgrade_every_released_version() {
+        use crate::migrations::*;
+
+        let nodes = format!("[{}]", agent_node_json("agent", "gpt-4.1-mini", "{}"));
+        let stored = serde_json::from_str::<serde_json::Value>(&pipeline_json(&nodes, "[]")).unwrap();
+        let released_versions = SCHEMA_VERSIONS.iter().copied()
+            .chain(MIGRATIONS.iter().map(|migration| migration.from))
+            .chain([crate::SCHEMA_VERSION]);
+        for version in released_versions {
+            let mut value = stored.clone();
+            value["schema_version"] = serde_json::json!(version);
+            let (pipeline, report) = upgrade_to_latest_with_report(value)
+                .unwrap_or_else(|err| panic!("schema version {version}: {err}"));
+            assert_eq!(pipeline.schema_version, crate::SCHEMA_VERSION);
+            assert_eq!(report.to_version, crate::SCHEMA_VERSION);
+            assert_eq!(pipeline.graph.nodes.len(), 1, "schema version {version}");
+        }
+
+        let mut unreleased = stored;
+        unreleased["schema_version"] = serde_json::json!("unreleased");
+        assert_eq!(upgrade_to_latest(unreleased).unwrap_err(), MigrationError::UnknownVersion("unreleased".to_string()));
+    }
+
+    #[test]
+    fn nodes_deserialize_by_type() {
+        // an end node with only required fields also has the shape of a start node
+        let nodes = r#"[
+            { "id": "end", "type": "end", "ports": [], "config": { "kind": "end" } },
+            { "id": "start", "type": "start", "ports": [], "config": { "kind": "start" } }
+        ]"#;
+        let pipeline = parse_json::<Pipeline>(&pipeline_json(nodes, "[]")).unwrap();
+        assert!(matches!(pipeline.graph.nodes.as_slice(), [Node::End(_), Node::Start(_)]));
+
+        let err = parse_json::<Node>(r#"{ "id": "loop", "type": "for_each", "ports": [], "config": {} }"#).unwrap_err();
+        assert!(err.to_string().starts_with("unknown type `for_each`, expected one of"), "{err}");
+        let err = parse_json::<Node>(r#"{ "id": "end", "type": "end", "ports": [], "config": { "kind": "end", "extra": 1 } }"#).unwrap_err();
+        assert!(err.to_string().contains("unknown field `extra`"), "{err}");
+    }
+
+    #[test]
+    fn node_constructors_fill_type_kind_and_ports() {
+        let agent = Node::agent("b_agent", "demo-agent")
+            .model("gpt-4.1-mini")
+            .param("temperature", 0.3)
+            .input("input", "{{workflow.input_as_text}}")
+            .output_to_state("agent_output", "agent_output")
+            .build();
+        let mut expected: serde_json::Value = serde_json::from_str(&agent_node_json("b_agent", "gpt-4.1-mini", r#"{ "temperature": 0.3 }"#)).unwrap();
+        expected["config"]["input_mapping"] = serde_json::json!({ "fields": { "input": "{{workflow.input_as_text}}" } });
+        expected["config"]["output_mapping"] = serde_json::json!({ "to_state": { "agent_output": "agent_output" } });
+        assert!(matches!(agent, Node::Agent(_)));
+        assert_eq!(serde_json::to_value(&agent).unwrap(), expected);
+
+        let if_else = Node::if_else("check").case("case-0", "size(state.movies) == 3").fallback("fallback").build();
+        let Node::IfElse(if_else) = if_else else {
+            panic!("if-else node expected, got {if_else:?}");
+        };
+        let port_ids = if_else.ports.iter().map(|port| (port.id.as_str(), port.direction)).collect::<Vec<_>>();
+        assert_eq!(port_ids, [("in", PortDirection::Input), ("case-0", PortDirection::Output), ("fallback", PortDirection::Output)]);
+        assert_eq!(serde_json::to_value(&if_else.config.cases[0].predicate).unwrap()["format"], "cel");
+
+        let body = [Node::set_state("b_set").assign("count", "state.count + 1").build()];
+        let while_node = Node::while_loop("loop", "state.count < 3").max_iterations(10).body(body, []).build();
+        let Node::While(while_node) = while_node else {
+            panic!("while node expected, got {while_node:?}");
+        };
+        assert_eq!(while_node.config.body.unwrap().nodes.len(), 1);
+        assert_eq!(while_node.config.max_iterations, Some(10));
+
+        let nodes = serde_json::to_string(&[Node::start("start").build(), Node::end("end").output_expression("state.count").build()]).unwrap();
+        let pipeline: Pipeline = serde_json::from_str(&pipeline_json(&nodes, "[]")).unwrap();
+        assert_eq!(pipeline.graph.nodes.iter().map(Node::node_type).collect::<Vec<_>>(), ["start", "end"]);
+    }
+}
@@ -0,0 +1,708 @@
+use crate::lint::escape_pointer;
+use crate::sources::{json_number, ParseError, Position, Positions};
+use serde_json::{Map, Value};
+
+/// Parses a YAML 1.2 document [text] with the JSON-compatible subset schemas need:
+/// - block mappings and sequences indented with spaces, a sequence may start at the indentation of its key
+/// - flow `[..]` and `{..}` collections, also over several lines and with trailing commas
+/// - plain scalars, folded when they continue on more indented lines, and `'..'` and `".."` scalars
+///   with the YAML escapes, e.g. `\t`, `\x41` or `\u00e9`
+/// - `|` and `>` block scalars with `-`/`+` chomping and an indentation indicator
+/// - `#` comments, and a leading `---` and trailing `...` marker
+///
+/// Plain scalars resolve with the core schema: `~` and `null` are null, `true` and `false` booleans,
+/// decimal, `0o` and `0x` integers and floats numbers, anything else, e.g. `yes` or `1_000`, a string.
+/// Anchors, aliases, tags, `?` complex keys, directives, several documents, tab indentation,
+/// duplicate keys, collections as flow mapping keys and `.inf` or `.nan` have no JSON equivalent
+/// and are errors with the position of the offending value.
+pub fn parse(text: &str) -> Result<(Value, Positions), ParseError> {
+    let mut lines = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let raw = raw.strip_prefix('\u{feff}').filter(|_| index == 0).unwrap_or(raw);
+        let indent = raw.len() - raw.trim_start_matches(' ').len();
+        let text = raw[indent..].to_string();
+        if text.starts_with('\t') && !text.trim().is_empty() {
+            return Err(ParseError { position: Position { line: index + 1, column: indent + 1 }, message: "tabs can not indent YAML".to_string() });
+        }
+        lines.push(Line { number: index + 1, indent, text, raw: raw.to_string() });
+    }
+
+    let mut parser = Parser { lines, index: 0, positions: Positions::new() };
+    parser.skip_blank();
+    if let Some(line) = parser.line() {
+        if line.text.starts_with('%') {
+            return Err(parser.error_at(parser.index, line.indent, "YAML directives are not supported"));
+        }
+        if line.indent == 0 && is_marker(&line.text, "---") {
+            if line.text.trim_end() != "---" && !line.text[3..].trim_start().starts_with('#') {
+                return Err(parser.error_at(parser.index, 4, "content after `---` is not supported, start it on the next line"));
+            }
+            parser.index += 1;
+            parser.skip_blank();
+        }
+    }
+
+    let value = match parser.line() {
+        Some(line) if !(line.indent == 0 && is_marker(&line.text, "...")) => {
+            parser.positions.insert(String::new(), Position { line: line.number, column: line.indent + 1 });
+            parser.parse_block(0, "")?
+        }
+        _ => Value::Null,
+    };
+
+    parser.skip_blank();
+    if let Some(line) = parser.line() {
+        if line.indent == 0 && is_marker(&line.text, "---") {
+            return Err(parser.error_at(parser.index, 0, "files with several YAML documents are not supported"));
+        }
+        if !(line.indent == 0 && is_marker(&line.text, "...")) {
+            return Err(parser.error_at(parser.index, line.indent, "unexpected content, check the indentation"));
+        }
+    }
+    Ok((value, parser.positions))
+}
+
+struct Line {
+    number: usize,
+    /// Spaces before [text]
+    indent: usize,
+    text: String,
+    raw: String,
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    index: usize,
+    positions: Positions,
+}
+
+/// Character of a multi-line value with its line index and 0-based column
+type Char = (char, usize, usize);
+
+impl Parser {
+    fn line(&self) -> Option<&Line> {
+        self.lines.get(self.index)
+    }
+
+    fn error_at(&self, line_index: usize, column: usize, message: impl Into<String>) -> ParseError {
+        let line = self.lines.get(line_index).map_or(self.lines.len() + 1, |line| line.number);
+        ParseError { position: Position { line, column: column + 1 }, message: message.into() }
+    }
+
+    /// Skips empty and comment lines
+    fn skip_blank(&mut self) {
+        while self.line().is_some_and(|line| is_blank(&line.text)) {
+            self.index += 1;
+        }
+    }
+
+    /// Node starting at the current line, indented by at least [min_indent]
+    fn parse_block(&mut self, min_indent: usize, pointer: &str) -> Result<Value, ParseError> {
+        self.skip_blank();
+        let Some(line) = self.line() else {
+            return Ok(Value::Null);
+        };
+        let indent = line.indent;
+        if indent < min_indent {
+            return Ok(Value::Null);
+        }
+        if is_sequence_entry(&line.text) {
+            self.parse_sequence(indent, pointer)
+        } else if self.mapping_key(self.index)?.is_some() {
+            self.parse_mapping(indent, pointer)
+        } else {
+            let column = indent;
+            self.parse_inline(column, min_indent.saturating_sub(1), pointer)
+        }
+    }
+
+    fn parse_mapping(&mut self, indent: usize, pointer: &str) -> Result<Value, ParseError> {
+        let mut map = Map::new();
+        loop {
+            self.skip_blank();
+            let Some(line) = self.line() else {
+                break;
+            };
+            if line.indent < indent || (line.indent == 0 && (is_marker(&line.text, "---") || is_marker(&line.text, "..."))) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(self.error_at(self.index, line.indent, "unexpected indentation"));
+            }
+            let Some((key, value_column)) = self.mapping_key(self.index)? else {
+                return Err(self.error_at(self.index, indent, "expected a mapping key"));
+            };
+            let position = Position { line: line.number, column: indent + 1 };
+            if map.contains_key(&key) {
+                return Err(self.error_at(self.index, indent, format!("duplicate key `{key}`")));
+            }
+            let member_pointer = format!("{pointer}/{}", escape_pointer(&key));
+            let rest_is_blank = is_blank(&line.text[value_column - indent..]);
+            self.positions.insert(member_pointer.clone(), position);
+
+            let value = if rest_is_blank {
+                self.index += 1;
+                self.skip_blank();
+                match self.line() {
+                    Some(next) if next.indent > indent => self.parse_block(indent + 1, &member_pointer)?,
+                    // sequences may stay at the indentation of their key
+                    Some(next) if next.indent == indent && is_sequence_entry(&next.text) => self.parse_sequence(indent, &member_pointer)?,
+                    _ => Value::Null,
+                }
+            } else {
+                self.parse_inline(value_column, indent, &member_pointer)?
+            };
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn parse_sequence(&mut self, indent: usize, pointer: &str) -> Result<Value, ParseError> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank();
+            let Some(line) = self.line() else {
+                break;
+            };
+            if line.indent < indent || !is_sequence_entry(&line.text) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(self.error_at(self.index, line.indent, "unexpected indentation"));
+            }
+            let item_pointer = format!("{pointer}/{}", values.len());
+            let rest = &line.text[1..];
+            let item_indent = indent + 1 + (rest.len() - rest.trim_start_matches(' ').len());
+            let rest_is_blank = is_blank(rest);
+            // items are at their value, or at the `-` when the value starts on the next line
+            let column = if rest_is_blank { indent } else { item_indent };
+            let position = Position { line: line.number, column: column + 1 };
+            self.positions.insert(item_pointer.clone(), position);
+
+            let value = if rest_is_blank {
+                self.index += 1;
+                self.parse_block(indent + 1, &item_pointer)?
+            } else {
+                // `- key: value` and `- - item` start a collection at the column of their content
+                let line = &mut self.lines[self.index];
+                line.text = line.text[item_indent - indent..].to_string();
+                line.indent = item_indent;
+                if is_sequence_entry(&line.text) {
+                    self.parse_sequence(item_indent, &item_pointer)?
+                } else if self.mapping_key(self.index)?.is_some() {
+                    self.parse_mapping(item_indent, &item_pointer)?
+                } else {
+                    self.parse_inline(item_indent, indent, &item_pointer)?
+                }
+            };
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    /// Key of the mapping entry on the line [line_index] and the column of its value
+    fn mapping_key(&self, line_index: usize) -> Result<Option<(String, usize)>, ParseError> {
+        let line = &self.lines[line_index];
+        let text = &line.text;
+        if let Some(first) = text.chars().next() {
+            check_indicator(first).map_err(|message| self.error_at(line_index, line.indent, message))?;
+        }
+        let chars = text.chars().collect::<Vec<_>>();
+        let (key, after_key) = match chars.first() {
+            Some('"' | '\'') => {
+                let stream = chars.iter().enumerate().map(|(column, c)| (*c, line_index, line.indent + column)).collect::<Vec<_>>();
+                let mut cursor = 0;
+                let Ok(key) = self.parse_quoted(&stream, &mut cursor) else {
+                    return Ok(None);
+                };
+                let mut after_key = cursor;
+                while chars.get(after_key) == Some(&' ') {
+                    after_key += 1;
+                }
+                if chars.get(after_key) != Some(&':') {
+                    return Ok(None);
+                }
+                (key, after_key)
+            }
+            Some('[' | '{') | None => return Ok(None),
+            Some(_) => {
+                let Some(colon) = find_value_indicator(&chars) else {
+                    return Ok(None);
+                };
+                (chars[..colon].iter().collect::<String>().trim_end().to_string(), colon)
+            }
+        };
+        if chars.get(after_key + 1).is_some_and(|c| !c.is_whitespace()) {
+            return Ok(None);
+        }
+        let mut value_column = after_key + 1;
+        while chars.get(value_column).is_some_and(|c| *c == ' ') {
+            value_column += 1;
+        }
+        let byte_column = chars[..value_column].iter().collect::<String>().len();
+        Ok(Some((key, line.indent + byte_column)))
+    }
+
+    /// Scalar or flow collection starting at byte [column] of the current line, continuation
+    /// lines of plain and quoted scalars are indented more than [parent_indent]
+    fn parse_inline(&mut self, column: usize, parent_indent: usize, pointer: &str) -> Result<Value, ParseError> {
+        let line_index = self.index;
+        let line = &self.lines[line_index];
+        let text = &line.raw[column..];
+        let char_column = line.raw[..column].chars().count();
+        let Some(first) = text.chars().next() else {
+            self.index += 1;
+            return Ok(Value::Null);
+        };
+        check_indicator(first).map_err(|message| self.error_at(line_index, char_column, message))?;
+
+        match first {
+            '|' | '>' => self.parse_block_scalar(column, parent_indent),
+            '[' | '{' | '"' | '\'' => {
+                let stream = self.stream_from(line_index, column);
+                let mut cursor = 0;
+                let value = match first {
+                    '[' | '{' => self.parse_flow(&stream, &mut cursor, pointer)?,
+                    _ => Value::String(self.parse_quoted(&stream, &mut cursor)?),
+                };
+                // the rest of the last line may only be a comment
+                let (end_line, end_column) = match stream.get(cursor) {
+                    Some((_, end_line, end_column)) => (*end_line, *end_column),
+                    None => (self.lines.len() - 1, usize::MAX),
+                };
+                let rest = self.lines[end_line].raw.chars().skip(end_column).collect::<String>();
+                if !rest.trim().is_empty() && !rest.trim_start().starts_with('#') {
+                    let column = end_column + rest.chars().count() - rest.trim_start().chars().count();
+                    return Err(self.error_at(end_line, column, format!("unexpected `{}` after the value", rest.trim())));
+                }
+                self.index = end_line + 1;
+                Ok(value)
+            }
+            _ => self.parse_plain(column, parent_indent),
+        }
+    }
+
+    /// Plain scalar with its continuation lines, folded into one line
+    fn parse_plain(&mut self, column: usize, parent_indent: usize) -> Result<Value, ParseError> {
+        let line_index = self.index;
+        let text = strip_comment(&self.lines[line_index].raw[column..]);
+        let chars = text.chars().collect::<Vec<_>>();
+        if let Some(colon) = find_value_indicator(&chars) {
+            let column = self.lines[line_index].raw[..column].chars().count() + colon;
+            return Err(self.error_at(line_index, column, "mapping values are not allowed here, quote the value if `: ` is part of it"));
+        }
+        let mut scalar = text.trim().to_string();
+        self.index += 1;
+
+        let mut multi_line = false;
+        let mut empty_lines = 0;
+        while let Some(line) = self.line() {
+            if line.text.trim().is_empty() {
+                empty_lines += 1;
+                self.index += 1;
+                continue;
+            }
+            if line.indent <= parent_indent || line.text.starts_with('#') || self.mapping_key(self.index)?.is_some() {
+                break;
+            }
+            let continuation = strip_comment(&line.text).trim().to_string();
+            scalar.push_str(&if empty_lines == 0 { " ".to_string() } else { "\n".repeat(empty_lines) });
+            scalar.push_str(&continuation);
+            multi_line = true;
+            empty_lines = 0;
+            let has_comment = strip_comment(&line.text).len() != line.text.len();
+            self.index += 1;
+            if has_comment {
+                break;
+            }
+        }
+        // trailing empty lines belong to whatever follows
+        self.index -= empty_lines;
+        Ok(if multi_line { Value::String(scalar) } else { resolve_plain(&scalar).map_err(|message| self.error_at(line_index, column, message))? })
+    }
+
+    /// Literal `|` or folded `>` scalar with its header at byte [column] of the current line
+    fn parse_block_scalar(&mut self, column: usize, parent_indent: usize) -> Result<Value, ParseError> {
+        let header_index = self.index;
+        let header = strip_comment(&self.lines[header_index].raw[column..]).trim_end().to_string();
+        let literal = header.starts_with('|');
+        let mut chomping = '\0';
+        let mut explicit_indent = None;
+        for c in header.chars().skip(1) {
+            match c {
+                '-' | '+' if chomping == '\0' => chomping = c,
+                '1'..='9' if explicit_indent.is_none() => explicit_indent = c.to_digit(10).map(|digit| digit as usize),
+                _ => return Err(self.error_at(header_index, column, format!("invalid block scalar header `{header}`"))),
+            }
+        }
+        self.index += 1;
+
+        let content_indent = match explicit_indent {
+            Some(indent) => parent_indent + indent,
+            None => self.lines[self.index..].iter()
+                .find(|line| !line.raw.trim().is_empty())
+                .map_or(parent_indent + 1, |line| line.indent),
+        };
+        let mut content = Vec::new();
+        while let Some(line) = self.line() {
+            if line.raw.trim().is_empty() {
+                content.push(String::new());
+            } else if line.indent >= content_indent && content_indent > parent_indent {
+                content.push(line.raw[content_indent..].to_string());
+            } else {
+                break;
+            }
+            self.index += 1;
+        }
+        let trailing = content.iter().rev().take_while(|line| line.is_empty()).count();
+        // trailing empty lines belong to whatever follows unless they are kept
+        content.truncate(content.len() - trailing);
+        if chomping != '+' {
+            self.index -= trailing;
+        }
+
+        let mut scalar = if literal { content.join("\n") } else { fold(&content) };
+        match chomping {
+            '-' => {}
+            '+' => scalar.push_str(&"\n".repeat(trailing + usize::from(!content.is_empty()))),
+            _ if !content.is_empty() => scalar.push('\n'),
+            _ => {}
+        }
+        Ok(Value::String(scalar))
+    }
+
+    /// Characters from byte [column] of line [line_index] to the end of the file
+    fn stream_from(&self, line_index: usize, column: usize) -> Vec<Char> {
+        let mut stream = Vec::new();
+        for (index, line) in self.lines.iter().enumerate().skip(line_index) {
+            let start = if index == line_index { line.raw[..column].chars().count() } else { 0 };
+            stream.extend(line.raw.chars().enumerate().skip(start).map(|(char_column, c)| (c, index, char_column)));
+            stream.push(('\n', index, line.raw.chars().count()));
+        }
+        stream
+    }
+
+    fn stream_error(&self, stream: &[Char], cursor: usize, message: impl Into<String>) -> ParseError {
+        match stream.get(cursor) {
+            Some((_, line_index, column)) => self.error_at(*line_index, *column, message),
+            None => self.error_at(self.lines.len(), 0, message),
+        }
+    }
+
+    /// Skips spaces, line breaks and comments of a flow collection
+    fn skip_flow_whitespace(&self, stream: &[Char], cursor: &mut usize) {
+        while let Some((c, _, _)) = stream.get(*cursor) {
+            if *c == '#' && (*cursor == 0 || stream[*cursor - 1].0.is_whitespace()) {
+                while stream.get(*cursor).is_some_and(|(c, _, _)| *c != '\n') {
+                    *cursor += 1;
+                }
+            } else if c.is_whitespace() {
+                *cursor += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_flow(&mut self, stream: &[Char], cursor: &mut usize, pointer: &str) -> Result<Value, ParseError> {
+        self.skip_flow_whitespace(stream, cursor);
+        let Some((first, line_index, column)) = stream.get(*cursor).copied() else {
+            return Err(self.stream_error(stream, *cursor, "unexpected end of the file"));
+        };
+        check_indicator(first).map_err(|message| self.error_at(line_index, column, message))?;
+        match first {
+            '[' => {
+                *cursor += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_flow_whitespace(stream, cursor);
+                    match stream.get(*cursor) {
+                        Some((']', _, _)) => {
+                            *cursor += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        Some((_, line_index, column)) => {
+                            let item_pointer = format!("{pointer}/{}", values.len());
+                            self.positions.insert(item_pointer.clone(), Position { line: self.lines[*line_index].number, column: column + 1 });
+                            values.push(self.parse_flow(stream, cursor, &item_pointer)?);
+                        }
+                        None => return Err(self.error_at(line_index, column, "unterminated flow sequence")),
+                    }
+                    self.skip_flow_whitespace(stream, cursor);
+                    match stream.get(*cursor) {
+                        Some((',', _, _)) => *cursor += 1,
+                        Some((']', _, _)) => {}
+                        Some(_) => return Err(self.stream_error(stream, *cursor, "expected `,` or `]`")),
+                        None => return Err(self.error_at(line_index, column, "unterminated flow sequence")),
+                    }
+                }
+            }
+            '{' => {
+                *cursor += 1;
+                let mut map = Map::new();
+                loop {
+                    self.skip_flow_whitespace(stream, cursor);
+                    let (key_line, key_column) = match stream.get(*cursor) {
+                        Some(('}', _, _)) => {
+                            *cursor += 1;
+                            return Ok(Value::Object(map));
+                        }
+                        Some((_, key_line, key_column)) => (*key_line, *key_column),
+                        None => return Err(self.error_at(line_index, column, "unterminated flow mapping")),
+                    };
+                    let key = match self.parse_flow(stream, cursor, pointer)? {
+                        Value::String(key) => key,
+                        Value::Array(_) | Value::Object(_) => return Err(self.error_at(key_line, key_column, "mapping keys must be scalars")),
+                        key => key.to_string(),
+                    };
+                    if map.contains_key(&key) {
+                        return Err(self.error_at(key_line, key_column, format!("duplicate key `{key}`")));
+                    }
+                    let member_pointer = format!("{pointer}/{}", escape_pointer(&key));
+                    self.positions.insert(member_pointer.clone(), Position { line: self.lines[key_line].number, column: key_column + 1 });
+                    self.skip_flow_whitespace(stream, cursor);
+                    let value = match stream.get(*cursor) {
+                        Some((':', _, _)) => {
+                            *cursor += 1;
+                            self.skip_flow_whitespace(stream, cursor);
+                            match stream.get(*cursor) {
+                                Some((',' | '}', _, _)) => Value::Null,
+                                _ => self.parse_flow(stream, cursor, &member_pointer)?,
+                            }
+                        }
+                        _ => Value::Null,
+                    };
+                    map.insert(key, value);
+                    self.skip_flow_whitespace(stream, cursor);
+                    match stream.get(*cursor) {
+                        Some((',', _, _)) => *cursor += 1,
+                        Some(('}', _, _)) => {}
+                        Some(_) => return Err(self.stream_error(stream, *cursor, "expected `,` or `}`")),
+                        None => return Err(self.error_at(line_index, column, "unterminated flow mapping")),
+                    }
+                }
+            }
+            '"' | '\'' => self.parse_quoted(stream, cursor).map(Value::String),
+            _ => {
+                let start = *cursor;
+                while let Some((c, _, _)) = stream.get(*cursor) {
+                    let next_is_space = stream.get(*cursor + 1).is_none_or(|(next, _, _)| next.is_whitespace() || ",[]{}".contains(*next));
+                    if matches!(c, ',' | '[' | ']' | '{' | '}' | '\n') || (*c == ':' && next_is_space)
+                        || (*c == '#' && *cursor > start && stream[*cursor - 1].0.is_whitespace())
+                    {
+                        break;
+                    }
+                    *cursor += 1;
+                }
+                let scalar = stream[start..*cursor].iter().map(|(c, _, _)| c).collect::<String>();
+                resolve_plain(scalar.trim()).map_err(|message| self.error_at(line_index, column, message))
+            }
+        }
+    }
+
+    /// Single or double-quoted scalar, line breaks inside fold as in plain scalars
+    fn parse_quoted(&self, stream: &[Char], cursor: &mut usize) -> Result<String, ParseError> {
+        let start = *cursor;
+        let quote = stream[start].0;
+        *cursor += 1;
+        let mut scalar = String::new();
+        loop {
+            let Some((c, _, _)) = stream.get(*cursor).copied() else {
+                return Err(self.stream_error(stream, start, "unterminated string"));
+            };
+            *cursor += 1;
+            match c {
+                '\'' if quote == '\'' && stream.get(*cursor).is_some_and(|(c, _, _)| *c == '\'') => {
+                    *cursor += 1;
+                    scalar.push('\'');
+                }
+                c if c == quote => return Ok(scalar),
+                '\n' => {
+                    // trailing spaces end the line, leading spaces of the next one are indentation
+                    scalar.truncate(scalar.trim_end_matches([' ', '\t']).len());
+                    let mut empty_lines = 0;
+                    loop {
+                        while stream.get(*cursor).is_some_and(|(c, _, _)| *c == ' ' || *c == '\t') {
+                            *cursor += 1;
+                        }
+                        if stream.get(*cursor).is_some_and(|(c, _, _)| *c == '\n') {
+                            *cursor += 1;
+                            empty_lines += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                    scalar.push_str(&if empty_lines == 0 { " ".to_string() } else { "\n".repeat(empty_lines) });
+                }
+                '\\' if quote == '"' => {
+                    let Some((escape, line_index, column)) = stream.get(*cursor).copied() else {
+                        return Err(self.stream_error(stream, start, "unterminated string"));
+                    };
+                    *cursor += 1;
+                    let escaped = match escape {
+                        '0' => '\0',
+                        'a' => '\u{7}',
+                        'b' => '\u{8}',
+                        't' | '\t' => '\t',
+                        'n' => '\n',
+                        'v' => '\u{b}',
+                        'f' => '\u{c}',
+                        'r' => '\r',
+                        'e' => '\u{1b}',
+                        ' ' => ' ',
+                        '"' => '"',
+                        '/' => '/',
+                        '\\' => '\\',
+                        'N' => '\u{85}',
+                        '_' => '\u{a0}',
+                        'L' => '\u{2028}',
+                        'P' => '\u{2029}',
+                        'x' | 'u' | 'U' => {
+                            let digits = match escape { 'x' => 2, 'u' => 4, _ => 8 };
+                            let hex = stream.get(*cursor..*cursor + digits)
+                                .map(|chars| chars.iter().map(|(c, _, _)| c).collect::<String>())
+                                .and_then(|hex| u32::from_str_radix(&hex, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error_at(line_index, column, format!("invalid `\\{escape}` escape")))?;
+                            *cursor += digits;
+                            hex
+                        }
+                        // an escaped line break joins the lines without a space
+                        '\n' => {
+                            while stream.get(*cursor).is_some_and(|(c, _, _)| *c == ' ' || *c == '\t') {
+                                *cursor += 1;
+                            }
+                            continue;
+                        }
+                        _ => return Err(self.error_at(line_index, column, format!("invalid escape `\\{escape}`"))),
+                    };
+                    scalar.push(escaped);
+                }
+                c => scalar.push(c),
+            }
+        }
+    }
+}
+
+/// Folds lines of a `>` block scalar: lines join with a space, empty lines become line breaks,
+/// and more indented lines keep their breaks
+fn fold(lines: &[String]) -> String {
+    let is_more_indented = |line: &str| line.starts_with([' ', '\t']);
+    let mut folded = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 {
+            let previous = &lines[index - 1];
+            let separator = if line.is_empty() {
+                // the break before empty lines is dropped, unless a more indented line follows them
+                let next = lines[index..].iter().find(|line| !line.is_empty());
+                if previous.is_empty() || is_more_indented(previous) || next.is_some_and(|next| is_more_indented(next)) { "\n" } else { "" }
+            } else if previous.is_empty() || is_more_indented(previous) || is_more_indented(line) {
+                "\n"
+            } else {
+                " "
+            };
+            folded.push_str(separator);
+        }
+        folded.push_str(line);
+    }
+    folded
+}
+
+/// Value of a plain scalar by the YAML 1.2 core schema
+fn resolve_plain(scalar: &str) -> Result<Value, String> {
+    match scalar {
+        "" | "~" | "null" | "Null" | "NULL" => return Ok(Value::Null),
+        "true" | "True" | "TRUE" => return Ok(Value::Bool(true)),
+        "false" | "False" | "FALSE" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    let unsigned = scalar.trim_start_matches(['-', '+']);
+    if matches!(unsigned, ".inf" | ".Inf" | ".INF" | ".nan" | ".NaN" | ".NAN") {
+        return Err(format!("`{scalar}` can not be represented in JSON, quote it for a string"));
+    }
+    if let Some(octal) = scalar.strip_prefix("0o") && !octal.is_empty() && octal.chars().all(|c| c.is_digit(8)) {
+        return u64::from_str_radix(octal, 8).map(Value::from).map_err(|err| format!("invalid number `{scalar}`: {err}"));
+    }
+    if let Some(hex) = scalar.strip_prefix("0x") && !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return json_number(scalar).map(Value::Number).ok_or_else(|| format!("invalid number `{scalar}`"));
+    }
+    let digits = unsigned.chars().filter(char::is_ascii_digit).count();
+    if digits > 0 && scalar.len() - unsigned.len() <= 1 && unsigned.chars().all(|c| c.is_ascii_digit()) {
+        let integer = unsigned.trim_start_matches('0');
+        let integer = if integer.is_empty() { "0" } else { integer };
+        let sign = if scalar.starts_with('-') { "-" } else { "" };
+        return json_number(&format!("{sign}{integer}")).map(Value::Number).ok_or_else(|| format!("invalid number `{scalar}`"));
+    }
+    if digits > 0 && scalar.len() - unsigned.len() <= 1 && is_float(unsigned) {
+        return json_number(scalar).map(Value::Number).ok_or_else(|| format!("invalid number `{scalar}`"));
+    }
+    Ok(Value::String(scalar.to_string()))
+}
+
+/// Whether [unsigned] is `1.5`, `.5`, `1.` or any of them with an exponent
+fn is_float(unsigned: &str) -> bool {
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(index) => (&unsigned[..index], Some(&unsigned[index + 1..])),
+        None => (unsigned, None),
+    };
+    let mantissa_ok = match mantissa.split_once('.') {
+        Some((whole, fraction)) => whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) && !(whole.is_empty() && fraction.is_empty()),
+        None => exponent.is_some() && !mantissa.is_empty() && mantissa.chars().all(|c| c.is_ascii_digit()),
+    };
+    let exponent_ok = exponent.is_none_or(|exponent| {
+        let digits = exponent.trim_start_matches(['-', '+']);
+        !digits.is_empty() && exponent.len() - digits.len() <= 1 && digits.chars().all(|c| c.is_ascii_digit())
+    });
+    mantissa_ok && exponent_ok
+}
+
+/// Errors for indicators of YAML features without a JSON equivalent
+fn check_indicator(first: char) -> Result<(), String> {
+    match first {
+        '&' => Err("anchors are not supported".to_string()),
+        '*' => Err("aliases are not supported".to_string()),
+        '!' => Err("tags are not supported".to_string()),
+        '?' => Err("complex mapping keys are not supported".to_string()),
+        '@' | '`' => Err(format!("{first:?} is reserved, quote the value")),
+        _ => Ok(()),
+    }
+}
+
+/// Index of the `:` that separates a plain key from its value
+fn find_value_indicator(chars: &[char]) -> Option<usize> {
+    for (index, c) in chars.iter().enumerate() {
+        if *c == '#' && index > 0 && chars[index - 1].is_whitespace() {
+            return None;
+        }
+        if *c == ':' && chars.get(index + 1).is_none_or(|next| next.is_whitespace()) {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// [text] without a trailing comment, a `#` after whitespace
+fn strip_comment(text: &str) -> &str {
+    let mut previous = ' ';
+    for (index, c) in text.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &text[..index];
+        }
+        previous = c;
+    }
+    text
+}
+
+fn is_blank(text: &str) -> bool {
+    let text = text.trim_start();
+    text.is_empty() || text.starts_with('#')
+}
+
+fn is_sequence_entry(text: &str) -> bool {
+    text == "-" || text.starts_with("- ") || text.starts_with("-\t")
+}
+
+/// Whether [text] is the document [marker] `---` or `...`, alone or followed by whitespace
+fn is_marker(text: &str, marker: &str) -> bool {
+    text.strip_prefix(marker).is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))
+}
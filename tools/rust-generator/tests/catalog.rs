@@ -0,0 +1,131 @@
+use rust_generator::catalog::{resolve_ids, Catalog};
+use rust_generator::sources::SourceMap;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+fn catalog(entries: &[(&str, &str)]) -> Catalog {
+    let entries = entries.iter().map(|(uri, path)| (uri.to_string(), path.to_string())).collect();
+    Catalog::new(&entries).unwrap()
+}
+
+/// Directory under the test temp dir with files of [contents] by relative path
+fn vendor_dir(name: &str, contents: &[(&str, &str)]) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    for (path, content) in contents {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// Runs [resolve_ids] on [schemas] by file key, returns the resolved schemas and the source map
+fn resolve(schemas: &[(&str, Value)], catalog: &Catalog, schemas_path: &Path) -> Result<(BTreeMap<String, Value>, SourceMap), String> {
+    let mut schemas = schemas.iter().map(|(file_key, schema)| (file_key.to_string(), schema.clone())).collect();
+    let mut source_map = SourceMap::new(".schema.json");
+    resolve_ids(&mut schemas, &mut source_map, catalog, schemas_path).map_err(|err| err.to_string())?;
+    Ok((schemas, source_map))
+}
+
+#[test]
+fn catalog_uris_must_be_absolute_without_fragment() {
+    for uri in ["common/v1.json", "https://schemas.example.com/common.json#/$defs/port", "//schemas.example.com/common.json"] {
+        let entries = BTreeMap::from([(uri.to_string(), "common.json".to_string())]);
+        assert!(Catalog::new(&entries).is_err(), "`{uri}` is accepted");
+    }
+}
+
+#[test]
+fn catalog_looks_up_exact_uris_then_the_longest_prefix() {
+    let catalog = catalog(&[
+        ("https://schemas.example.com/common/v1.json", "vendor/common-v1.schema.json"),
+        ("https://schemas.example.com/", "vendor/all/"),
+        ("https://schemas.example.com/shared/", "/opt/shared/"),
+        ("urn:example:port", "/opt/port.json"),
+    ]);
+
+    assert_eq!(catalog.path("https://schemas.example.com/common/v1.json"), Some(PathBuf::from("vendor/common-v1.schema.json")));
+    assert_eq!(catalog.path("https://schemas.example.com/shared/port.json"), Some(PathBuf::from("/opt/shared/port.json")));
+    assert_eq!(catalog.path("https://schemas.example.com/shared/v2/edge.json"), Some(PathBuf::from("/opt/shared/v2/edge.json")));
+    assert_eq!(catalog.path("https://schemas.example.com/common/v2.json"), Some(PathBuf::from("vendor/all/common/v2.json")));
+    assert_eq!(catalog.path("urn:example:port"), Some(PathBuf::from("/opt/port.json")));
+    // a URI without `/` at the end maps only itself
+    assert_eq!(catalog.path("urn:example:port:v2"), None);
+    assert_eq!(catalog.path("https://other.example.com/common/v1.json"), None);
+}
+
+#[test]
+fn refs_to_ids_of_local_schemas_become_relative_file_refs() {
+    let (schemas, _) = resolve(&[
+        ("common", json!({ "$id": "https://schemas.example.com/common.json", "$defs": { "port": { "type": "object" } } })),
+        ("nodes/agent", json!({
+            "$id": "https://schemas.example.com/nodes/agent.json",
+            "properties": {
+                "port": { "$ref": "https://schemas.example.com/common.json#/$defs/port" },
+                // relative to the `$id` of the file, not to its path
+                "same": { "$ref": "../common.json#/$defs/port" },
+                "dots": { "$ref": "https://schemas.example.com/nodes/../common.json" },
+                "self": { "$ref": "https://schemas.example.com/nodes/agent.json#/properties/port" },
+                "local": { "$ref": "#/$defs/config" }
+            }
+        })),
+        ("nodes/end", json!({ "properties": { "next": { "$ref": "./agent.schema.json" } } })),
+    ], &Catalog::default(), Path::new("schemas")).unwrap();
+
+    assert_eq!(schemas["nodes/agent"]["properties"], json!({
+        "port": { "$ref": "../common.schema.json#/$defs/port" },
+        "same": { "$ref": "../common.schema.json#/$defs/port" },
+        "dots": { "$ref": "../common.schema.json" },
+        "self": { "$ref": "#/properties/port" },
+        "local": { "$ref": "#/$defs/config" }
+    }));
+    // files without `$id` keep their path refs
+    assert_eq!(schemas["nodes/end"]["properties"]["next"], json!({ "$ref": "./agent.schema.json" }));
+}
+
+#[test]
+fn catalog_files_are_loaded_once_referenced_with_their_own_refs() {
+    let vendor = vendor_dir("catalog_vendor", &[
+        ("common-v1.schema.yaml", "$id: https://schemas.example.com/common/v1.json\n$defs:\n  port:\n    $ref: ./port.json\n"),
+        ("shared/port.json", r#"{ "type": "object", "properties": { "id": { "type": "string" } } }"#),
+        ("unused.json", "{}"),
+    ]);
+    let catalog = catalog(&[
+        ("https://schemas.example.com/common/v1.json", &vendor.join("common-v1.schema.yaml").display().to_string()),
+        ("https://schemas.example.com/common/", &vendor.join("shared/").display().to_string()),
+    ]);
+
+    let (schemas, source_map) = resolve(&[
+        ("agent", json!({ "properties": { "port": { "$ref": "https://schemas.example.com/common/v1.json#/$defs/port" } } })),
+    ], &catalog, &vendor).unwrap();
+
+    assert_eq!(schemas.keys().collect::<Vec<_>>(), ["agent", "common/port", "common/v1"]);
+    assert_eq!(schemas["agent"]["properties"]["port"], json!({ "$ref": "./common/v1.schema.json#/$defs/port" }));
+    assert_eq!(schemas["common/v1"]["$defs"]["port"], json!({ "$ref": "./port.schema.json" }));
+    assert_eq!(source_map.catalog_uri("common/port"), Some("https://schemas.example.com/common/port.json"));
+    assert_eq!(source_map.path("common/v1"), vendor.join("common-v1.schema.yaml").display().to_string());
+    assert_eq!(source_map.catalog_uri("agent"), None);
+}
+
+#[test]
+fn unknown_and_duplicate_ids_are_errors() {
+    let err = resolve(&[
+        ("agent", json!({ "properties": { "port": { "$ref": "https://schemas.example.com/port.json" } } })),
+    ], &Catalog::default(), Path::new("schemas")).unwrap_err();
+    assert_eq!(err, "agent.schema.json#/properties/port/$ref: `$ref` `https://schemas.example.com/port.json` resolves to \
+        `https://schemas.example.com/port.json`, which is neither the `$id` of a schema nor in the catalog, map it to a local file \
+        with `--catalog https://schemas.example.com/port.json=PATH` or in `schemas.catalog`");
+
+    let err = resolve(&[
+        ("agent", json!({ "$id": "https://schemas.example.com/node.json" })),
+        ("end", json!({ "$id": "https://schemas.example.com/node.json#" })),
+    ], &Catalog::default(), Path::new("schemas")).unwrap_err();
+    assert_eq!(err, "end.schema.json#/$id: `$id` `https://schemas.example.com/node.json` is also the `$id` of agent.schema.json");
+
+    let err = resolve(&[
+        ("agent", json!({ "$ref": "https://schemas.example.com/missing.json" })),
+    ], &catalog(&[("https://schemas.example.com/", "/nonexistent/missing/")]), Path::new("schemas")).unwrap_err();
+    assert!(err.starts_with("catalog file /nonexistent/missing/missing.json of `https://schemas.example.com/missing.json` can not be read"), "{err}");
+}
@@ -0,0 +1,69 @@
+use rust_generator::{diff_crate_files, stale_crate_files, write_crate_files, GeneratedFile};
+use std::path::{Path, PathBuf};
+
+/// Empty directory [name] for a crate under the target directory
+fn crate_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("check").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn files() -> Vec<GeneratedFile> {
+    vec![
+        GeneratedFile { path: PathBuf::from("Cargo.toml"), content: "[package]\nname = \"schemas\"\n".to_string() },
+        GeneratedFile { path: PathBuf::from("src/lib.rs"), content: "pub mod types;\npub mod node;\n".to_string() },
+        GeneratedFile { path: PathBuf::from("src/node.rs"), content: "pub fn node() {}\n".to_string() },
+        GeneratedFile { path: PathBuf::from("src/types/mod.rs"), content: "pub mod agent;\npub use agent::*;\n".to_string() },
+        GeneratedFile { path: PathBuf::from("src/types/agent.rs"), content: "pub struct Agent {}\n".to_string() },
+    ]
+}
+
+#[test]
+fn up_to_date_crate_has_no_diffs_or_stale_files() {
+    let dir = crate_dir("up_to_date");
+    write_crate_files(&dir, &files()).unwrap();
+    // hand-written files of other modules are not generated, they are not stale
+    std::fs::write(dir.join("src/expression.rs"), "pub struct Expression;\n").unwrap();
+
+    assert!(diff_crate_files(&dir, &files()).unwrap().is_empty());
+    assert!(stale_crate_files(&dir, &files()).unwrap().is_empty());
+}
+
+#[test]
+fn modified_and_missing_files_are_diffed() {
+    let dir = crate_dir("modified");
+    write_crate_files(&dir, &files()).unwrap();
+    std::fs::write(dir.join("src/types/agent.rs"), "pub struct Agent { pub id: String }\n").unwrap();
+    std::fs::remove_file(dir.join("src/node.rs")).unwrap();
+
+    let diffs = diff_crate_files(&dir, &files()).unwrap();
+    let node_path = dir.join("src/node.rs").display().to_string();
+    let agent_path = dir.join("src/types/agent.rs").display().to_string();
+    assert_eq!(diffs, [
+        format!("--- {node_path}\n+++ {node_path}\n@@ -0,0 +1 @@\n+pub fn node() {{}}\n"),
+        format!("--- {agent_path}\n+++ {agent_path}\n@@ -1 +1 @@\n-pub struct Agent {{ pub id: String }}\n+pub struct Agent {{}}\n"),
+    ]);
+}
+
+#[test]
+fn runtime_and_type_modules_no_longer_generated_are_stale() {
+    let dir = crate_dir("stale");
+    write_crate_files(&dir, &files()).unwrap();
+    // a runtime module left out by roots, the module of a deleted schema file and the single types.rs
+    for stale in ["src/policy.rs", "src/types/end.rs", "src/types.rs", "src/bin/pipeline.rs"] {
+        let path = dir.join(stale);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    std::fs::write(dir.join("src/types/notes.md"), "").unwrap();
+
+    assert_eq!(stale_crate_files(&dir, &files()).unwrap(), [
+        dir.join("src/bin/pipeline.rs"),
+        dir.join("src/policy.rs"),
+        dir.join("src/types/end.rs"),
+        dir.join("src/types.rs"),
+    ]);
+    // stale files are not diffed, only the generated ones
+    assert!(diff_crate_files(&dir, &files()).unwrap().is_empty());
+}
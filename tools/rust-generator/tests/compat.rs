@@ -0,0 +1,159 @@
+use rust_generator::compat::{compare_bundled_schemas, CompatReport, SemverBump};
+use serde_json::{json, Value};
+
+/// Bundled schema with the single definition `port` of [def]
+fn bundle(def: Value) -> Value {
+    json!({ "$defs": { "port": def } })
+}
+
+fn port() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "id": { "type": "string", "minLength": 1 },
+            "direction": { "enum": ["input", "output"] },
+            "weight": { "type": "integer" }
+        },
+        "required": ["id", "direction"],
+        "additionalProperties": false
+    })
+}
+
+/// Compares [port] with the result of [change] applied to it
+fn compare_port(change: impl FnOnce(&mut Value)) -> CompatReport {
+    let mut new = port();
+    change(&mut new);
+    compare_bundled_schemas(&bundle(port()), &bundle(new))
+}
+
+fn descriptions(report: &CompatReport) -> (Vec<String>, Vec<String>) {
+    (
+        report.breaking.iter().map(ToString::to_string).collect(),
+        report.additive.iter().map(ToString::to_string).collect(),
+    )
+}
+
+fn rust_api(report: &CompatReport) -> Vec<String> {
+    report.rust_api.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn identical_schemas_need_no_bump() {
+    let report = compare_port(|_| {});
+    assert_eq!(report, CompatReport::default());
+    assert_eq!(report.bump(), SemverBump::None);
+}
+
+#[test]
+fn description_changes_are_a_patch() {
+    let report = compare_port(|port| port["description"] = json!("Port of a node"));
+    assert!(report.breaking.is_empty() && report.additive.is_empty());
+    assert_eq!(report.bump(), SemverBump::Patch);
+}
+
+#[test]
+fn removed_definition_is_breaking_and_added_one_additive() {
+    let old = json!({ "$defs": { "port": port(), "edge": { "type": "object" } } });
+    let new = json!({ "$defs": { "port": port(), "node": { "type": "object" } } });
+    let report = compare_bundled_schemas(&old, &new);
+    assert_eq!(descriptions(&report), (vec!["edge: definition removed".to_string()], vec!["node: definition added".to_string()]));
+    assert_eq!(report.bump(), SemverBump::Major);
+}
+
+#[test]
+fn removed_property_is_breaking() {
+    let report = compare_port(|port| {
+        port["properties"].as_object_mut().unwrap().remove("weight");
+    });
+    assert_eq!(descriptions(&report).0, ["port/properties/weight: property `weight` removed"]);
+}
+
+#[test]
+fn added_optional_property_is_additive_and_changes_struct_literals() {
+    let report = compare_port(|port| port["properties"]["label"] = json!({ "type": "string" }));
+    assert_eq!(descriptions(&report), (vec![], vec!["port/properties/label: optional property `label` added".to_string()]));
+    assert_eq!(rust_api(&report), ["port/properties/label: generated struct gets field `label`, struct literals need it"]);
+    assert_eq!(report.bump(), SemverBump::Minor);
+}
+
+#[test]
+fn added_required_property_is_breaking() {
+    let report = compare_port(|port| {
+        port["properties"]["label"] = json!({ "type": "string" });
+        port["required"] = json!(["id", "direction", "label"]);
+    });
+    assert_eq!(descriptions(&report), (vec!["port/required: property `label` became required".to_string()], vec![]));
+    assert_eq!(rust_api(&report), ["port/properties/label: generated struct gets field `label`, struct literals need it"]);
+    assert_eq!(report.bump(), SemverBump::Major);
+}
+
+#[test]
+fn required_properties_break_and_optional_ones_extend() {
+    let report = compare_port(|port| port["required"] = json!(["id", "weight"]));
+    assert_eq!(descriptions(&report), (
+        vec!["port/required: property `weight` became required".to_string()],
+        vec!["port/required: property `direction` became optional".to_string()],
+    ));
+    assert_eq!(rust_api(&report), ["port/required: field `direction` becomes an `Option`"]);
+}
+
+#[test]
+fn narrowed_types_are_breaking_and_widened_ones_additive() {
+    let narrowed = compare_port(|port| port["properties"]["id"]["type"] = json!("integer"));
+    assert_eq!(descriptions(&narrowed).0, ["port/properties/id: type changed from string to integer, narrowed"]);
+    assert!(rust_api(&narrowed).is_empty());
+
+    let widened = compare_port(|port| port["properties"]["weight"]["type"] = json!("number"));
+    assert_eq!(descriptions(&widened), (vec![], vec!["port/properties/weight: type changed from integer to number, widened".to_string()]));
+    assert_eq!(rust_api(&widened), ["port/properties/weight: generated Rust type changes"]);
+
+    let nullable = compare_port(|port| port["properties"]["id"]["type"] = json!(["string", "null"]));
+    assert_eq!(descriptions(&nullable).1, ["port/properties/id: type changed from string to null | string, widened"]);
+}
+
+#[test]
+fn added_enum_values_are_additive_and_removed_ones_breaking() {
+    let added = compare_port(|port| port["properties"]["direction"]["enum"] = json!(["input", "output", "both"]));
+    assert_eq!(descriptions(&added), (vec![], vec!["port/properties/direction/enum: enum value \"both\" added".to_string()]));
+    assert_eq!(rust_api(&added), ["port/properties/direction/enum: generated enum gets a variant for \"both\", exhaustive matches need it"]);
+    assert_eq!(added.bump(), SemverBump::Minor);
+
+    let removed = compare_port(|port| port["properties"]["direction"]["enum"] = json!(["input"]));
+    assert_eq!(descriptions(&removed).0, ["port/properties/direction/enum: enum value \"output\" removed"]);
+}
+
+#[test]
+fn one_of_branches_are_compared_in_place() {
+    let old = bundle(json!({ "oneOf": [{ "$ref": "#/$defs/a" }, { "type": "string" }] }));
+    let added = compare_bundled_schemas(&old, &bundle(json!({ "oneOf": [{ "$ref": "#/$defs/a" }, { "type": "string" }, { "type": "integer" }] })));
+    assert_eq!(descriptions(&added), (vec![], vec!["port/oneOf/2: oneOf branch {\"type\":\"integer\"} added".to_string()]));
+    assert_eq!(rust_api(&added), ["port/oneOf/2: generated enum gets a variant, exhaustive matches need it"]);
+    assert_eq!(added.bump(), SemverBump::Minor);
+
+    let all_of = bundle(json!({ "allOf": [{ "$ref": "#/$defs/a" }] }));
+    let narrowed = bundle(json!({ "allOf": [{ "$ref": "#/$defs/a" }, { "required": ["id"] }] }));
+    assert_eq!(descriptions(&compare_bundled_schemas(&all_of, &narrowed)).0, [
+        "port/allOf/1: allOf branch {\"required\":[\"id\"]} added",
+    ]);
+
+    let changed = bundle(json!({ "oneOf": [{ "$ref": "#/$defs/b" }, { "type": "string" }] }));
+    assert_eq!(descriptions(&compare_bundled_schemas(&old, &changed)).0, [
+        "port/oneOf/0: $ref changed from \"#/$defs/a\" to \"#/$defs/b\"",
+    ]);
+}
+
+#[test]
+fn loosened_constraints_are_additive_and_tightened_ones_breaking() {
+    let loosened = compare_port(|port| {
+        port["additionalProperties"] = json!(true);
+        port["properties"]["id"]["minLength"] = json!(0);
+    });
+    assert_eq!(descriptions(&loosened), (vec![], vec![
+        "port/additionalProperties: additionalProperties changed from false to true".to_string(),
+        "port/properties/id/minLength: minLength changed from Some(1.0) to Some(0.0)".to_string(),
+    ]));
+    assert_eq!(loosened.bump(), SemverBump::Minor);
+
+    let tightened = compare_port(|port| port["properties"]["id"]["maxLength"] = json!(64));
+    assert_eq!(descriptions(&tightened).0, ["port/properties/id/maxLength: maxLength changed from None to Some(64.0)"]);
+}
@@ -0,0 +1,78 @@
+use rust_generator::config::GeneratorConfig;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn config_paths_are_relative_to_the_config_file() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("config_paths");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("rust-generator.toml");
+    std::fs::write(&path, r#"
+[schemas.catalog]
+"https://schemas.example.com/common/v1.json" = "vendor/common-v1.schema.json"
+"https://schemas.example.com/shared/" = "/opt/shared/"
+
+[output]
+modules = ["src/expression.rs", "../hand_written/port.rs"]
+"#).unwrap();
+
+    let config = GeneratorConfig::load(&path).unwrap();
+    assert_eq!(config.schemas.catalog["https://schemas.example.com/common/v1.json"], dir.join("vendor/common-v1.schema.json").display().to_string());
+    assert_eq!(config.schemas.catalog["https://schemas.example.com/shared/"], "/opt/shared/");
+    assert_eq!(config.output.modules, [
+        dir.join("src/expression.rs").display().to_string(),
+        dir.join("../hand_written/port.rs").display().to_string(),
+    ]);
+
+    // a config that is not read from a file keeps its paths
+    let config = GeneratorConfig::from_toml("[output]\nmodules = [\"src/expression.rs\"]").unwrap();
+    assert_eq!(config.output.modules, ["src/expression.rs"]);
+}
+
+#[test]
+fn unknown_config_keys_are_rejected() {
+    let cases = [
+        ("[types]\nstruct_bulder = true\n", "unknown field `struct_bulder`"),
+        ("[typess]\nstruct_builder = true\n", "unknown field `typess`"),
+        ("[types.definitions.agent]\nrenamed = \"AgentNode\"\n", "unknown field `renamed`"),
+    ];
+    for (content, expected) in cases {
+        let err = GeneratorConfig::from_toml(content).err().map(|err| err.to_string()).unwrap_or_default();
+        assert!(err.contains(expected), "{content:?}: {err}");
+    }
+}
+
+/// Whether a crate generated from [config] with [args] has struct builders
+fn generates_struct_builders(name: &str, config: &str, args: &[&str]) -> bool {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("struct_builder").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("schemas")).unwrap();
+    std::fs::write(dir.join("schemas/agent.schema.json"), r#"{
+        "$schema": "http://json-schema.org/draft-07/schema",
+        "description": "Agent node",
+        "type": "object",
+        "properties": { "id": { "type": "string" } }
+    }"#).unwrap();
+    std::fs::write(dir.join("rust-generator.toml"), config).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-generator"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["generate", "--package-name", "schemas", "--package-version", "0.0.1", "--schema-version", "1"])
+        .arg("--input").arg(dir.join("schemas"))
+        .arg("--output").arg(dir.join("out"))
+        .arg("--config").arg(dir.join("rust-generator.toml"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let types = std::fs::read_to_string(dir.join("out/rust/src/types/agent.rs")).unwrap();
+    types.contains("pub fn builder()")
+}
+
+#[test]
+fn struct_builder_flag_overrides_the_config() {
+    assert!(!generates_struct_builders("default", "", &[]));
+    assert!(generates_struct_builders("config", "[types]\nstruct_builder = true\n", &[]));
+    assert!(!generates_struct_builders("flag_false", "[types]\nstruct_builder = true\n", &["--struct-builder=false"]));
+    assert!(generates_struct_builders("flag", "", &["--struct-builder"]));
+}
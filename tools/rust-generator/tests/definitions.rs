@@ -0,0 +1,127 @@
+use rust_generator::config::{DefinitionConfig, GeneratorConfig, TypesConfig};
+use rust_generator::definitions::DefinitionRules;
+use rust_generator::passes::default_passes;
+use rust_generator::{bundle_schemas, generate_crate_files, generate_rs_code, rename_runtime_fields, Bundle, GeneratedTypes};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Config of the repository schemas with [definitions] rules
+fn config(definitions: &[(&str, DefinitionConfig)]) -> GeneratorConfig {
+    let mut config = GeneratorConfig::for_package("agentic-pipeline-schemas", "0.0.1", "1");
+    config.types.definitions = definitions.iter().map(|(key, rules)| (key.to_string(), rules.clone())).collect();
+    config
+}
+
+fn repository_bundle(config: &GeneratorConfig) -> Bundle {
+    let schemas_path = OsString::from(Path::new(env!("CARGO_MANIFEST_DIR")).join("../../schemas"));
+    bundle_schemas(&schemas_path, &config.schemas).unwrap()
+}
+
+fn generate(bundle: &Bundle, config: &GeneratorConfig) -> Result<GeneratedTypes, String> {
+    let passes = default_passes(&bundle.schema, &config.types).unwrap();
+    generate_rs_code(bundle, &config.types, &passes).map_err(|err| err.to_string())
+}
+
+fn fields(fields: &[(&str, &str)]) -> DefinitionConfig {
+    let fields = fields.iter().map(|(json_name, field)| (json_name.to_string(), field.to_string())).collect();
+    DefinitionConfig { fields, ..DefinitionConfig::default() }
+}
+
+#[test]
+fn renamed_fields_keep_their_json_names_and_runtime_struct_literals_follow() {
+    let config = config(&[("end", fields(&[("type", "node_type")]))]);
+    let generated = generate(&repository_bundle(&config), &config).unwrap();
+    let types = &generated.files[0].content;
+    assert!(types.contains("#[serde(rename = \"type\")]\n    pub node_type: serde_json::Value,"), "{types}");
+    assert_eq!(generated.field_renames["End"], BTreeMap::from([("type_".to_string(), "node_type".to_string())]));
+
+    // the crate tests build an `End` with the generated field name
+    let src_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src").into_os_string();
+    let mut files = generate_crate_files(&src_path, &config, true).unwrap();
+    rename_runtime_fields(&mut files, &generated.field_renames).unwrap();
+    let lib_rs = files.iter().find(|file| file.path == Path::new("src/lib.rs")).unwrap();
+    assert!(lib_rs.content.contains("let node = End {"));
+    assert!(lib_rs.content.contains("node_type: serde_json::Value::String(\"end\".to_string()),"));
+    assert!(!lib_rs.content.contains("type_: serde_json::Value::String(\"end\".to_string()),"));
+    // other structs keep the field
+    assert!(lib_rs.content.contains("type_: serde_json::Value::String(\"start\".to_string()),"));
+}
+
+#[test]
+fn fields_the_runtime_reads_can_not_be_renamed() {
+    let config = config(&[("agent", fields(&[("id", "node_id")]))]);
+    let mut bundle = repository_bundle(&config);
+    let err = generate(&bundle, &config).err();
+    assert_eq!(err.as_deref(), Some("types.definitions.agent: field `id` of `Agent` can not be renamed, the runtime modules or the crate tests read it"));
+
+    // without the runtime modules nothing reads it
+    bundle.sources.remove("pipeline");
+    let generated = generate(&bundle, &config).unwrap();
+    assert!(generated.files[0].content.contains("#[serde(rename = \"id\")]\n    pub node_id: String,"));
+}
+
+#[test]
+fn conflicting_definition_rules_are_rejected() {
+    let replaced = DefinitionConfig { replace: Some("crate::expression::Expression".to_string()), ..fields(&[("format", "kind")]) };
+    let cases = [
+        (config(&[("common.expressionSpec", replaced)]), "types.definitions.common.expressionSpec: fields of a replaced definition can not be renamed"),
+        (config(&[("end.expressionSpec", DefinitionConfig::default())]), "types.definitions.end.expressionSpec: definition `expressionSpec` comes from `common`, not `end`"),
+        (config(&[("expression", DefinitionConfig::default())]), "types.definitions: there is no definition `expression` in the bundled schema"),
+    ];
+    for (config, expected) in cases {
+        assert_eq!(generate(&repository_bundle(&config), &config).err().as_deref(), Some(expected));
+    }
+
+    let mut config = config(&[("end", fields(&[("type", "node_type")]))]);
+    config.types.struct_builder = true;
+    let err = generate(&repository_bundle(&config), &config).err();
+    assert_eq!(err.as_deref(), Some("types.definitions.end: fields can not be renamed together with struct builders"));
+}
+
+#[test]
+fn replaced_definitions_keep_an_alias_of_the_generated_name() {
+    let replaced = DefinitionConfig { replace: Some("crate::expression::Expression".to_string()), ..DefinitionConfig::default() };
+    let config = config(&[("common.expressionSpec", replaced)]);
+    let types = &generate(&repository_bundle(&config), &config).unwrap().files[0].content;
+    assert!(types.contains("pub type ExpressionSpec = crate::expression::Expression;"), "{types}");
+    assert!(!types.contains("pub struct ExpressionSpec"), "{types}");
+    assert!(types.contains("fn expression_spec_replacement_round_trips_schema_json()"), "{types}");
+}
+
+#[test]
+fn replacement_tests_sample_every_branch_and_enum_value() {
+    let schema = json!({ "$defs": {
+        "selector": {
+            "type": "object",
+            "required": ["mode", "expression"],
+            "properties": {
+                "mode": { "enum": ["expression", "state"] },
+                "expression": { "oneOf": [{ "type": "string" }, { "$ref": "#/$defs/spec" }] }
+            }
+        },
+        "spec": { "type": "object", "required": ["format"], "properties": { "format": { "const": "jmespath" } } },
+        "tree": { "type": "object", "required": ["child"], "properties": { "child": { "$ref": "#/$defs/tree" } } }
+    }});
+    let bundle = Bundle { schema: schema.clone(), sources: BTreeMap::new(), locations: BTreeMap::new() };
+    let replace = |path: &str| DefinitionConfig { replace: Some(path.to_string()), ..DefinitionConfig::default() };
+    let types_config = TypesConfig {
+        definitions: BTreeMap::from([
+            ("selector".to_string(), replace("crate::Selector")),
+            ("tree".to_string(), replace("crate::Tree")),
+        ]),
+        ..TypesConfig::default()
+    };
+
+    let tests = DefinitionRules::resolve(&types_config, &bundle, &[]).unwrap().replacement_tests(&schema);
+    let samples = [
+        r#"{"mode":"expression","expression":"sample"}"#,
+        r#"{"mode":"state","expression":"sample"}"#,
+        r#"{"mode":"expression","expression":{"format":"jmespath"}}"#,
+    ];
+    assert!(tests.contains(&format!(r##"serde_json::from_str(r#"[{}]"#)"##, samples.join(","))), "{tests}");
+    // a required recursive `$ref` has no finite instance
+    assert!(tests.contains(r##"fn tree_replacement_round_trips_schema_json() {
+        let samples: Vec<serde_json::Value> = serde_json::from_str(r#"[]"#).unwrap();"##), "{tests}");
+}
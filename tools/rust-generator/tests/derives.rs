@@ -0,0 +1,72 @@
+use rust_generator::config::TypesConfig;
+use rust_generator::derives::apply_derives;
+
+/// Applies [derives] to the types of [source], returns the file and the skipped derives
+fn derive(source: &str, derives: &[&str]) -> (syn::File, Vec<String>) {
+    let mut file = syn::parse_file(source).unwrap();
+    let types_config = TypesConfig { derives: derives.iter().map(ToString::to_string).collect(), ..TypesConfig::default() };
+    let skipped = apply_derives(&mut file, &types_config).unwrap();
+    (file, skipped.iter().map(ToString::to_string).collect())
+}
+
+/// Derive list of [type_name] in [file]
+fn derives_of(file: &syn::File, type_name: &str) -> String {
+    file.items.iter()
+        .find_map(|item| match item {
+            syn::Item::Struct(item) if item.ident == type_name => Some(&item.attrs),
+            syn::Item::Enum(item) if item.ident == type_name => Some(&item.attrs),
+            _ => None,
+        })
+        .and_then(|attrs| attrs.iter().find(|attr| attr.path().is_ident("derive")))
+        .map(|attr| attr.meta.require_list().unwrap().tokens.to_string())
+        .unwrap_or_default()
+}
+
+#[test]
+fn eq_and_hash_are_skipped_on_types_with_floats_or_json_values() {
+    let source = r#"
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Id(pub String);
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Metadata { pub extra: ::serde_json::Map<String, ::serde_json::Value> }
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Defaults { pub value: Option<serde_json::Value> }
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Threshold { pub score: f64 }
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Node { pub id: Id, pub metadata: Vec<Metadata> }
+    "#;
+    let (file, skipped) = derive(source, &["Eq", "Hash"]);
+    assert_eq!(derives_of(&file, "Id"), "Clone , Debug , PartialEq , Eq , Hash");
+    for type_name in ["Metadata", "Defaults", "Threshold", "Node"] {
+        assert_eq!(derives_of(&file, type_name), "Clone , Debug , PartialEq", "{type_name}");
+    }
+    assert_eq!(skipped, [
+        "`Eq` is not derived for `Defaults`: field `value` has type `Value`, which does not implement `Eq`",
+        "`Hash` is not derived for `Defaults`: field `value` has type `Value`, which does not implement `Hash`",
+        "`Eq` is not derived for `Metadata`: field `extra` has type `Map`, which does not implement `Eq`",
+        "`Hash` is not derived for `Metadata`: field `extra` has type `Map`, which does not implement `Hash`",
+        "`Eq` is not derived for `Node`: field `metadata` has type `Metadata`, which does not implement `Eq`",
+        "`Hash` is not derived for `Node`: field `metadata` has type `Metadata`, which does not implement `Hash`",
+        "`Eq` is not derived for `Threshold`: field `score` has type `f64`, which does not implement `Eq`",
+        "`Hash` is not derived for `Threshold`: field `score` has type `f64`, which does not implement `Hash`",
+    ]);
+}
+
+#[test]
+fn derives_need_their_supertraits_and_enums_have_no_default() {
+    let source = r#"
+        #[derive(Clone, Debug)]
+        pub struct Port { pub name: String, pub tags: std::collections::HashMap<String, String> }
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Direction { In, Out }
+    "#;
+    let (file, skipped) = derive(source, &["Eq", "Hash", "Default"]);
+    assert_eq!(derives_of(&file, "Port"), "Clone , Debug , Default");
+    assert_eq!(derives_of(&file, "Direction"), "Clone , Debug , PartialEq , Eq , Hash");
+    assert_eq!(skipped, [
+        "`Default` is not derived for `Direction`: enums have no default variant to derive `Default` from",
+        "`Eq` is not derived for `Port`: `Eq` requires `PartialEq`, which `Port` does not derive",
+        "`Hash` is not derived for `Port`: field `tags` has type `HashMap`, which does not implement `Hash`",
+    ]);
+}
@@ -0,0 +1,57 @@
+use rust_generator::config::GeneratorConfig;
+use rust_generator::passes::default_passes;
+use rust_generator::{bundle_loaded_schemas, bundle_schemas, generate_crate_files, generate_rs_modules, serialize_bundled_schema};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Generates the crate from the repository schemas in memory, returns its files by path
+fn generate() -> Vec<(PathBuf, String)> {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let schemas_path = OsString::from(manifest_dir.join("../../schemas"));
+    let config = GeneratorConfig::for_package("agentic-pipeline-schemas", "0.0.1", "1");
+
+    let mut files = generate_crate_files(&manifest_dir.join("src").into_os_string(), &config, true).unwrap();
+    let bundle = bundle_schemas(&schemas_path, &config.schemas).unwrap();
+    let passes = default_passes(&bundle.schema, &config.types).unwrap();
+    files.extend(generate_rs_modules(&bundle, &config.types, &passes).unwrap().files);
+
+    let mut generated = files.into_iter().map(|file| (file.path, file.content)).collect::<Vec<_>>();
+    generated.push((PathBuf::from("bundled.schema.json"), serialize_bundled_schema(&bundle.schema).unwrap()));
+    generated
+}
+
+#[test]
+fn generating_twice_gives_identical_files() {
+    let first = generate();
+    let second = generate();
+
+    assert_eq!(first.len(), second.len());
+    for ((first_path, first_content), (second_path, second_content)) in first.iter().zip(&second) {
+        assert_eq!(first_path, second_path);
+        assert!(first_content == second_content, "{} differs between runs", first_path.display());
+    }
+}
+
+#[test]
+fn one_of_branches_of_several_files_are_merged_in_file_order() {
+    let node = |branches: &[&str]| {
+        let branches = branches.iter().map(|file| json!({ "$ref": format!("./{file}.schema.json") })).collect::<Vec<_>>();
+        json!({ "$defs": { "node": { "description": "Node of a graph", "oneOf": branches } } })
+    };
+    let schemas = BTreeMap::from([
+        ("a_graph".to_string(), node(&["start", "agent", "end"])),
+        ("b_subgraph".to_string(), node(&["agent", "while"])),
+        ("c_plugin".to_string(), node(&["custom", "start"])),
+    ]);
+
+    let bundle = bundle_loaded_schemas(&schemas, ".schema.json");
+    let branches = bundle.schema["$defs"]["node"]["oneOf"].as_array().unwrap()
+        .iter()
+        .map(|branch| branch["$ref"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(branches, ["#/$defs/start", "#/$defs/agent", "#/$defs/end", "#/$defs/while", "#/$defs/custom"]);
+    assert_eq!(bundle.schema["$defs"]["node"]["description"], "Node of a graph");
+    assert_eq!(bundle.sources["node"], "a_graph");
+}
@@ -0,0 +1,149 @@
+use rust_generator::config::SchemasConfig;
+use rust_generator::discovery::{definition_key, discover_schema_files, relative_file_ref, resolve_file_ref, Glob};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn glob_matching() {
+    let cases = [
+        ("**", "agent.schema.json", true),
+        ("**", "nodes/deep/agent.schema.json", true),
+        ("*.schema.json", "agent.schema.json", true),
+        ("*.schema.json", "nodes/agent.schema.json", false),
+        ("nodes/*", "nodes/agent.schema.json", true),
+        ("nodes/*", "nodes/deep/agent.schema.json", false),
+        ("nodes/**", "nodes/deep/agent.schema.json", true),
+        ("nodes/**", "common.schema.json", false),
+        ("**/agent.schema.json", "agent.schema.json", true),
+        ("**/agent.schema.json", "a/b/c/agent.schema.json", true),
+        ("nodes/**/*.schema.yaml", "nodes/agent.schema.yaml", true),
+        ("nodes/**/*.schema.yaml", "nodes/x/y/agent.schema.yaml", true),
+        ("nodes/**/*.schema.yaml", "nodes/x/agent.schema.json", false),
+        ("**/drafts/**", "nodes/drafts/wip.schema.json", true),
+        ("**/drafts/**", "nodes/drafts.schema.json", false),
+        ("v?/*.json", "v1/common.json", true),
+        ("v?/*.json", "v10/common.json", false),
+        ("v?/*.json", "v/common.json", false),
+        ("a*b*c", "abc", true),
+        ("a*b*c", "axxbyyc", true),
+        ("a*b*c", "axxbyy", false),
+        ("*", "", true),
+        ("?", "", false),
+        // `?` is one character, also outside ASCII
+        ("v?.schema.json", "vé.schema.json", true),
+        ("v??.schema.json", "vé.schema.json", false),
+        ("*é?", "caféx", true),
+        // no character classes or alternatives
+        ("v[12].json", "v[12].json", true),
+        ("v[12].json", "v1.json", false),
+        ("{a,b}.json", "a.json", false),
+    ];
+    for (pattern, path, expected) in cases {
+        assert_eq!(Glob::new(pattern).unwrap().matches(path), expected, "`{pattern}` on `{path}`");
+    }
+}
+
+#[test]
+fn invalid_globs_are_rejected() {
+    for pattern in ["", "/abs/*.json", "nodes//*.json", "nodes/", "a**/b", "nodes/**x"] {
+        assert!(Glob::new(pattern).is_err(), "`{pattern}` is accepted");
+    }
+}
+
+#[test]
+fn file_refs_resolve_relative_to_the_referencing_file() {
+    let suffix = ".schema.json";
+    let cases = [
+        ("agent", "", Some("agent")),
+        ("agent", "./common.schema.json", Some("common")),
+        ("agent", "common.schema.json", Some("common")),
+        ("nodes/agent", "../common.schema.json", Some("common")),
+        ("nodes/agent", "./end.schema.json", Some("nodes/end")),
+        ("nodes/agent", "../shared/./port.schema.json", Some("shared/port")),
+        ("nodes/deep/agent", "../../common.schema.json", Some("common")),
+        ("nodes/deep/agent", "../x/../end.schema.json", Some("nodes/end")),
+        // other formats of the same file key
+        ("nodes/agent", "../common.schema.yaml", Some("common")),
+        ("nodes/agent", "../common.schema.json5", Some("common")),
+        // outside the schemas directory or without the suffix
+        ("agent", "../common.schema.json", None),
+        ("nodes/agent", "../../common.schema.json", None),
+        ("agent", "common.json", None),
+    ];
+    for (file_key, path, expected) in cases {
+        assert_eq!(resolve_file_ref(file_key, path, suffix).as_deref(), expected, "`{path}` in `{file_key}`");
+    }
+}
+
+#[test]
+fn relative_file_refs_round_trip() {
+    let suffix = ".schema.json";
+    let cases = [
+        ("agent", "common", "./common.schema.json"),
+        ("nodes/agent", "nodes/end", "./end.schema.json"),
+        ("nodes/agent", "common", "../common.schema.json"),
+        ("nodes/deep/agent", "common", "../../common.schema.json"),
+        ("nodes/deep/agent", "nodes/shared/port", "../shared/port.schema.json"),
+        ("agent", "nodes/deep/end", "./nodes/deep/end.schema.json"),
+    ];
+    for (file_key, target, expected) in cases {
+        let path = relative_file_ref(file_key, target, suffix);
+        assert_eq!(path, expected, "`{target}` from `{file_key}`");
+        assert_eq!(resolve_file_ref(file_key, &path, suffix).as_deref(), Some(target));
+    }
+}
+
+#[test]
+fn definition_keys_flatten_file_keys() {
+    assert_eq!(definition_key("common"), "common");
+    assert_eq!(definition_key("nodes/deep/agent"), "nodes_deep_agent");
+}
+
+/// Directory under the test temp dir with empty files at [paths]
+fn schemas_dir(name: &str, paths: &[&str]) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    for path in paths {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "{}").unwrap();
+    }
+    dir
+}
+
+#[test]
+fn discovery_scans_recursively_with_include_and_exclude_globs() {
+    let dir = schemas_dir("discovery_globs", &[
+        "common.schema.json",
+        "README.md",
+        "nodes/agent.schema.yaml",
+        "nodes/end.schema.json5",
+        "nodes/drafts/wip.schema.json",
+        "vendor/other.json",
+    ]);
+    let files = discover_schema_files(&dir, &SchemasConfig::default()).unwrap();
+    assert_eq!(files, BTreeMap::from([
+        ("common".to_string(), "common.schema.json".to_string()),
+        ("nodes/agent".to_string(), "nodes/agent.schema.yaml".to_string()),
+        ("nodes/drafts/wip".to_string(), "nodes/drafts/wip.schema.json".to_string()),
+        ("nodes/end".to_string(), "nodes/end.schema.json5".to_string()),
+    ]));
+
+    let schemas_config = SchemasConfig {
+        include: vec!["nodes/**".to_string()],
+        exclude: vec!["**/drafts/**".to_string()],
+        ..SchemasConfig::default()
+    };
+    let files = discover_schema_files(&dir, &schemas_config).unwrap();
+    assert_eq!(files.keys().collect::<Vec<_>>(), ["nodes/agent", "nodes/end"]);
+}
+
+#[test]
+fn discovery_rejects_files_bundled_as_the_same_definition() {
+    let dir = schemas_dir("discovery_clash", &["nodes_agent.schema.json", "nodes/agent.schema.json"]);
+    let err = discover_schema_files(&dir, &SchemasConfig::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "nodes/agent.schema.json and nodes_agent.schema.json are both bundled as definition `nodes_agent`, rename one of them"
+    );
+}
@@ -0,0 +1,155 @@
+use rust_generator::drafts::{lower_schemas, Draft, DRAFT_07_SCHEMA};
+use rust_generator::sources::SourceMap;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+const DRAFT_2019_09: &str = "https://json-schema.org/draft/2019-09/schema";
+const DRAFT_2020_12: &str = "https://json-schema.org/draft/2020-12/schema";
+
+fn lower_files(schemas: &[(&str, Value)]) -> Result<BTreeMap<String, Value>, String> {
+    let schemas = schemas.iter().map(|(file_key, schema)| (file_key.to_string(), schema.clone())).collect();
+    lower_schemas(&schemas, &SourceMap::new(".schema.json")).map_err(|err| err.to_string())
+}
+
+/// Lowers [schema] as the file `common`
+fn lower(schema: Value) -> Result<Value, String> {
+    lower_files(&[("common", schema)]).map(|mut lowered| lowered.remove("common").unwrap())
+}
+
+#[test]
+fn drafts_are_selected_by_schema_uri() {
+    assert_eq!(Draft::of(&json!({})), Ok(Draft::Draft07));
+    assert_eq!(Draft::of(&json!({ "$schema": "http://json-schema.org/draft-07/schema#" })), Ok(Draft::Draft07));
+    assert_eq!(Draft::of(&json!({ "$schema": DRAFT_2019_09 })), Ok(Draft::Draft201909));
+    assert_eq!(Draft::of(&json!({ "$schema": "http://json-schema.org/draft/2020-12/schema#" })), Ok(Draft::Draft202012));
+    assert!(Draft::of(&json!({ "$schema": "http://json-schema.org/draft-04/schema#" })).is_err());
+}
+
+#[test]
+fn draft_07_files_are_unchanged() {
+    let schema = json!({ "$schema": DRAFT_07_SCHEMA, "dependentRequired": { "a": ["b"] }, "$defs": { "x": { "$anchor": "x" } } });
+    assert_eq!(lower(schema.clone()), Ok(schema));
+}
+
+#[test]
+fn definitions_are_lowered_and_kept_in_defs() {
+    let lowered = lower(json!({
+        "$schema": DRAFT_2020_12,
+        "$ref": "#/$defs/pair",
+        "$defs": {
+            "pair": { "type": "array", "prefixItems": [{ "type": "string" }, { "type": "integer" }], "minItems": 2, "maxItems": 2 },
+            "nested": { "$defs": { "inner": { "dependentRequired": { "a": ["b"] } } } }
+        }
+    }));
+    assert_eq!(lowered, Ok(json!({
+        "$schema": DRAFT_07_SCHEMA,
+        "$ref": "#/$defs/pair",
+        "$defs": {
+            "pair": { "type": "array", "minItems": 2, "maxItems": 2, "items": [{ "type": "string" }, { "type": "integer" }] },
+            "nested": { "$defs": { "inner": { "dependencies": { "a": ["b"] } } } }
+        }
+    })));
+}
+
+#[test]
+fn prefix_items_become_tuple_items() {
+    let lowered = lower(json!({
+        "$schema": DRAFT_2020_12,
+        "prefixItems": [{ "type": "string" }],
+        "items": false,
+        "minItems": 1,
+        "maxItems": 1
+    }));
+    assert_eq!(lowered, Ok(json!({
+        "$schema": DRAFT_07_SCHEMA,
+        "minItems": 1,
+        "maxItems": 1,
+        "additionalItems": false,
+        "items": [{ "type": "string" }]
+    })));
+
+    // typify generates tuples only for fixed lengths
+    let err = lower(json!({ "$schema": DRAFT_2020_12, "properties": { "pair": { "prefixItems": [{}], "minItems": 1 } } }));
+    assert_eq!(err, Err("common.schema.json#/properties/pair/prefixItems: \
+        `prefixItems` is generated as a tuple, it needs equal non-zero `minItems` and `maxItems`".to_string()));
+
+    // 2019-09 has array `items` already
+    let schema = json!({ "$schema": DRAFT_2019_09, "items": [{ "type": "string" }], "additionalItems": false });
+    let mut expected = schema.clone();
+    expected["$schema"] = json!(DRAFT_07_SCHEMA);
+    assert_eq!(lower(schema), Ok(expected));
+}
+
+#[test]
+fn dependent_keywords_become_dependencies() {
+    let lowered = lower(json!({
+        "$schema": DRAFT_2019_09,
+        "dependentRequired": { "billing": ["address"], "card": ["number"] },
+        "dependentSchemas": { "card": { "properties": { "cvc": { "type": "string" } } } }
+    }));
+    assert_eq!(lowered, Ok(json!({
+        "$schema": DRAFT_07_SCHEMA,
+        "dependencies": {
+            "card": { "allOf": [{ "properties": { "cvc": { "type": "string" } } }, { "required": ["number"] }] },
+            "billing": ["address"]
+        }
+    })));
+}
+
+#[test]
+fn unevaluated_keywords_become_additional_ones_where_nothing_else_evaluates() {
+    let lowered = lower(json!({
+        "$schema": DRAFT_2020_12,
+        "properties": { "id": { "type": "string" } },
+        "unevaluatedProperties": false,
+        "$defs": {
+            "list": { "type": "array", "unevaluatedItems": { "type": "integer" } },
+            "tuple": { "items": [{}], "unevaluatedItems": false },
+            "closed": { "additionalProperties": false, "unevaluatedProperties": false }
+        }
+    }));
+    assert_eq!(lowered, Ok(json!({
+        "$schema": DRAFT_07_SCHEMA,
+        "properties": { "id": { "type": "string" } },
+        "additionalProperties": false,
+        "$defs": {
+            "list": { "type": "array", "items": { "type": "integer" } },
+            "tuple": { "items": [{}], "additionalItems": false },
+            "closed": { "additionalProperties": false }
+        }
+    })));
+
+    let err = lower(json!({ "$schema": DRAFT_2020_12, "allOf": [{ "properties": { "id": {} } }], "unevaluatedProperties": false }));
+    assert_eq!(err, Err("common.schema.json#/unevaluatedProperties: `unevaluatedProperties` can not be lowered to draft-07 \
+        `additionalProperties`, it also sees what `allOf` evaluates".to_string()));
+}
+
+#[test]
+fn anchors_become_pointer_references() {
+    let lowered = lower_files(&[
+        ("graph", json!({
+            "$schema": DRAFT_2020_12,
+            "$defs": {
+                "node": { "$anchor": "node", "type": "object" },
+                "tree": { "$dynamicAnchor": "tree", "properties": { "children": { "items": { "$dynamicRef": "#tree" } } } }
+            }
+        })),
+        ("nodes/agent", json!({ "$schema": DRAFT_2020_12, "properties": { "next": { "$ref": "../graph.schema.json#node" } } })),
+    ]).unwrap();
+    assert_eq!(lowered["graph"]["$defs"]["node"], json!({ "type": "object" }));
+    assert_eq!(lowered["graph"]["$defs"]["tree"]["properties"]["children"]["items"], json!({ "$ref": "#/$defs/tree" }));
+    assert_eq!(lowered["nodes/agent"]["properties"]["next"], json!({ "$ref": "../graph.schema.json#/$defs/node" }));
+
+    let err = lower(json!({ "$schema": DRAFT_2020_12, "properties": { "a": { "$ref": "#missing" } } }));
+    assert_eq!(err, Err("common.schema.json#/properties/a/$ref: there is no anchor `missing` in common.schema.json".to_string()));
+
+    let err = lower(json!({ "$schema": DRAFT_2020_12, "properties": { "a": { "$anchor": "a" }, "b": { "$ref": "#a" } } }));
+    assert_eq!(err, Err("common.schema.json#/properties/b/$ref: anchor `a` is at common.schema.json#/properties/a, \
+        only anchors on a file root or on a definition can be bundled".to_string()));
+}
+
+#[test]
+fn keywords_without_equivalent_are_errors() {
+    let err = lower(json!({ "$schema": DRAFT_2019_09, "contains": { "type": "string" }, "minContains": 2 }));
+    assert_eq!(err, Err("common.schema.json#/minContains: `minContains` has no draft-07 equivalent".to_string()));
+}
@@ -0,0 +1,233 @@
+use rust_generator::config::{LintLevel, LintsConfig};
+use rust_generator::docs::lint_missing_descriptions;
+use rust_generator::lint::lint_schemas;
+use rust_generator::sources::{parse_source, SourceFormat, SourceMap};
+use rust_generator::{bundle_loaded_schemas, bundle_prebundled_schema};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Node schema of [node_type] with its config definition, as the node files of the repository
+fn node(node_type: &str) -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema",
+        "type": "object",
+        "properties": {
+            "id": { "type": "string" },
+            "type": { "const": node_type },
+            "ports": { "type": "array" },
+            "config": { "$ref": "#/$defs/config" }
+        },
+        "required": ["id", "type", "ports", "config"],
+        "additionalProperties": false,
+        "$defs": {
+            "config": {
+                "type": "object",
+                "properties": { "kind": { "const": node_type } },
+                "required": ["kind"],
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+/// Schemas without lint issues: a pipeline, a graph with the `node` union and two nodes
+fn schemas() -> BTreeMap<String, Value> {
+    BTreeMap::from([
+        ("pipeline".to_string(), json!({
+            "$schema": "http://json-schema.org/draft-07/schema",
+            "type": "object",
+            "properties": { "nodes": { "type": "array", "items": { "$ref": "./graph.schema.json#/$defs/node" } } }
+        })),
+        ("graph".to_string(), json!({
+            "$schema": "http://json-schema.org/draft-07/schema",
+            "$defs": {
+                "node": { "oneOf": [{ "$ref": "./nodes/agent.schema.json" }, { "$ref": "./nodes/end.schema.json" }] }
+            }
+        })),
+        ("nodes/agent".to_string(), node("agent")),
+        ("nodes/end".to_string(), node("end")),
+    ])
+}
+
+fn lint(schemas: &BTreeMap<String, Value>) -> Vec<String> {
+    lint_schemas(schemas, &SourceMap::new(".schema.json"), &LintsConfig::default())
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Issues after [change] is applied to the schema of [file_key]
+fn lint_changed(file_key: &str, change: impl FnOnce(&mut Value)) -> Vec<String> {
+    let mut schemas = schemas();
+    change(schemas.get_mut(file_key).unwrap());
+    lint(&schemas)
+}
+
+#[test]
+fn consistent_schemas_have_no_issues() {
+    assert_eq!(lint(&schemas()), Vec::<String>::new());
+}
+
+#[test]
+fn required_without_property() {
+    let issues = lint_changed("pipeline", |schema| schema["required"] = json!(["nodes", "edges"]));
+    assert_eq!(issues, ["pipeline.schema.json#/required/1: `edges` is required, but it is not in `properties` [required_without_property]"]);
+
+    // nested subschemas are checked, `required` in instances such as `default` is not
+    let issues = lint_changed("nodes/agent", |schema| {
+        schema["$defs"]["config"]["default"] = json!({ "required": ["anything"] });
+        schema["$defs"]["config"]["properties"]["kind"]["required"] = json!(["value"]);
+    });
+    assert_eq!(issues, [
+        "nodes/agent.schema.json#/$defs/config/properties/kind/required/0: `value` is required, but it is not in `properties` [required_without_property]",
+    ]);
+}
+
+#[test]
+fn unreferenced_definition() {
+    let issues = lint_changed("graph", |schema| schema["$defs"]["edge"] = json!({ "type": "object" }));
+    assert_eq!(issues, ["graph.schema.json#/$defs/edge: definition `edge` is not referenced by any schema [unreferenced_definition]"]);
+
+    // references by file key and pointer from other directories count
+    let issues = lint_changed("nodes/end", |schema| {
+        schema["$defs"]["edge"] = json!({ "type": "object" });
+        schema["properties"]["id"] = json!({ "$ref": "../nodes/end.schema.json#/$defs/edge" });
+    });
+    assert_eq!(issues, Vec::<String>::new());
+
+    // roots of pre-bundled files keep `$schema` and are entry points
+    let issues = lint_changed("graph", |schema| {
+        schema["$defs"]["pipeline"] = json!({ "$schema": "http://json-schema.org/draft-07/schema", "type": "object" });
+    });
+    assert_eq!(issues, Vec::<String>::new());
+}
+
+#[test]
+fn node_shape() {
+    let issues = lint_changed("nodes/end", |schema| {
+        schema["properties"].as_object_mut().unwrap().remove("ports");
+        schema["required"] = json!(["type", "config"]);
+    });
+    assert_eq!(issues, [
+        "nodes/end.schema.json#: node schema does not require `id` [node_shape]",
+        "nodes/end.schema.json#: node schema has no `ports` property [node_shape]",
+    ]);
+}
+
+#[test]
+fn node_discriminator() {
+    let issues = lint_changed("nodes/end", |schema| schema["properties"]["type"] = json!({ "type": "string" }));
+    assert_eq!(issues, ["nodes/end.schema.json#/properties/type: node `type` has no string `const` discriminator [node_discriminator]"]);
+
+    let issues = lint_changed("nodes/end", |schema| schema["properties"]["type"]["const"] = json!("agent"));
+    assert_eq!(issues, [
+        "nodes/end.schema.json#/$defs/config/properties/kind/const: config `kind` is \"end\", but the node `type` is \"agent\" [node_discriminator]",
+        "nodes/end.schema.json#/properties/type: node type `agent` is also used by nodes/agent.schema.json# [node_discriminator]",
+    ]);
+}
+
+#[test]
+fn missing_additional_properties() {
+    let issues = lint_changed("nodes/end", |schema| {
+        schema.as_object_mut().unwrap().remove("additionalProperties");
+        schema["$defs"]["config"]["additionalProperties"] = json!(true);
+    });
+    assert_eq!(issues, [
+        "nodes/end.schema.json#: `additionalProperties: false` is missing, other node schemas set it [missing_additional_properties]",
+        "nodes/end.schema.json#/$defs/config: `additionalProperties: false` is missing, other node configs set it [missing_additional_properties]",
+    ]);
+
+    // a group where no schema is closed is consistent
+    let mut schemas = schemas();
+    for file_key in ["nodes/agent", "nodes/end"] {
+        schemas.get_mut(file_key).unwrap().as_object_mut().unwrap().remove("additionalProperties");
+    }
+    assert_eq!(lint(&schemas), Vec::<String>::new());
+}
+
+#[test]
+fn levels_of_the_config_apply() {
+    let mut schemas = schemas();
+    schemas.get_mut("graph").unwrap()["$defs"]["edge"] = json!({ "type": "object" });
+    let lints_config = LintsConfig { unreferenced_definition: LintLevel::Allow, ..LintsConfig::default() };
+    assert!(lint_schemas(&schemas, &SourceMap::new(".schema.json"), &lints_config).is_empty());
+
+    let lints_config = LintsConfig { unreferenced_definition: LintLevel::Deny, ..LintsConfig::default() };
+    let issues = lint_schemas(&schemas, &SourceMap::new(".schema.json"), &lints_config);
+    assert_eq!(issues.iter().map(|issue| issue.level).collect::<Vec<_>>(), [LintLevel::Deny]);
+}
+
+#[test]
+fn issues_have_positions_and_skip_catalog_files() {
+    let text = "type: object\nproperties:\n  id: {type: string}\nrequired: [id, name]\n";
+    let (schema, positions) = parse_source(text, SourceFormat::Yaml).unwrap();
+    let schemas = BTreeMap::from([("common".to_string(), schema.clone()), ("vendor".to_string(), schema)]);
+    let mut source_map = SourceMap::new(".schema.json");
+    source_map.insert("common", "common.schema.yaml", positions.clone());
+    source_map.insert_catalog_file("vendor", "/vendor/common.json", "https://example.com/common.json", positions);
+
+    let issues = lint_schemas(&schemas, &source_map, &LintsConfig::default()).iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(issues, ["common.schema.yaml:4:16: #/required/1: `name` is required, but it is not in `properties` [required_without_property]"]);
+}
+
+#[test]
+fn definitions_without_description_are_reported_at_their_level() {
+    let common = r#"{
+  "$schema": "http://json-schema.org/draft-07/schema",
+  "$defs": {
+    "port": { "type": "string" },
+    "blank": { "description": "  " }
+  }
+}"#;
+    let agent = r#"{
+  "description": "Agent node",
+  "type": "object",
+  "$defs": {
+    "params": {
+      "$defs": { "model": { "type": "string" } }
+    }
+  }
+}"#;
+    let mut schemas = BTreeMap::new();
+    let mut source_map = SourceMap::new(".schema.json");
+    for (file_key, text) in [("common", common), ("nodes/agent", agent)] {
+        let (schema, positions) = parse_source(text, SourceFormat::Json).unwrap();
+        schemas.insert(file_key.to_string(), schema);
+        source_map.insert(file_key, &format!("{file_key}.schema.json"), positions);
+    }
+    let bundle = bundle_loaded_schemas(&schemas, ".schema.json");
+
+    // `common` and `params` only hold `$defs`, a definition in nested `$defs` is located where it is written
+    let issues = lint_missing_descriptions(&bundle, &source_map, &LintsConfig::default());
+    assert_eq!(issues.iter().map(ToString::to_string).collect::<Vec<_>>(), [
+        "common.schema.json:4:5: #/$defs/port: definition `port` has no description, its type is generated without docs [missing_description]",
+        "common.schema.json:5:5: #/$defs/blank: definition `blank` has no description, its type is generated without docs [missing_description]",
+        "nodes/agent.schema.json:6:18: #/$defs/params/$defs/model: definition `model` has no description, its type is generated without docs [missing_description]",
+    ]);
+    assert!(issues.iter().all(|issue| issue.level == LintLevel::Warn));
+
+    let lints_config = LintsConfig { missing_description: LintLevel::Allow, ..LintsConfig::default() };
+    assert!(lint_missing_descriptions(&bundle, &source_map, &lints_config).is_empty());
+    let lints_config = LintsConfig { missing_description: LintLevel::Deny, ..LintsConfig::default() };
+    assert!(lint_missing_descriptions(&bundle, &source_map, &lints_config).iter().all(|issue| issue.level == LintLevel::Deny));
+}
+
+#[test]
+fn definitions_of_pre_bundled_schemas_are_reported_in_the_bundle_file() {
+    let text = r#"{
+  "$schema": "http://json-schema.org/draft-07/schema",
+  "$defs": {
+    "common": { "$schema": "http://json-schema.org/draft-07/schema" },
+    "port": { "type": "string" }
+  }
+}"#;
+    let (schema, positions) = parse_source(text, SourceFormat::Json).unwrap();
+    let mut source_map = SourceMap::new(".schema.json");
+    source_map.insert("bundled", "bundled.schema.json", positions);
+    let bundle = bundle_prebundled_schema("bundled", &schema);
+
+    let issues = lint_missing_descriptions(&bundle, &source_map, &LintsConfig::default());
+    assert_eq!(issues.iter().map(ToString::to_string).collect::<Vec<_>>(), [
+        "bundled.schema.json:5:5: #/$defs/port: definition `port` has no description, its type is generated without docs [missing_description]",
+    ]);
+}
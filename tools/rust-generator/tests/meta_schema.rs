@@ -0,0 +1,157 @@
+use rust_generator::config::{LintLevel, LintsConfig, SchemasConfig};
+use rust_generator::meta_schema::{validate_bundled_meta_schemas, validate_meta_schemas};
+use rust_generator::sources::{parse_source, SourceFormat, SourceMap};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+const DRAFT_07: &str = "http://json-schema.org/draft-07/schema";
+const DRAFT_2020_12: &str = "https://json-schema.org/draft/2020-12/schema";
+
+fn validate_with(schema: Value, schemas_config: &SchemasConfig) -> Vec<String> {
+    let schemas = BTreeMap::from([("common".to_string(), schema)]);
+    validate_meta_schemas(&schemas, &SourceMap::new(".schema.json"), schemas_config, &LintsConfig::default())
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn validate(schema: Value) -> Vec<String> {
+    validate_with(schema, &SchemasConfig::default())
+}
+
+#[test]
+fn valid_schemas_of_every_draft_have_no_issues() {
+    let draft_07 = json!({
+        "$schema": DRAFT_07,
+        "type": "object",
+        "properties": {
+            "id": { "type": "string", "pattern": "^[a-z_]+$", "minLength": 1 },
+            "tags": { "type": "array", "items": { "type": "string" }, "uniqueItems": true },
+            "port": { "$ref": "#/$defs/port" }
+        },
+        "required": ["id"],
+        "additionalProperties": false,
+        // draft-07 files keep their definitions in `$defs`
+        "$defs": { "port": { "enum": ["in", "out"] } },
+        "definitions": { "legacy": { "type": ["string", "null"] } }
+    });
+    assert_eq!(validate(draft_07), Vec::<String>::new());
+
+    let draft_2020_12 = json!({
+        "$schema": DRAFT_2020_12,
+        "type": "array",
+        "prefixItems": [{ "type": "string" }, { "type": "integer" }],
+        "items": false,
+        "$defs": { "pair": { "dependentRequired": { "a": ["b"] }, "unevaluatedProperties": false } }
+    });
+    assert_eq!(validate(draft_2020_12), Vec::<String>::new());
+}
+
+#[test]
+fn invalid_keyword_values_are_reported() {
+    let issues = validate(json!({
+        "$schema": DRAFT_07,
+        "type": "strng",
+        "required": ["id", "id"],
+        "minLength": -1,
+        "pattern": "[a-",
+        "properties": { "id": { "type": 1 } }
+    }));
+    assert_eq!(issues.len(), 5, "{issues:#?}");
+    assert!(issues.iter().all(|issue| issue.starts_with("common.schema.json#/") && issue.ends_with("[invalid_schema]")), "{issues:#?}");
+    for pointer in ["#/minLength:", "#/pattern:", "#/properties/id/type:", "#/required/1:", "#/type:"] {
+        assert!(issues.iter().any(|issue| issue.contains(pointer)), "no issue at {pointer}: {issues:#?}");
+    }
+    assert!(issues.contains(&"common.schema.json#/required/1: \"id\" is a duplicate [invalid_schema]".to_string()), "{issues:#?}");
+    assert!(issues.contains(&"common.schema.json#/minLength: -1 is less than 0 [invalid_schema]".to_string()), "{issues:#?}");
+    assert!(issues.contains(&"common.schema.json#/pattern: invalid regular expression: Unbalanced bracket [invalid_schema]".to_string()), "{issues:#?}");
+}
+
+#[test]
+fn unknown_keywords_are_reported_unless_vendor_keywords() {
+    let schema = json!({
+        "$schema": DRAFT_07,
+        "type": "object",
+        "x-rust-type": "crate::Id",
+        "properties": { "id": { "type": "string", "x-ui-widget": "text", "deprecatedd": true } }
+    });
+    let schemas_config = SchemasConfig { vendor_keywords: Vec::new(), ..SchemasConfig::default() };
+    assert_eq!(validate_with(schema.clone(), &schemas_config), [
+        "common.schema.json#/properties/id/deprecatedd: `deprecatedd` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]",
+        "common.schema.json#/properties/id/x-ui-widget: `x-ui-widget` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]",
+        "common.schema.json#/x-rust-type: `x-rust-type` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]",
+    ]);
+
+    // `x-rust-type` is a vendor keyword by default
+    assert_eq!(validate(schema.clone()), [
+        "common.schema.json#/properties/id/deprecatedd: `deprecatedd` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]",
+        "common.schema.json#/properties/id/x-ui-widget: `x-ui-widget` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]",
+    ]);
+    let schemas_config = SchemasConfig { vendor_keywords: vec!["x-rust-type".to_string(), "x-ui-*".to_string()], ..SchemasConfig::default() };
+    assert_eq!(validate_with(schema, &schemas_config), [
+        "common.schema.json#/properties/id/deprecatedd: `deprecatedd` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]",
+    ]);
+
+    // keywords of later drafts are unknown to draft-07
+    assert_eq!(validate(json!({ "$schema": DRAFT_07, "prefixItems": [] })), [
+        "common.schema.json#/prefixItems: `prefixItems` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]",
+    ]);
+}
+
+#[test]
+fn files_without_schema_uri_are_draft_07_and_other_drafts_invalid() {
+    assert_eq!(validate(json!({ "type": "object", "$defs": { "id": { "type": "string" } } })), Vec::<String>::new());
+
+    let unsupported = validate(json!({ "$schema": "http://json-schema.org/draft-04/schema#", "type": "object" }));
+    assert_eq!(unsupported, [
+        "common.schema.json#/$schema: meta-schema `http://json-schema.org/draft-04/schema#` is not supported, supported are: \
+        json-schema.org/draft-07/schema, json-schema.org/draft/2019-09/schema, json-schema.org/draft/2020-12/schema [invalid_schema]",
+    ]);
+    assert_eq!(validate(json!({ "$schema": 7 })), ["common.schema.json#/$schema: `$schema` is not a string [invalid_schema]"]);
+}
+
+#[test]
+fn issues_have_positions_and_levels_of_the_config() {
+    let text = "$schema: http://json-schema.org/draft-07/schema\ntype: object\nproperties:\n  id:\n    type: text\n";
+    let (schema, positions) = parse_source(text, SourceFormat::Yaml).unwrap();
+    let schemas = BTreeMap::from([("common".to_string(), schema)]);
+    let mut source_map = SourceMap::new(".schema.json");
+    source_map.insert("common", "common.schema.yaml", positions);
+
+    let issues = validate_meta_schemas(&schemas, &source_map, &SchemasConfig::default(), &LintsConfig::default());
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].to_string().starts_with("common.schema.yaml:5:5: #/properties/id/type: "), "{}", issues[0]);
+    assert_eq!(issues[0].level, LintLevel::Deny);
+
+    let lints_config = LintsConfig { invalid_schema: LintLevel::Allow, ..LintsConfig::default() };
+    assert!(validate_meta_schemas(&schemas, &source_map, &SchemasConfig::default(), &lints_config).is_empty());
+}
+
+#[test]
+fn definitions_of_pre_bundled_schemas_are_validated() {
+    let bundle = json!({
+        "$schema": DRAFT_07,
+        "$defs": {
+            "agent": { "$schema": DRAFT_07, "type": "object", "properties": { "node": { "$ref": "#/$defs/node" } } },
+            // merged from the `oneOf`s of same-named definitions of two files
+            "node": { "oneOf": [{ "$ref": "#/$defs/agent" }, { "type": "string" }] },
+            // older bundles stored merged `oneOf`s under the definition key, which is no keyword
+            "edge": { "edge": [{ "$ref": "#/$defs/agent" }] },
+            "port": { "type": "strng", "x-rust-type": "crate::Port", "portt": true },
+            "pair": { "$schema": DRAFT_2020_12, "prefixItems": [{}], "$defs": {} },
+            "legacy": { "$schema": "http://json-schema.org/draft-04/schema#" }
+        }
+    });
+    let issues = validate_bundled_meta_schemas("bundled", &bundle, &SourceMap::new(".schema.json"), &SchemasConfig::default(), &LintsConfig::default())
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(issues.len(), 4, "{issues:#?}");
+    assert_eq!(issues[0], "bundled.schema.json#/$defs/edge/edge: `edge` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]");
+    assert!(issues[1].starts_with("bundled.schema.json#/$defs/legacy/$schema: meta-schema `http://json-schema.org/draft-04/schema#` is not supported"), "{issues:#?}");
+    assert_eq!(issues[2], "bundled.schema.json#/$defs/port/portt: `portt` is not a keyword of json-schema.org/draft-07/schema [unknown_keyword]");
+    assert!(issues[3].starts_with("bundled.schema.json#/$defs/port/type: ") && issues[3].ends_with("[invalid_schema]"), "{issues:#?}");
+
+    let issues = validate_bundled_meta_schemas("bundled", &json!({ "$schema": DRAFT_07 }), &SourceMap::new(".schema.json"), &SchemasConfig::default(), &LintsConfig::default());
+    assert_eq!(issues.iter().map(ToString::to_string).collect::<Vec<_>>(), ["bundled.schema.json#: bundled schema has no `$defs` [invalid_schema]"]);
+}
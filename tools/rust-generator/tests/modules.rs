@@ -0,0 +1,109 @@
+use rust_generator::config::{DefinitionConfig, TypesConfig};
+use rust_generator::modules::split_by_source;
+use rust_generator::passes::default_passes;
+use rust_generator::{bundle_loaded_schemas, generate_rs_modules};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Names of the types, aliases and impls of [file] in order, impls as `impl Type`
+fn item_names(file: &syn::File) -> Vec<String> {
+    file.items.iter()
+        .map(|item| match item {
+            syn::Item::Struct(item) => item.ident.to_string(),
+            syn::Item::Enum(item) => item.ident.to_string(),
+            syn::Item::Type(item) => item.ident.to_string(),
+            syn::Item::Mod(item) => format!("mod {}", item.ident),
+            syn::Item::Impl(item) => match &*item.self_ty {
+                syn::Type::Path(path) => format!("impl {}", path.path.segments.last().unwrap().ident),
+                _ => "impl".to_string(),
+            },
+            _ => "other".to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn types_are_split_by_the_file_of_their_definition() {
+    let file = syn::parse_file(r#"
+        pub mod error {}
+        pub struct Agent { pub config: AgentSettings, pub ports: Vec<Port> }
+        pub struct AgentSettings { pub output_mapping: Option<AgentConfigOutputMapping>, pub pool: AgentPool }
+        impl From<&AgentSettings> for AgentSettings { fn from(value: &AgentSettings) -> Self { value.clone() } }
+        pub enum AgentConfigOutputMapping { Fields(AgentConfigOutputMappingFields), Expression(String) }
+        pub struct AgentConfigOutputMappingFields {}
+        pub struct AgentPool { pub size: i64 }
+        pub struct Port { pub id: String }
+        pub struct Unused {}
+        pub type AgentConfig = AgentSettings;
+    "#).unwrap();
+    let sources = BTreeMap::from([
+        ("agent".to_string(), "nodes/agent".to_string()),
+        ("agentConfig".to_string(), "nodes/agent".to_string()),
+        ("agentPool".to_string(), "pool".to_string()),
+        ("port".to_string(), "common".to_string()),
+    ]);
+    let types_config = TypesConfig {
+        definitions: BTreeMap::from([
+            ("agentConfig".to_string(), DefinitionConfig { rename: Some("AgentSettings".to_string()), ..DefinitionConfig::default() }),
+        ]),
+        ..TypesConfig::default()
+    };
+
+    let (root, modules) = split_by_source(file, &sources, &types_config);
+    assert_eq!(item_names(&root), ["mod error", "Unused"]);
+    assert_eq!(modules.keys().collect::<Vec<_>>(), ["common", "nodes/agent", "pool"]);
+    // nested types go with the renamed definition type that uses them, the alias with its definition
+    assert_eq!(item_names(&modules["nodes/agent"]), [
+        "Agent",
+        "AgentSettings",
+        "impl AgentSettings",
+        "AgentConfigOutputMapping",
+        "AgentConfigOutputMappingFields",
+        "AgentConfig",
+    ]);
+    // a definition named like a nested type of another file stays in its own file
+    assert_eq!(item_names(&modules["pool"]), ["AgentPool"]);
+    assert_eq!(item_names(&modules["common"]), ["Port"]);
+}
+
+#[test]
+fn modules_are_generated_per_schema_file_and_re_exported() {
+    let schemas = BTreeMap::from([
+        ("common".to_string(), json!({
+            "$schema": "http://json-schema.org/draft-07/schema",
+            "$defs": { "port": { "type": "object", "properties": { "id": { "type": "string" } } } }
+        })),
+        ("while".to_string(), json!({
+            "$schema": "http://json-schema.org/draft-07/schema",
+            "type": "object",
+            "properties": {
+                "ports": { "type": "array", "items": { "$ref": "./common.schema.json#/$defs/port" } },
+                "mode": { "enum": ["all", "any"] }
+            }
+        })),
+    ]);
+    let bundle = bundle_loaded_schemas(&schemas, ".schema.json");
+    let types_config = TypesConfig::default();
+    let passes = default_passes(&bundle.schema, &types_config).unwrap();
+    let files = generate_rs_modules(&bundle, &types_config, &passes).unwrap().files;
+
+    let paths = files.iter().map(|file| file.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths, [
+        PathBuf::from("src/types/mod.rs"),
+        PathBuf::from("src/types/common.rs"),
+        PathBuf::from("src/types/while.rs"),
+    ]);
+    let mod_rs = &files[0].content;
+    // `while` is a keyword, its module is raw
+    assert!(mod_rs.starts_with("pub mod common;\npub use common::*;\npub mod r#while;\npub use r#while::*;\n"), "{mod_rs}");
+    assert!(mod_rs.contains("pub mod error {"), "{mod_rs}");
+
+    let common = &files[1].content;
+    assert!(common.starts_with("#[allow(unused_imports)]\nuse super::*;\n"), "{common}");
+    assert!(common.contains("pub struct Port {"), "{common}");
+    let while_rs = &files[2].content;
+    assert!(while_rs.contains("pub struct While {"), "{while_rs}");
+    assert!(while_rs.contains("pub enum WhileMode {"), "{while_rs}");
+    assert!(!while_rs.contains("pub struct Port {"), "{while_rs}");
+}
@@ -0,0 +1,300 @@
+use rust_generator::config::TypesConfig;
+use rust_generator::docs::SchemaDocs;
+use rust_generator::passes::{AstPass, DiscriminatedUnions, InjectAttributes, SimplifyPaths, SortItems, StripDocComments};
+use serde_json::{json, Value};
+
+/// Applies [pass] to [source], returns the printed file
+fn apply(pass: &impl AstPass, source: &str) -> String {
+    let mut file = syn::parse_file(source).unwrap();
+    pass.apply(&mut file).unwrap();
+    prettyplease::unparse(&file)
+}
+
+fn node_schema(node_types: &[&str]) -> Value {
+    let mut defs = serde_json::Map::new();
+    let branches = node_types.iter().map(|node_type| json!({ "$ref": format!("#/$defs/{node_type}") })).collect::<Vec<_>>();
+    defs.insert("node".to_string(), json!({ "oneOf": branches }));
+    for node_type in node_types {
+        defs.insert(node_type.to_string(), json!({
+            "type": "object",
+            "required": ["id", "type"],
+            "properties": { "id": { "type": "string" }, "type": { "const": node_type } }
+        }));
+    }
+    json!({ "$defs": defs })
+}
+
+const NODE_SOURCE: &str = r#"
+    #[derive(::serde::Deserialize, ::serde::Serialize, Clone, Debug)]
+    #[serde(untagged)]
+    pub enum Node {
+        Start(Start),
+        IfElse(IfElse),
+    }
+"#;
+
+#[test]
+fn unions_with_a_discriminator_deserialize_by_its_value() {
+    let pass = DiscriminatedUnions::new(&node_schema(&["start", "if_else"]), &TypesConfig::default());
+    let code = apply(&pass, NODE_SOURCE);
+
+    // serialization stays untagged, each variant writes its own discriminator
+    assert!(code.contains("#[derive(::serde::Serialize, Clone, Debug)]\n#[serde(untagged)]\npub enum Node"), "{code}");
+    assert!(code.contains("impl<'de> ::serde::Deserialize<'de> for Node"), "{code}");
+    assert!(code.contains(r#"match value.get("type").and_then(::serde_json::Value::as_str)"#), "{code}");
+    assert!(code.contains(r#"Some("start") => {
+                ::serde_json::from_value(value)
+                    .map(Self::Start)"#), "{code}");
+    assert!(code.contains(r#"Some("if_else") => {
+                ::serde_json::from_value(value)
+                    .map(Self::IfElse)"#), "{code}");
+    assert!(code.contains(r#""unknown type `{other}`, expected one of `if_else`, `start`""#), "{code}");
+    assert!(code.contains(r#"None => Err(::serde::de::Error::missing_field("type"))"#), "{code}");
+}
+
+#[test]
+fn unions_without_a_shared_required_const_stay_untagged() {
+    let mut optional = node_schema(&["start", "if_else"]);
+    optional["$defs"]["if_else"]["required"] = json!(["id"]);
+    let mut duplicate = node_schema(&["start", "if_else"]);
+    duplicate["$defs"]["if_else"]["properties"]["type"]["const"] = json!("start");
+    let mut untyped = node_schema(&["start", "if_else"]);
+    untyped["$defs"]["node"]["oneOf"][1] = json!({ "type": "string" });
+
+    for schema in [optional, duplicate, untyped] {
+        let pass = DiscriminatedUnions::new(&schema, &TypesConfig::default());
+        let code = apply(&pass, NODE_SOURCE);
+        assert!(code.contains("#[derive(::serde::Deserialize, ::serde::Serialize, Clone, Debug)]"), "{code}");
+        assert!(!code.contains("impl<'de>"), "{code}");
+    }
+}
+
+#[test]
+fn variants_without_a_discriminator_value_are_errors() {
+    let pass = DiscriminatedUnions::new(&node_schema(&["start", "if_else"]), &TypesConfig::default());
+    let mut file = syn::parse_file(r#"
+        #[derive(::serde::Deserialize)]
+        #[serde(untagged)]
+        pub enum Node {
+            Start(Start),
+            Other(serde_json::Value),
+        }
+    "#).unwrap();
+    let err = pass.apply(&mut file).unwrap_err();
+    assert_eq!(err.to_string(), "variant `Node::Other` has no `type` value");
+}
+
+#[test]
+fn schema_docs_render_title_description_default_and_examples() {
+    let schema = json!({ "$defs": {
+        "port": {
+            "title": "Port",
+            "description": "Connection point of a node",
+            "examples": [{ "id": "in" }],
+            "type": "object",
+            "properties": {
+                "id": { "description": "Unique within the node", "default": "in", "type": "string" },
+                "type": { "title": "Kind of the port", "const": "port" }
+            }
+        },
+        "direction": {
+            "oneOf": [
+                { "const": "input", "description": "Receives values" },
+                { "$ref": "#/$defs/port", "title": "Nested port" }
+            ]
+        }
+    }});
+    let pass = SchemaDocs::new(&schema, &TypesConfig::default());
+    let code = apply(&pass, r#"
+        /// typify docs are replaced
+        pub struct Port {
+            pub id: String,
+            #[serde(rename = "type")]
+            pub type_: serde_json::Value,
+        }
+        pub enum Direction {
+            #[serde(rename = "input")]
+            Input,
+            Port(Port),
+        }
+    "#);
+
+    assert_eq!(code, r#"/// Port
+///
+/// Connection point of a node
+///
+/// # Examples
+///
+/// ```json
+/// {
+///   "id": "in"
+/// }
+/// ```
+pub struct Port {
+    /// Unique within the node
+    ///
+    /// Default: `"in"`
+    pub id: String,
+    /// Kind of the port
+    #[serde(rename = "type")]
+    pub type_: serde_json::Value,
+}
+pub enum Direction {
+    /// Receives values
+    #[serde(rename = "input")]
+    Input,
+    /// Nested port
+    Port(Port),
+}
+"#);
+}
+
+#[test]
+fn simplify_paths_keep_names_of_generated_types_qualified() {
+    let code = apply(&SimplifyPaths, r#"
+        #[derive(::serde::Serialize, Clone)]
+        pub struct String {
+            pub value: ::std::string::String,
+        }
+        pub enum Result {
+            Passed,
+            Failed,
+        }
+        pub struct Pipeline {
+            /// Name such as `::std::string::String`
+            pub name: String,
+            pub labels: ::std::vec::Vec<::std::string::String>,
+            pub outcome: ::std::option::Option<::std::result::Result<Result, ::serde_json::Value>>,
+        }
+        impl ::std::convert::From<&Pipeline> for Pipeline {
+            fn from(value: &Pipeline) -> Self {
+                let _ = "::std::vec::Vec";
+                value.clone()
+            }
+        }
+    "#);
+    assert_eq!(code, r#"#[derive(serde::Serialize, Clone)]
+pub struct String {
+    pub value: std::string::String,
+}
+pub enum Result {
+    Passed,
+    Failed,
+}
+pub struct Pipeline {
+    /// Name such as `::std::string::String`
+    pub name: String,
+    pub labels: Vec<std::string::String>,
+    pub outcome: Option<std::result::Result<Result, serde_json::Value>>,
+}
+impl From<&Pipeline> for Pipeline {
+    fn from(value: &Pipeline) -> Self {
+        let _ = "::std::vec::Vec";
+        value.clone()
+    }
+}
+"#);
+}
+
+#[test]
+fn sort_items_orders_types_by_name_with_their_impls() {
+    let code = apply(&SortItems, r#"
+        pub mod error {}
+        impl From<&Port> for Port { fn from(value: &Port) -> Self { value.clone() } }
+        pub struct Port {}
+        pub enum Direction { Input }
+        impl Direction { pub fn is_input(&self) -> bool { true } }
+        pub struct Agent {}
+        impl Default for Port { fn default() -> Self { Port {} } }
+        pub mod builder {}
+    "#);
+    let items = syn::parse_file(&code).unwrap().items.iter()
+        .map(|item| match item {
+            syn::Item::Struct(item) => format!("struct {}", item.ident),
+            syn::Item::Enum(item) => format!("enum {}", item.ident),
+            syn::Item::Mod(item) => format!("mod {}", item.ident),
+            syn::Item::Impl(item) => match &item.trait_ {
+                Some((_, path, _)) => format!("impl {}", path.segments.last().unwrap().ident),
+                None => "impl".to_string(),
+            },
+            _ => "other".to_string(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(items, [
+        "mod error",
+        "struct Agent",
+        "enum Direction",
+        "impl",
+        "struct Port",
+        "impl From",
+        "impl Default",
+        "mod builder",
+    ]);
+}
+
+#[test]
+fn inject_attributes_add_to_all_types_or_to_the_named_one() {
+    let source = "pub struct Port {}\npub enum Direction { Input }\npub type Ports = Vec<Port>;\n";
+
+    let all = InjectAttributes::parse(None, &["#[serde(deny_unknown_fields)]".to_string()]).unwrap();
+    assert_eq!(apply(&all, source), "#[serde(deny_unknown_fields)]\npub struct Port {}\n#[serde(deny_unknown_fields)]\npub enum Direction {\n    Input,\n}\npub type Ports = Vec<Port>;\n");
+
+    let attributes = ["#[cfg_attr(feature = \"schemars\", derive(schemars::JsonSchema))] #[non_exhaustive]".to_string()];
+    let named = InjectAttributes::parse(Some("Direction".to_string()), &attributes).unwrap();
+    assert_eq!(apply(&named, source), "pub struct Port {}\n#[cfg_attr(feature = \"schemars\", derive(schemars::JsonSchema))]\n#[non_exhaustive]\npub enum Direction {\n    Input,\n}\npub type Ports = Vec<Port>;\n");
+
+    let missing = InjectAttributes::parse(Some("Edge".to_string()), &attributes).unwrap();
+    let err = missing.apply(&mut syn::parse_file(source).unwrap()).unwrap_err();
+    assert_eq!(err.to_string(), "there is no generated type `Edge` to add attributes to");
+
+    let err = InjectAttributes::parse(None, &["#[serde(".to_string()]).err().unwrap();
+    assert!(err.to_string().starts_with("invalid attribute `#[serde(`: "), "{err}");
+}
+
+#[test]
+fn strip_doc_comments_removes_docs_of_items_fields_and_variants() {
+    let code = apply(&StripDocComments, r#"
+        /// Port
+        #[derive(Clone)]
+        pub struct Port {
+            /// Id
+            #[serde(rename = "id")]
+            pub id: String,
+        }
+        /// Direction
+        pub enum Direction {
+            /// Input
+            Input,
+            /// Named
+            Named {
+                /// Name
+                name: String,
+            },
+        }
+        /// Ports
+        pub type Ports = Vec<Port>;
+        /// Impl
+        impl Port {}
+        /// Error types
+        pub mod error {
+            /// Kept, nested items are not generated from the schema
+            pub struct ConversionError;
+        }
+    "#);
+    assert_eq!(code, r#"#[derive(Clone)]
+pub struct Port {
+    #[serde(rename = "id")]
+    pub id: String,
+}
+pub enum Direction {
+    Input,
+    Named { name: String },
+}
+pub type Ports = Vec<Port>;
+impl Port {}
+pub mod error {
+    /// Kept, nested items are not generated from the schema
+    pub struct ConversionError;
+}
+"#);
+}
@@ -0,0 +1,43 @@
+use rust_generator::prune_unreachable;
+use serde_json::{json, Value};
+
+fn schema() -> Value {
+    json!({ "$defs": {
+        "pipeline": {
+            "type": "object",
+            "properties": { "graph": { "type": "array", "items": { "$ref": "#/$defs/node" } } }
+        },
+        "node": { "oneOf": [{ "$ref": "#/$defs/agent" }, { "allOf": [{ "$ref": "#/$defs/end" }] }] },
+        "agent": { "type": "object", "additionalProperties": { "$ref": "#/$defs/port" } },
+        "end": { "type": "object" },
+        "port": { "type": "string" },
+        "edge": { "type": "object", "properties": { "from": { "$ref": "#/$defs/port" } } },
+        "plugin": { "$ref": "#/$defs/edge" }
+    }})
+}
+
+fn def_keys(schema: &Value) -> Vec<&str> {
+    schema["$defs"].as_object().unwrap().keys().map(String::as_str).collect()
+}
+
+#[test]
+fn definitions_reachable_through_nested_refs_are_kept() {
+    let mut pipeline = schema();
+    let pruned = prune_unreachable(&mut pipeline, &["pipeline".to_string()]).unwrap();
+    assert_eq!(pruned, ["edge", "plugin"]);
+    assert_eq!(def_keys(&pipeline), ["pipeline", "node", "agent", "end", "port"]);
+
+    let mut several = schema();
+    let pruned = prune_unreachable(&mut several, &["end".to_string(), "plugin".to_string()]).unwrap();
+    assert_eq!(pruned, ["agent", "node", "pipeline"]);
+    assert_eq!(def_keys(&several), ["end", "port", "edge", "plugin"]);
+}
+
+#[test]
+fn unknown_roots_are_errors() {
+    let mut schema = schema();
+    let err = prune_unreachable(&mut schema, &["pipeline".to_string(), "graph".to_string()]).unwrap_err();
+    assert_eq!(err.to_string(), "root definition `graph` is not in the bundled schema");
+    // nothing is pruned
+    assert_eq!(def_keys(&schema).len(), 7);
+}
@@ -0,0 +1,247 @@
+use rust_generator::sources::{parse_source, Position, SourceFormat, SourceMap};
+use serde_json::{json, Value};
+
+/// Parses [text] as [format] and returns the value, panics with the positioned error otherwise
+fn accept(format: SourceFormat, text: &str) -> Value {
+    match parse_source(text, format) {
+        Ok((value, _)) => value,
+        Err(err) => panic!("{format:?} rejected {text:?}: {err}"),
+    }
+}
+
+/// Parses [text] as [format] and returns the positioned error, panics if it is accepted
+fn reject(format: SourceFormat, text: &str) -> String {
+    match parse_source(text, format) {
+        Ok((value, _)) => panic!("{format:?} accepted {text:?} as {value}"),
+        Err(err) => err.to_string(),
+    }
+}
+
+fn position(format: SourceFormat, text: &str, pointer: &str) -> Option<Position> {
+    let (_, positions) = parse_source(text, format).unwrap();
+    positions.get(pointer).copied()
+}
+
+#[test]
+fn yaml_accepts_the_json_compatible_subset() {
+    let cases: Vec<(&str, Value)> = vec![
+        ("", Value::Null),
+        ("# only a comment\n", Value::Null),
+        ("---\na: 1\n...\n", json!({"a": 1})),
+        ("a: 1\nb: two\n", json!({"a": 1, "b": "two"})),
+        ("a:\n  b:\n    c: true\n", json!({"a": {"b": {"c": true}}})),
+        ("- 1\n- 2\n", json!([1, 2])),
+        ("a:\n- x\n- y\n", json!({"a": ["x", "y"]})),
+        ("a:\n  - x\n  - y\n", json!({"a": ["x", "y"]})),
+        ("- a: 1\n  b: 2\n- c: 3\n", json!([{"a": 1, "b": 2}, {"c": 3}])),
+        ("- - 1\n  - 2\n", json!([[1, 2]])),
+        ("a:\nb: ~\nc: null\n", json!({"a": null, "b": null, "c": null})),
+        ("a: b # comment\n", json!({"a": "b"})),
+        ("a: b#not a comment\n", json!({"a": "b#not a comment"})),
+        ("url: http://example.com/a\n", json!({"url": "http://example.com/a"})),
+        ("$ref: '#/$defs/node'\n", json!({"$ref": "#/$defs/node"})),
+        ("\"quoted key\": 1\n'single': 2\n", json!({"quoted key": 1, "single": 2})),
+        // core schema scalars
+        ("[true, false, True, FALSE]\n", json!([true, false, true, false])),
+        ("[yes, no, on, off, y, n]\n", json!(["yes", "no", "on", "off", "y", "n"])),
+        ("[0, -1, +2, 007, 0o17, 0x1f]\n", json!([0, -1, 2, 7, 15, 31])),
+        ("[1.5, -0.5, .5, 1., 1e3, 2.5E-1]\n", json!([1.5, -0.5, 0.5, 1.0, 1000.0, 0.25])),
+        ("[1.0, 10]\n", json!([1.0, 10])),
+        ("[1_000, 0b101, 1.2.3, 12abc]\n", json!(["1_000", "0b101", "1.2.3", "12abc"])),
+        ("a: '1'\nb: \"true\"\n", json!({"a": "1", "b": "true"})),
+        // quoted scalars
+        ("a: 'it''s'\n", json!({"a": "it's"})),
+        ("a: \"tab\\tnew\\nline \\\"q\\\" \\u00e9 \\x41 \\\\\"\n", json!({"a": "tab\tnew\nline \"q\" é A \\"})),
+        ("a: 'one\n  two\n\n  three'\n", json!({"a": "one two\nthree"})),
+        ("a: \"one \\\n  two\"\n", json!({"a": "one two"})),
+        // plain multi-line scalars fold into one line
+        ("a: one\n  two\n  three\n", json!({"a": "one two three"})),
+        ("- one\n  two\n", json!(["one two"])),
+        // block scalars with chomping and indentation indicators
+        ("a: |\n  line 1\n  line 2\nb: 1\n", json!({"a": "line 1\nline 2\n", "b": 1})),
+        ("a: |-\n  text\n\n", json!({"a": "text"})),
+        ("a: |+\n  text\n\n", json!({"a": "text\n\n"})),
+        ("a: >\n  folded\n  text\n\n  next\n", json!({"a": "folded text\nnext\n"})),
+        ("a: >-\n  one\n    more\n  two\n", json!({"a": "one\n  more\ntwo"})),
+        ("a: |2\n    indented\n", json!({"a": "  indented\n"})),
+        ("a: |\n  # not a comment\n", json!({"a": "# not a comment\n"})),
+        ("- |\n  item\n- 2\n", json!(["item\n", 2])),
+        // flow collections
+        ("{a: 1, b: [x, y], c: {}}\n", json!({"a": 1, "b": ["x", "y"], "c": {}})),
+        ("a: [1, 2, ]\n", json!({"a": [1, 2]})),
+        ("a: {x: 1,}\n", json!({"a": {"x": 1}})),
+        ("a: [\n  1,\n  2\n]\n", json!({"a": [1, 2]})),
+        ("a: ['x, y', \"z]\"]\n", json!({"a": ["x, y", "z]"]})),
+        ("a: [http://x.io/a, b:c]\n", json!({"a": ["http://x.io/a", "b:c"]})),
+        ("a: {\"k\": v, 'm': [null, ~]}\n", json!({"a": {"k": "v", "m": [null, null]}})),
+        ("required: [kind, agent_id]\n", json!({"required": ["kind", "agent_id"]})),
+        ("enum: []\n", json!({"enum": []})),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(accept(SourceFormat::Yaml, text), expected, "{text:?}");
+    }
+}
+
+#[test]
+fn yaml_rejects_what_json_can_not_represent_with_positions() {
+    let cases = [
+        ("a: &anchor 1\nb: *anchor\n", "1:4: anchors are not supported"),
+        ("a: *anchor\n", "1:4: aliases are not supported"),
+        ("a: !!str 1\n", "1:4: tags are not supported"),
+        ("? complex\n: key\n", "1:1: complex mapping keys are not supported"),
+        ("%YAML 1.2\n---\na: 1\n", "1:1: YAML directives are not supported"),
+        ("a: 1\n---\nb: 2\n", "2:1: files with several YAML documents are not supported"),
+        ("a:\n\tb: 1\n", "2:1: tabs can not indent YAML"),
+        ("a: 1\na: 2\n", "2:1: duplicate key `a`"),
+        ("a: .inf\n", "1:4: `.inf` can not be represented in JSON, quote it for a string"),
+        ("a: -.Inf\n", "1:4: `-.Inf` can not be represented in JSON, quote it for a string"),
+        ("a: .nan\n", "1:4: `.nan` can not be represented in JSON, quote it for a string"),
+        ("a: b: c\n", "1:5: mapping values are not allowed here, quote the value if `: ` is part of it"),
+        ("a: 1\n  b: 2\n", "2:3: unexpected indentation"),
+        ("a:\n  b: 1\n c: 2\n", "3:2: unexpected indentation"),
+        ("- 1\na: 2\n", "2:1: unexpected content, check the indentation"),
+        ("a: [1, 2\n", "1:4: unterminated flow sequence"),
+        ("a: {x: 1\n", "1:4: unterminated flow mapping"),
+        ("a: [[1] 2]\n", "1:9: expected `,` or `]`"),
+        ("a: 'open\n", "1:4: unterminated string"),
+        ("a: \"open\n", "1:4: unterminated string"),
+        ("a: \"\\q\"\n", "1:6: invalid escape `\\q`"),
+        ("a: [1, 2] trailing\n", "1:11: unexpected `trailing` after the value"),
+        ("--- a: 1\n", "1:5: content after `---` is not supported, start it on the next line"),
+        ("a: |x\n  text\n", "1:4: invalid block scalar header `|x`"),
+        ("a: {[1]: 2}\n", "1:5: mapping keys must be scalars"),
+        ("a: {x: 1, x: 2}\n", "1:11: duplicate key `x`"),
+        ("a: \"\\xZZ\"\n", "1:6: invalid `\\x` escape"),
+        ("a: @value\n", "1:4: '@' is reserved, quote the value"),
+        ("a: `value`\n", "1:4: '`' is reserved, quote the value"),
+        ("a: [1,\n", "1:4: unterminated flow sequence"),
+        ("a:\n  &anchor\n    b: 1\n", "2:3: anchors are not supported"),
+        ("a: 1\nb: 2\n c: 3\n", "3:2: unexpected indentation"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(reject(SourceFormat::Yaml, text), expected, "{text:?}");
+    }
+}
+
+#[test]
+fn yaml_records_positions_at_keys_and_item_values() {
+    let text = "type: object\nproperties:\n  id:\n    type: string\nrequired:\n  - id\n  -\n    name\nenum: [a, {b: 1}]\n";
+    assert_eq!(position(SourceFormat::Yaml, text, ""), Some(Position { line: 1, column: 1 }));
+    assert_eq!(position(SourceFormat::Yaml, text, "/properties/id/type"), Some(Position { line: 4, column: 5 }));
+    assert_eq!(position(SourceFormat::Yaml, text, "/required/0"), Some(Position { line: 6, column: 5 }));
+    // an item starting on the next line is at its `-`
+    assert_eq!(position(SourceFormat::Yaml, text, "/required/1"), Some(Position { line: 7, column: 3 }));
+    assert_eq!(position(SourceFormat::Yaml, text, "/enum/1"), Some(Position { line: 9, column: 11 }));
+    assert_eq!(position(SourceFormat::Yaml, text, "/enum/1/b"), Some(Position { line: 9, column: 12 }));
+}
+
+#[test]
+fn json5_accepts_its_extensions() {
+    let cases: Vec<(&str, Value)> = vec![
+        ("{}", json!({})),
+        ("// comment\n{a: 1, /* inline */ b: 2,}", json!({"a": 1, "b": 2})),
+        ("{$ref: '#/$defs/x', _k: 1, 'quoted key': 2, \"d\": 3}", json!({"$ref": "#/$defs/x", "_k": 1, "quoted key": 2, "d": 3})),
+        ("[1, 2, 3,]", json!([1, 2, 3])),
+        ("['it\\'s', \"say \\\"hi\\\"\", 'mixed \"q\"']", json!(["it's", "say \"hi\"", "mixed \"q\""])),
+        ("'\\b\\f\\n\\r\\t\\v\\0\\x41\\u00e9\\/'", json!("\u{8}\u{c}\n\r\t\u{b}\0Aé/")),
+        ("'\\uD83D\\uDE00'", json!("😀")),
+        ("'line \\\ncontinued'", json!("line continued")),
+        ("[0x1F, -0xa, +1, .5, 5., 1e3, -0.0]", json!([31, -10, 1, 0.5, 5.0, 1000.0, -0.0])),
+        ("[1.0, 10]", json!([1.0, 10])),
+        ("[null, true, false]", json!([null, true, false])),
+        ("{a: {b: [{}]}}", json!({"a": {"b": [{}]}})),
+        ("{a: 1, a: 2}", json!({"a": 2})),
+        ("\u{feff}{a: 1}", json!({"a": 1})),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(accept(SourceFormat::Json5, text), expected, "{text:?}");
+    }
+}
+
+#[test]
+fn json5_rejects_invalid_input_with_positions() {
+    let cases = [
+        ("[Infinity]", "1:2: `Infinity` can not be represented in JSON"),
+        ("{a: -Infinity}", "1:5: `-Infinity` can not be represented in JSON"),
+        ("{a: NaN}", "1:5: `NaN` can not be represented in JSON"),
+        ("{a: undefined}", "1:5: unexpected `undefined`, strings need quotes"),
+        ("{a 1}", "1:4: expected `:`, found `1`"),
+        ("{a: 1 b: 2}", "1:7: expected `,` or `}`, found `b`"),
+        ("[1 2]", "1:4: expected `,` or `]`, found `2`"),
+        ("{\n  a: 1,\n", "3:1: unterminated object"),
+        ("[1,", "1:4: unexpected end of the file"),
+        ("'open", "1:1: unterminated string"),
+        ("'line\nbreak'", "1:6: line breaks in strings need a `\\` before them"),
+        ("/* open", "1:1: unterminated block comment"),
+        ("{a: 1} 2", "1:8: trailing characters after the value"),
+        ("[0x]", "1:2: invalid number `0x`"),
+        ("['\\1']", "1:5: invalid escape `\\1`"),
+        ("['\\xZZ']", "1:5: invalid hexadecimal escape"),
+        ("{1: 2}", "1:2: expected a property name, found `1`"),
+        ("{a\\x41: 1}", "1:5: expected a `\\u` escape in the property name"),
+        ("{a", "1:3: expected `:`, found the end of the file"),
+        ("{a:", "1:4: unexpected end of the file"),
+        ("[1, 2", "1:6: unterminated array"),
+        ("{a: 1,", "1:7: unterminated object"),
+        ("[#]", "1:2: unexpected `#`"),
+        ("", "1:1: unexpected end of the file"),
+        ("{a: '\\u00'}", "1:10: invalid hexadecimal escape"),
+        ("[+-1]", "1:2: invalid number `+-1`"),
+        ("[0x+1]", "1:2: invalid number `0x+1`"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(reject(SourceFormat::Json5, text), expected, "{text:?}");
+    }
+}
+
+#[test]
+fn json_keeps_serde_json_values_and_reports_its_errors_with_positions() {
+    let text = "{\n  \"a\": [1.0, 10],\n  \"b\": {\"c\": \"d\"}\n}";
+    assert_eq!(accept(SourceFormat::Json, text), json!({"a": [1.0, 10], "b": {"c": "d"}}));
+    assert_eq!(position(SourceFormat::Json, text, "/b/c"), Some(Position { line: 3, column: 9 }));
+    assert_eq!(position(SourceFormat::Json, text, "/a/1"), Some(Position { line: 2, column: 14 }));
+
+    assert_eq!(reject(SourceFormat::Json, "{\n  \"a\": 1,\n}"), "3:1: trailing comma");
+    assert!(reject(SourceFormat::Json, "{a: 1}").starts_with("1:2: "));
+    assert_eq!(reject(SourceFormat::Json, "[1, 2"), "1:5: EOF while parsing a list");
+}
+
+#[test]
+fn every_format_gives_the_same_value() {
+    let json = r#"{"$schema": "http://json-schema.org/draft-07/schema", "type": "object",
+        "properties": {"id": {"type": "string", "minLength": 1}, "score": {"type": "number", "default": 0.5}},
+        "required": ["id"], "additionalProperties": false}"#;
+    let json5 = "{$schema: 'http://json-schema.org/draft-07/schema', type: 'object',
+        properties: {id: {type: 'string', minLength: 1}, score: {type: 'number', default: .5,},},
+        required: ['id'], additionalProperties: false}";
+    let yaml = "$schema: http://json-schema.org/draft-07/schema\ntype: object\nproperties:\n  id:\n    type: string\n    minLength: 1\n  score: {type: number, default: 0.5}\nrequired: [id]\nadditionalProperties: false\n";
+    let expected = accept(SourceFormat::Json, json);
+    assert_eq!(accept(SourceFormat::Json5, json5), expected);
+    assert_eq!(accept(SourceFormat::Yaml, yaml), expected);
+}
+
+#[test]
+fn source_map_locates_pointers_in_the_original_file() {
+    let text = "type: object\nproperties:\n  id:\n    type: string\n";
+    let (_, positions) = parse_source(text, SourceFormat::Yaml).unwrap();
+    let mut source_map = SourceMap::new(".schema.json");
+    source_map.insert("nodes/agent", "nodes/agent.schema.yaml", positions);
+
+    assert_eq!(source_map.locate("nodes/agent", "/properties/id/type"), "nodes/agent.schema.yaml:4:5: #/properties/id/type");
+    // pointers into values added by lowering fall back to their closest parent
+    assert_eq!(source_map.locate("nodes/agent", "/properties/id/items/0"), "nodes/agent.schema.yaml:3:3: #/properties/id/items/0");
+    assert_eq!(source_map.locate("common", "/type"), "common.schema.json#/type");
+}
+
+#[test]
+fn suffixes_select_the_format() {
+    let suffixes = SourceFormat::suffixes(".schema.json");
+    assert_eq!(suffixes, vec![
+        (".schema.json".to_string(), SourceFormat::Json),
+        (".schema.json5".to_string(), SourceFormat::Json5),
+        (".schema.yaml".to_string(), SourceFormat::Yaml),
+    ]);
+    assert_eq!(SourceFormat::of("a/b.schema.yml"), SourceFormat::Yaml);
+    assert_eq!(SourceFormat::strip_suffix("common/v1.schema.json5", ".schema.json"), "common/v1");
+    assert_eq!(SourceFormat::strip_suffix("common/v1.json", ".schema.json"), "common/v1");
+}